@@ -0,0 +1,93 @@
+//! Renders the progress image from the README: two spheres resting on a
+//! floor, lit by a small sphere light.
+//!
+//!     cargo run --release --example progress_image
+
+extern crate nalgebra as na;
+extern crate raymundo;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use raymundo::light::SphereLight;
+use raymundo::shape::{Plane, Sphere};
+use raymundo::{
+    graphics, render, Color, GraphicsContext, PerspectiveCamera, RenderSettings, Scene, Surface,
+};
+
+fn main() {
+    // The floor is the z = 0 plane, with the spheres on a line at an angle to
+    // the image
+    let camera = PerspectiveCamera::look_at(
+        &na::Point3::new(0.0, -9.44, 4.41),
+        &na::Point3::new(0.0, 7.02, 0.0),
+        &na::Vector3::z(),
+        PI / 5.0,
+        1024,
+        1024,
+    );
+    let mut ctx = GraphicsContext::new(Box::new(camera));
+
+    let mut scene = Scene::new();
+    scene.add_light(
+        "light",
+        Box::new(SphereLight {
+            pose: na::Isometry3::translation(3.31, -2.45, 5.0),
+            radius: 0.25,
+            color: Color::new(1.0, 1.0, 1.0),
+            intensity: 100.0,
+        }),
+    );
+    scene.add_shape(
+        "floor",
+        Box::new(Plane {
+            pose: na::Isometry3::identity(),
+        }),
+    );
+
+    let offset = na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), PI * 3.5 / 4.0)
+        * na::Vector3::new(1.15, 0.0, 0.0);
+    scene.add_shape(
+        "sphere_one",
+        Box::new(Sphere {
+            pose: na::Isometry3::translation(offset.x, offset.y, 1.0),
+            radius: 1.0,
+        }),
+    );
+    scene.add_shape(
+        "sphere_two",
+        Box::new(Sphere {
+            pose: na::Isometry3::translation(-offset.x, -offset.y, 1.0),
+            radius: 1.0,
+        }),
+    );
+    scene.set_material(
+        "sphere_one",
+        Arc::new(Surface {
+            diffuse: Color::new(0.8, 0.25, 0.2),
+            specular: Color::new(0.5, 0.5, 0.5),
+            shininess: 64.0,
+            ..Default::default()
+        }),
+    );
+    scene.set_material(
+        "sphere_two",
+        Arc::new(Surface {
+            diffuse: Color::new(0.2, 0.4, 0.8),
+            specular: Color::new(0.2, 0.2, 0.2),
+            shininess: 16.0,
+            ..Default::default()
+        }),
+    );
+    scene.rebuild_bvh();
+
+    ctx.imgbuf = render(&scene, ctx.camera.as_ref(), &RenderSettings::default());
+
+    for name in ["sphere_one", "sphere_two"].iter() {
+        graphics::draw_axes(scene.get_shape(name).unwrap().origin(), 0.5, &mut ctx);
+    }
+    graphics::draw_axes(scene.get_light("light").unwrap().origin(), 0.5, &mut ctx);
+
+    ctx.save("progress.png").expect("Failed to save image");
+    println!("Saved progress.png");
+}
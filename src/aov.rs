@@ -0,0 +1,258 @@
+extern crate image;
+extern crate nalgebra as na;
+
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::camera::Camera;
+use crate::hdr::{self, Channel, ExrPrecision};
+use crate::material::Color;
+use crate::scene::Scene;
+
+/// Arbitrary output variables, per-pixel properties of the first surface
+/// seen through each pixel, e.g. as ground truth for perception.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// Distance along the camera's optical axis.
+    Depth,
+    /// World space surface normal.
+    Normal,
+    /// World space position.
+    Position,
+    /// Diffuse reflectance of the material.
+    Albedo,
+    /// ID of the shape, from `Scene::shape_ids`.
+    ObjectId,
+}
+
+impl Aov {
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    /// Extension of the file the AOV is saved as, 16-bit PNG for depth and
+    /// IDs, EXR for float data.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Aov::Depth | Aov::ObjectId => "png",
+            Aov::Normal | Aov::Position | Aov::Albedo => "exr",
+        }
+    }
+}
+
+pub type Gray16Image = image::ImageBuffer<image::Luma<u16>, Vec<u16>>;
+
+/// Every AOV of a frame, each with one value per pixel in row-major order
+/// from the top left. Pixels where nothing is hit have infinite depth, zero
+/// normal, position and albedo, and object ID zero.
+#[derive(Clone, Debug)]
+pub struct AovBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub depth: Vec<f32>,
+    pub normal: Vec<na::Vector3<f32>>,
+    pub position: Vec<na::Point3<f32>>,
+    pub albedo: Vec<Color>,
+    pub object_id: Vec<u32>,
+}
+
+/// Casts a ray through the centre of every pixel of `camera`, and through
+/// the centre of its lens, recording the AOVs of what it hits. Values are
+/// not filtered, as that would blend IDs and the edges of objects.
+pub fn render_aovs(scene: &Scene, camera: &dyn Camera) -> AovBuffers {
+    let (width, height) = camera.resolution();
+    info!("Rendering {}x{} AOVs", width, height);
+    let count = (width * height) as usize;
+    let mut buffers = AovBuffers {
+        width,
+        height,
+        depth: vec![f32::INFINITY; count],
+        normal: vec![na::Vector3::zeros(); count],
+        position: vec![na::Point3::origin(); count],
+        albedo: vec![Color::zeros(); count],
+        object_id: vec![0; count],
+    };
+    let ids = scene.shape_ids();
+    let world_to_camera = camera.pose().inverse();
+    let lens = na::Point2::new(0.5, 0.5);
+    for y in 0..height {
+        for x in 0..width {
+            let film = na::Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let ray = camera.generate_ray(&film, &lens);
+            let hit = match scene.ray_cast(&ray) {
+                Some(hit) => hit,
+                None => continue,
+            };
+            let idx = (y * width + x) as usize;
+            buffers.depth[idx] = (world_to_camera * hit.near).z;
+            buffers.normal[idx] = hit.normal.normalize();
+            buffers.position[idx] = hit.near;
+            buffers.albedo[idx] = scene.material_of(&hit).diffuse(&hit);
+            buffers.object_id[idx] = hit.shape.as_ref().map_or(0, |name| ids[name.as_ref()]);
+        }
+    }
+    buffers
+}
+
+impl AovBuffers {
+    /// Depth in units of 1 / `scale`, e.g. millimetres for a scale of 1000
+    /// with scenes in metres. Zero marks pixels without a hit or too far
+    /// away to represent.
+    pub fn depth_image(&self, scale: f32) -> Gray16Image {
+        Gray16Image::from_fn(self.width, self.height, |x, y| {
+            let value = (self.depth[(y * self.width + x) as usize] * scale).round();
+            image::Luma([if value > 0.0 && value <= f32::from(u16::MAX) {
+                value as u16
+            } else {
+                0
+            }])
+        })
+    }
+
+    /// Object IDs, which must fit in 16 bits. Larger IDs, of scenes with
+    /// more shapes than that, are clipped to the largest 16-bit value.
+    pub fn object_id_image(&self) -> Gray16Image {
+        let clipped = self
+            .object_id
+            .iter()
+            .filter(|&&id| id > u32::from(u16::MAX))
+            .count();
+        if clipped > 0 {
+            warn!(
+                "{} pixels have object IDs above {}, clipped in the 16-bit image",
+                clipped,
+                u16::MAX
+            );
+        }
+        Gray16Image::from_fn(self.width, self.height, |x, y| {
+            let id = self.object_id[(y * self.width + x) as usize];
+            image::Luma([id.min(u32::from(u16::MAX)) as u16])
+        })
+    }
+
+    /// The float AOVs as R, G and B channels, None for depth and IDs.
+    pub fn channels(&self, aov: Aov) -> Option<Vec<Channel>> {
+        let vectors: Vec<na::Vector3<f32>> = match aov {
+            Aov::Normal => self.normal.clone(),
+            Aov::Position => self.position.iter().map(|p| p.coords).collect(),
+            Aov::Albedo => self.albedo.clone(),
+            Aov::Depth | Aov::ObjectId => return None,
+        };
+        Some(
+            ["R", "G", "B"]
+                .iter()
+                .enumerate()
+                .map(|(c, name)| Channel::new(name, vectors.iter().map(|v| v[c]).collect()))
+                .collect(),
+        )
+    }
+
+    /// Writes `aov` to `path` in the format given by `Aov::extension`, with
+    /// depth scaled by `depth_scale` as in `depth_image`.
+    pub fn save(&self, aov: Aov, path: &Path, depth_scale: f32) -> image::ImageResult<()> {
+        match aov {
+            Aov::Depth => self.depth_image(depth_scale).save(path),
+            Aov::ObjectId => self.object_id_image().save(path),
+            Aov::Normal | Aov::Position | Aov::Albedo => {
+                let channels = self.channels(aov).unwrap_or_default();
+                let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+                hdr::write_exr(
+                    &mut writer,
+                    self.width,
+                    self.height,
+                    &channels,
+                    ExrPrecision::Float,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use approx::relative_eq;
+
+    use crate::camera::PerspectiveCamera;
+    use crate::material::Surface;
+    use crate::test_util::{floor_and_balls, top_down_camera};
+
+    /// A red sphere in front of a floor, seen from straight above.
+    fn make_scene() -> (Scene, PerspectiveCamera) {
+        let mut scene = floor_and_balls(&[("ball", [0.0, 0.0, 1.0])]);
+        scene.set_material(
+            "ball",
+            Arc::new(Surface {
+                diffuse: Color::new(0.8, 0.1, 0.1),
+                ..Default::default()
+            }),
+        );
+        (scene, top_down_camera(5.0, 41, 31))
+    }
+
+    #[test]
+    fn aovs_of_the_centre_pixel() {
+        let (scene, camera) = make_scene();
+        let buffers = render_aovs(&scene, &camera);
+        let centre = (15 * 41 + 20) as usize;
+        assert!(relative_eq!(buffers.depth[centre], 3.0, epsilon = 1.0e-4));
+        assert!(relative_eq!(
+            buffers.normal[centre],
+            na::Vector3::z(),
+            epsilon = 1.0e-4
+        ));
+        assert!(relative_eq!(
+            buffers.position[centre],
+            na::Point3::new(0.0, 0.0, 2.0),
+            epsilon = 1.0e-4
+        ));
+        assert_eq!(buffers.albedo[centre], Color::new(0.8, 0.1, 0.1));
+        // IDs are numbered by name, the ball before the floor
+        assert_eq!(buffers.object_id[centre], 1);
+        assert_eq!(buffers.object_id[0], 2);
+        // Depth is along the optical axis, not the distance to the eye
+        assert!(relative_eq!(buffers.depth[0], 5.0, epsilon = 1.0e-4));
+    }
+
+    #[test]
+    fn misses_have_no_depth() {
+        let (mut scene, camera) = make_scene();
+        scene.remove_shape("floor");
+        let buffers = render_aovs(&scene, &camera);
+        assert_eq!(buffers.depth[0], f32::INFINITY);
+        assert_eq!(buffers.object_id[0], 0);
+
+        let depth = buffers.depth_image(1000.0);
+        assert_eq!(depth.get_pixel(0, 0).0[0], 0);
+        assert_eq!(depth.get_pixel(20, 15).0[0], 3000);
+        // Beyond 65.535 m at millimetre scale
+        assert_eq!(buffers.depth_image(100_000.0).get_pixel(20, 15).0[0], 0);
+        assert_eq!(buffers.object_id_image().get_pixel(20, 15).0[0], 1);
+    }
+
+    #[test]
+    fn float_aovs_as_channels() {
+        let (scene, camera) = make_scene();
+        let buffers = render_aovs(&scene, &camera);
+        assert!(buffers.channels(Aov::Depth).is_none());
+        let channels = buffers.channels(Aov::Position).unwrap();
+        let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["R", "G", "B"]);
+        assert_eq!(channels[2].values.len(), 41 * 31);
+        assert!(relative_eq!(
+            channels[2].values[15 * 41 + 20],
+            2.0,
+            epsilon = 1.0e-4
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+extern crate nalgebra as na;
+
+use crate::geometry::{Aabb, Ray};
+
+/// Number of buckets the centroid range is binned into when evaluating the
+/// surface area heuristic.
+const BUCKET_COUNT: usize = 12;
+/// Nodes with at most this many primitives may become leaves.
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting a node relative to intersecting a primitive.
+const TRAVERSAL_COST: f32 = 0.125;
+
+#[derive(Clone, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    /// Leaf: index of the first primitive. Interior: index of the second child,
+    /// the first child always directly follows its parent.
+    offset: usize,
+    /// Number of primitives in a leaf, zero for interior nodes.
+    count: usize,
+    /// Split axis of an interior node.
+    axis: usize,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: na::Point3<f32>,
+}
+
+/// Bounding volume hierarchy over a set of primitives, built with the surface
+/// area heuristic and stored as a flattened depth-first array of nodes.
+///
+/// Primitives are referred to by their index in the slice of bounds the
+/// hierarchy was built from. Primitives with empty or unbounded bounds are
+/// left out.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut items: Vec<BuildItem> = bounds
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.is_finite())
+            .map(|(index, b)| BuildItem {
+                index,
+                bounds: *b,
+                centroid: b.centroid(),
+            })
+            .collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * items.len()),
+            primitives: Vec::with_capacity(items.len()),
+        };
+        if !items.is_empty() {
+            bvh.build_node(&mut items);
+        }
+        bvh
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    fn push_leaf(&mut self, node_idx: usize, items: &[BuildItem]) {
+        self.nodes[node_idx].offset = self.primitives.len();
+        self.nodes[node_idx].count = items.len();
+        self.primitives.extend(items.iter().map(|item| item.index));
+    }
+
+    fn build_node(&mut self, items: &mut [BuildItem]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(Aabb::empty(), |b, item| b.union(&item.bounds));
+        let node_idx = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        if items.len() == 1 {
+            self.push_leaf(node_idx, items);
+            return node_idx;
+        }
+
+        let centroid_bounds = items
+            .iter()
+            .fold(Aabb::empty(), |b, item| b.grow(&item.centroid));
+        let axis = centroid_bounds.max_extent_axis();
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = centroid_bounds.max[axis] - axis_min;
+
+        // All centroids coincide, there is nothing to split on
+        if axis_extent <= 0.0 {
+            self.push_leaf(node_idx, items);
+            return node_idx;
+        }
+
+        let bucket_of = |item: &BuildItem| {
+            let relative = (item.centroid[axis] - axis_min) / axis_extent;
+            ((relative * BUCKET_COUNT as f32) as usize).min(BUCKET_COUNT - 1)
+        };
+
+        let mut counts = [0_usize; BUCKET_COUNT];
+        let mut bucket_bounds = [Aabb::empty(); BUCKET_COUNT];
+        for item in items.iter() {
+            let b = bucket_of(item);
+            counts[b] += 1;
+            bucket_bounds[b] = bucket_bounds[b].union(&item.bounds);
+        }
+
+        // Cost of splitting after each bucket
+        let mut best_split = 0;
+        let mut best_cost = f32::INFINITY;
+        for split in 0..BUCKET_COUNT - 1 {
+            let (mut left, mut right) = (Aabb::empty(), Aabb::empty());
+            let (mut left_count, mut right_count) = (0, 0);
+            for b in 0..=split {
+                left = left.union(&bucket_bounds[b]);
+                left_count += counts[b];
+            }
+            for b in split + 1..BUCKET_COUNT {
+                right = right.union(&bucket_bounds[b]);
+                right_count += counts[b];
+            }
+            let cost = TRAVERSAL_COST
+                + (left_count as f32 * left.surface_area()
+                    + right_count as f32 * right.surface_area())
+                    / bounds.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        let leaf_cost = items.len() as f32;
+        if items.len() <= MAX_LEAF_SIZE && leaf_cost <= best_cost {
+            self.push_leaf(node_idx, items);
+            return node_idx;
+        }
+
+        // Partition in place around the chosen bucket boundary
+        let mut mid = 0;
+        for idx in 0..items.len() {
+            if bucket_of(&items[idx]) <= best_split {
+                items.swap(idx, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == items.len() {
+            mid = items.len() / 2;
+            items.select_nth_unstable_by(mid, |lhs, rhs| {
+                lhs.centroid[axis].partial_cmp(&rhs.centroid[axis]).unwrap()
+            });
+        }
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build_node(left);
+        let second = self.build_node(right);
+        let node = &mut self.nodes[node_idx];
+        node.offset = second;
+        node.axis = axis;
+        node_idx
+    }
+
+    /// Finds the closest primitive hit along `ray`.
+    ///
+    /// `intersect` is called for every primitive whose bounds may contain a
+    /// hit closer than the best so far, and returns the ray parameter of its
+    /// hit along with whatever describes it.
+    pub fn closest_hit<T, F>(&self, ray: &Ray, mut intersect: F) -> Option<(f32, T)>
+    where
+        F: FnMut(usize) -> Option<(f32, T)>,
+    {
+        let mut closest: Option<(f32, T)> = None;
+        let mut t_max = f32::INFINITY;
+        self.traverse(ray, t_max, |primitive| {
+            if let Some((t, hit)) = intersect(primitive) {
+                if t < t_max {
+                    t_max = t;
+                    closest = Some((t, hit));
+                }
+            }
+            (t_max, false)
+        });
+        closest
+    }
+
+    /// Returns true as soon as `intersect` reports a hit for any primitive
+    /// whose bounds overlap `ray` before `t_max`.
+    pub fn any_hit<F>(&self, ray: &Ray, t_max: f32, mut intersect: F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut found = false;
+        self.traverse(ray, t_max, |primitive| {
+            found = intersect(primitive);
+            (t_max, found)
+        });
+        found
+    }
+
+    /// Visits primitives front to back. `visit` returns the current maximum
+    /// ray parameter of interest and whether to stop traversal.
+    fn traverse<F>(&self, ray: &Ray, mut t_max: f32, mut visit: F)
+    where
+        F: FnMut(usize) -> (f32, bool),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(node_idx) = stack.pop() {
+            let node = &self.nodes[node_idx];
+            match node.bounds.ray_intersect(ray) {
+                Some((t_near, _)) if t_near <= t_max => {}
+                _ => continue,
+            }
+
+            if node.is_leaf() {
+                for &primitive in &self.primitives[node.offset..node.offset + node.count] {
+                    let (new_t_max, stop) = visit(primitive);
+                    if stop {
+                        return;
+                    }
+                    t_max = new_t_max;
+                }
+            } else if ray.direction[node.axis] < 0.0 {
+                // Visit the child nearer to the ray origin first
+                stack.push(node_idx + 1);
+                stack.push(node.offset);
+            } else {
+                stack.push(node.offset);
+                stack.push(node_idx + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::shape::{Shape, Sphere};
+
+    // Deterministic spheres scattered in a 20 unit cube
+    fn make_spheres(count: usize) -> Vec<Sphere> {
+        let mut state: u32 = 12345;
+        let mut next = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..count)
+            .map(|_| Sphere {
+                pose: na::Isometry3::translation(
+                    next() * 20.0 - 10.0,
+                    next() * 20.0 - 10.0,
+                    next() * 20.0 - 30.0,
+                ),
+                radius: 0.2 + next(),
+            })
+            .collect()
+    }
+
+    fn hit_distance(ray: &Ray, sphere: &Sphere) -> Option<f32> {
+        sphere
+            .ray_cast(ray)
+            .map(|hit| (hit.near - ray.origin).norm())
+    }
+
+    fn camera_rays() -> Vec<Ray> {
+        let mut rays = Vec::new();
+        for x in -20..=20 {
+            for y in -20..=20 {
+                rays.push(Ray {
+                    origin: na::Point3::origin(),
+                    direction: na::Vector3::new(x as f32 * 0.02, y as f32 * 0.02, -1.0).normalize(),
+                });
+            }
+        }
+        rays
+    }
+
+    #[test]
+    fn closest_hit_matches_brute_force() {
+        let spheres = make_spheres(200);
+        let bounds: Vec<Aabb> = spheres.iter().map(|s| s.bounds()).collect();
+        let bvh = Bvh::build(&bounds);
+        assert!(bvh.node_count() > 1);
+
+        for ray in camera_rays() {
+            let expected = spheres
+                .iter()
+                .filter_map(|s| hit_distance(&ray, s))
+                .fold(None, |best: Option<f32>, d| {
+                    Some(best.map_or(d, |b| b.min(d)))
+                });
+            let actual = bvh
+                .closest_hit(&ray, |idx| {
+                    hit_distance(&ray, &spheres[idx]).map(|d| (d, idx))
+                })
+                .map(|(d, _)| d);
+            assert_eq!(expected, actual, "Mismatched hit for ray {}", ray);
+        }
+    }
+
+    #[test]
+    fn any_hit_matches_brute_force() {
+        let spheres = make_spheres(50);
+        let bounds: Vec<Aabb> = spheres.iter().map(|s| s.bounds()).collect();
+        let bvh = Bvh::build(&bounds);
+
+        for ray in camera_rays() {
+            let expected = spheres.iter().any(|s| s.ray_cast(&ray).is_some());
+            let actual = bvh.any_hit(&ray, f32::INFINITY, |idx| {
+                spheres[idx].ray_cast(&ray).is_some()
+            });
+            assert_eq!(expected, actual, "Mismatched occlusion for ray {}", ray);
+        }
+    }
+
+    #[test]
+    fn coincident_centroids() {
+        let bounds = vec![
+            Aabb::new(
+                na::Point3::new(-1.0, -1.0, -1.0),
+                na::Point3::new(1.0, 1.0, 1.0)
+            );
+            10
+        ];
+        let bvh = Bvh::build(&bounds);
+        assert_eq!(bvh.node_count(), 1);
+        assert_eq!(bvh.bounds(), Some(bounds[0]));
+    }
+
+    #[test]
+    fn empty() {
+        let bvh = Bvh::build(&[Aabb::empty()]);
+        assert!(bvh.is_empty());
+        let ray = Ray {
+            origin: na::Point3::origin(),
+            direction: na::Vector3::z(),
+        };
+        assert!(bvh.closest_hit(&ray, |idx| Some((0.0, idx))).is_none());
+    }
+}
@@ -0,0 +1,664 @@
+extern crate nalgebra as na;
+
+use crate::geometry::Ray;
+use crate::sampling;
+
+/// Maps between positions on the image and rays through the scene.
+///
+/// Image positions are in pixels, measured from the top left corner of the
+/// image with y pointing down, so pixel (x, y) covers the square from
+/// (x, y) to (x + 1, y + 1).
+pub trait Camera: Send + Sync {
+    /// Camera to world transform. Cameras look along their local +Z axis,
+    /// with +X pointing right and +Y pointing down the image.
+    fn pose(&self) -> &na::Isometry3<f32>;
+
+    /// Width and height of the image in pixels.
+    fn resolution(&self) -> (u32, u32);
+
+    /// World space ray through the image position `film`, with a normalized
+    /// direction. `lens` is a uniform sample in [0, 1)^2 picking the point on
+    /// the lens the ray leaves from, (0.5, 0.5) being its centre. Cameras
+    /// without a lens ignore it.
+    fn generate_ray(&self, film: &na::Point2<f32>, lens: &na::Point2<f32>) -> Ray;
+
+    /// Image position the world space `point` is seen at, or `None` if it is
+    /// behind the camera. Points out of focus are projected through the
+    /// centre of the lens.
+    fn project_point(&self, point: &na::Point3<f32>) -> Option<na::Point2<f32>>;
+}
+
+/// Camera to world transform of a camera at `eye` looking towards `target`,
+/// with `up` pointing up the image.
+pub fn look_at(
+    eye: &na::Point3<f32>,
+    target: &na::Point3<f32>,
+    up: &na::Vector3<f32>,
+) -> na::Isometry3<f32> {
+    // The camera's +Y points down the image
+    na::Isometry3::face_towards(eye, target, &-up)
+}
+
+/// Position on the image mapped to [-1, 1] along both axes.
+fn film_to_screen(film: &na::Point2<f32>, width: u32, height: u32) -> na::Point2<f32> {
+    na::Point2::new(
+        2.0 * film.x / width as f32 - 1.0,
+        2.0 * film.y / height as f32 - 1.0,
+    )
+}
+
+/// Inverse of `film_to_screen`.
+fn screen_to_film(screen: &na::Point2<f32>, width: u32, height: u32) -> na::Point2<f32> {
+    na::Point2::new(
+        (screen.x + 1.0) * width as f32 / 2.0,
+        (screen.y + 1.0) * height as f32 / 2.0,
+    )
+}
+
+/// A camera with a symmetric field of view. It is a pinhole camera when the
+/// aperture radius is zero, otherwise a thin lens which only keeps the plane
+/// at the focus distance sharp.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerspectiveCamera {
+    pub pose: na::Isometry3<f32>,
+    /// Vertical field of view in radians.
+    pub fov: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Radius of the lens in world units.
+    pub aperture_radius: f32,
+    /// Distance along the view direction of the plane in focus.
+    pub focus_distance: f32,
+}
+
+impl PerspectiveCamera {
+    pub fn look_at(
+        eye: &na::Point3<f32>,
+        target: &na::Point3<f32>,
+        up: &na::Vector3<f32>,
+        fov: f32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        PerspectiveCamera {
+            pose: look_at(eye, target, up),
+            fov,
+            width,
+            height,
+            aperture_radius: 0.0,
+            focus_distance: (target - eye).norm(),
+        }
+    }
+
+    /// Half the width and height of the image plane at unit distance.
+    fn half_extent(&self) -> na::Vector2<f32> {
+        let half_height = (self.fov / 2.0).tan();
+        let aspect = self.width as f32 / self.height as f32;
+        na::Vector2::new(half_height * aspect, half_height)
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn pose(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn generate_ray(&self, film: &na::Point2<f32>, lens: &na::Point2<f32>) -> Ray {
+        let screen = film_to_screen(film, self.width, self.height);
+        let extent = self.half_extent();
+        let direction = na::Vector3::new(screen.x * extent.x, screen.y * extent.y, 1.0);
+        if self.aperture_radius <= 0.0 {
+            return Ray {
+                origin: self.pose * na::Point3::origin(),
+                direction: (self.pose * direction).normalize(),
+            };
+        }
+
+        // Rays through the centre of a thin lens are not bent, and all rays
+        // from one point on the film meet again on the focus plane
+        let focus = na::Point3::from(direction * self.focus_distance);
+        let disk = sampling::concentric_disk(lens) * self.aperture_radius;
+        let origin = na::Point3::new(disk.x, disk.y, 0.0);
+        Ray {
+            origin: self.pose * origin,
+            direction: (self.pose * (focus - origin)).normalize(),
+        }
+    }
+
+    fn project_point(&self, point: &na::Point3<f32>) -> Option<na::Point2<f32>> {
+        let local = self.pose.inverse_transform_point(point);
+        if local.z <= 0.0 {
+            return None;
+        }
+        let extent = self.half_extent();
+        let screen = na::Point2::new(local.x / local.z / extent.x, local.y / local.z / extent.y);
+        Some(screen_to_film(&screen, self.width, self.height))
+    }
+}
+
+/// A camera with parallel rays, which starts them on its image plane so that
+/// only what is in front of that plane is seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrthographicCamera {
+    pub pose: na::Isometry3<f32>,
+    /// Height of the image plane in world units.
+    pub view_height: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OrthographicCamera {
+    pub fn look_at(
+        eye: &na::Point3<f32>,
+        target: &na::Point3<f32>,
+        up: &na::Vector3<f32>,
+        view_height: f32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        OrthographicCamera {
+            pose: look_at(eye, target, up),
+            view_height,
+            width,
+            height,
+        }
+    }
+
+    /// Half the width and height of the image plane.
+    fn half_extent(&self) -> na::Vector2<f32> {
+        let aspect = self.width as f32 / self.height as f32;
+        na::Vector2::new(self.view_height * aspect, self.view_height) / 2.0
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn pose(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn generate_ray(&self, film: &na::Point2<f32>, _lens: &na::Point2<f32>) -> Ray {
+        let screen = film_to_screen(film, self.width, self.height);
+        let extent = self.half_extent();
+        let origin = na::Point3::new(screen.x * extent.x, screen.y * extent.y, 0.0);
+        Ray {
+            origin: self.pose * origin,
+            direction: self.pose * na::Vector3::z(),
+        }
+    }
+
+    fn project_point(&self, point: &na::Point3<f32>) -> Option<na::Point2<f32>> {
+        let local = self.pose.inverse_transform_point(point);
+        if local.z < 0.0 {
+            return None;
+        }
+        let extent = self.half_extent();
+        let screen = na::Point2::new(local.x / extent.x, local.y / extent.y);
+        Some(screen_to_film(&screen, self.width, self.height))
+    }
+}
+
+/// Lens distortion, mapping the direction of a point in camera space to its
+/// position on the image plane at unit distance before the intrinsics are
+/// applied. Uses the same models and coefficients as OpenCV.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distortion {
+    /// An ideal pinhole.
+    None,
+    /// Radial (`k1`, `k2`, `k3`) and tangential (`p1`, `p2`) distortion.
+    BrownConrady {
+        k1: f32,
+        k2: f32,
+        p1: f32,
+        p2: f32,
+        k3: f32,
+    },
+    /// Equidistant fisheye, whose image radius grows with the angle from the
+    /// optical axis rather than its tangent, so it can see 180 degrees and
+    /// beyond.
+    Fisheye { k1: f32, k2: f32, k3: f32, k4: f32 },
+}
+
+/// Iterations used to invert distortion models, which converge in a handful
+/// for realistic coefficients.
+const UNDISTORT_ITERATIONS: usize = 20;
+
+impl Distortion {
+    /// Distorted image plane position of the camera space `direction`, or
+    /// `None` if the model cannot see it.
+    pub fn distort(&self, direction: &na::Vector3<f32>) -> Option<na::Point2<f32>> {
+        match *self {
+            Distortion::None => {
+                if direction.z <= 0.0 {
+                    return None;
+                }
+                Some(na::Point2::new(
+                    direction.x / direction.z,
+                    direction.y / direction.z,
+                ))
+            }
+            Distortion::BrownConrady { .. } => {
+                if direction.z <= 0.0 {
+                    return None;
+                }
+                let point = na::Point2::new(direction.x / direction.z, direction.y / direction.z);
+                Some(self.brown_conrady(&point).0)
+            }
+            Distortion::Fisheye { k1, k2, k3, k4 } => {
+                let r = direction.xy().norm();
+                if r == 0.0 {
+                    return if direction.z > 0.0 {
+                        Some(na::Point2::origin())
+                    } else {
+                        None
+                    };
+                }
+                let theta = r.atan2(direction.z);
+                let theta2 = theta * theta;
+                let theta_d =
+                    theta * (1.0 + theta2 * (k1 + theta2 * (k2 + theta2 * (k3 + theta2 * k4))));
+                Some(na::Point2::from(direction.xy() * (theta_d / r)))
+            }
+        }
+    }
+
+    /// Unit camera space direction seen at the distorted image plane
+    /// position `point`, the inverse of `distort`.
+    pub fn undistort(&self, point: &na::Point2<f32>) -> na::Vector3<f32> {
+        match *self {
+            Distortion::None => na::Vector3::new(point.x, point.y, 1.0).normalize(),
+            Distortion::BrownConrady { .. } => {
+                // Newton's method, starting from the distorted position
+                let mut undistorted = *point;
+                for _ in 0..UNDISTORT_ITERATIONS {
+                    let (distorted, jacobian) = self.brown_conrady(&undistorted);
+                    let step = match jacobian.try_inverse() {
+                        Some(inverse) => inverse * (distorted - point),
+                        None => break,
+                    };
+                    undistorted -= step;
+                    if step.norm() < 1.0e-7 {
+                        break;
+                    }
+                }
+                na::Vector3::new(undistorted.x, undistorted.y, 1.0).normalize()
+            }
+            Distortion::Fisheye { k1, k2, k3, k4 } => {
+                let theta_d = point.coords.norm();
+                if theta_d == 0.0 {
+                    return na::Vector3::z();
+                }
+                let mut theta = theta_d;
+                for _ in 0..UNDISTORT_ITERATIONS {
+                    let theta2 = theta * theta;
+                    let value = theta
+                        * (1.0 + theta2 * (k1 + theta2 * (k2 + theta2 * (k3 + theta2 * k4))))
+                        - theta_d;
+                    let slope = 1.0
+                        + theta2
+                            * (3.0 * k1
+                                + theta2 * (5.0 * k2 + theta2 * (7.0 * k3 + theta2 * 9.0 * k4)));
+                    let step = value / slope;
+                    theta -= step;
+                    if step.abs() < 1.0e-7 {
+                        break;
+                    }
+                }
+                let xy = point.coords * (theta.sin() / theta_d);
+                na::Vector3::new(xy.x, xy.y, theta.cos())
+            }
+        }
+    }
+
+    /// Brown-Conrady distortion of the undistorted image plane position
+    /// `point`, and its Jacobian.
+    fn brown_conrady(&self, point: &na::Point2<f32>) -> (na::Point2<f32>, na::Matrix2<f32>) {
+        let (k1, k2, p1, p2, k3) = match *self {
+            Distortion::BrownConrady { k1, k2, p1, p2, k3 } => (k1, k2, p1, p2, k3),
+            _ => (0.0, 0.0, 0.0, 0.0, 0.0),
+        };
+        let (x, y) = (point.x, point.y);
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        // Derivative of `radial` with respect to r^2
+        let d_radial = k1 + r2 * (2.0 * k2 + r2 * 3.0 * k3);
+        let distorted = na::Point2::new(
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+        );
+        let cross = 2.0 * x * y * d_radial + 2.0 * p1 * x + 2.0 * p2 * y;
+        let jacobian = na::Matrix2::new(
+            radial + 2.0 * x * x * d_radial + 2.0 * p1 * y + 6.0 * p2 * x,
+            cross,
+            cross,
+            radial + 2.0 * y * y * d_radial + 6.0 * p1 * y + 2.0 * p2 * x,
+        );
+        (distorted, jacobian)
+    }
+}
+
+/// A camera described by the intrinsics of a calibrated real camera, i.e.
+/// the focal lengths and principal point of its matrix K, plus lens
+/// distortion.
+///
+/// The principal point is an image position as used by `Camera`, where the
+/// centre of the top left pixel is at (0.5, 0.5). Calibrations from OpenCV
+/// put it at (0, 0), so add 0.5 to their `cx` and `cy`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PinholeCamera {
+    pub pose: na::Isometry3<f32>,
+    /// Focal lengths in pixels.
+    pub fx: f32,
+    pub fy: f32,
+    /// Principal point in pixels.
+    pub cx: f32,
+    pub cy: f32,
+    pub distortion: Distortion,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PinholeCamera {
+    /// Camera with the intrinsic matrix `k`. Its skew must be zero.
+    pub fn from_intrinsics(
+        pose: na::Isometry3<f32>,
+        k: &na::Matrix3<f32>,
+        distortion: Distortion,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        PinholeCamera {
+            pose,
+            fx: k[(0, 0)],
+            fy: k[(1, 1)],
+            cx: k[(0, 2)],
+            cy: k[(1, 2)],
+            distortion,
+            width,
+            height,
+        }
+    }
+
+    /// The intrinsic matrix K.
+    pub fn intrinsics(&self) -> na::Matrix3<f32> {
+        na::Matrix3::new(
+            self.fx, 0.0, self.cx, //
+            0.0, self.fy, self.cy, //
+            0.0, 0.0, 1.0,
+        )
+    }
+}
+
+impl Camera for PinholeCamera {
+    fn pose(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn generate_ray(&self, film: &na::Point2<f32>, _lens: &na::Point2<f32>) -> Ray {
+        let distorted = na::Point2::new((film.x - self.cx) / self.fx, (film.y - self.cy) / self.fy);
+        Ray {
+            origin: self.pose * na::Point3::origin(),
+            direction: self.pose * self.distortion.undistort(&distorted),
+        }
+    }
+
+    fn project_point(&self, point: &na::Point3<f32>) -> Option<na::Point2<f32>> {
+        let local = self.pose.inverse_transform_point(point);
+        let distorted = self.distortion.distort(&local.coords)?;
+        Some(na::Point2::new(
+            self.fx * distorted.x + self.cx,
+            self.fy * distorted.y + self.cy,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn lens_center() -> na::Point2<f32> {
+        na::Point2::new(0.5, 0.5)
+    }
+
+    fn make_camera() -> PerspectiveCamera {
+        PerspectiveCamera::look_at(
+            &na::Point3::new(1.0, 2.0, 3.0),
+            &na::Point3::new(1.0, 2.0, -7.0),
+            &na::Vector3::y(),
+            std::f32::consts::FRAC_PI_2,
+            200,
+            100,
+        )
+    }
+
+    #[test]
+    fn rays_start_at_eye() {
+        let camera = make_camera();
+        let ray = camera.generate_ray(&na::Point2::new(100.0, 50.0), &lens_center());
+        assert!(relative_eq!(ray.origin, na::Point3::new(1.0, 2.0, 3.0)));
+        assert!(relative_eq!(ray.direction, -na::Vector3::z()));
+    }
+
+    #[test]
+    fn image_corners() {
+        let camera = make_camera();
+        // Top left is up and to the left of the view direction
+        let ray = camera.generate_ray(&na::Point2::new(0.0, 0.0), &lens_center());
+        let expected = na::Vector3::new(-2.0, 1.0, -1.0).normalize();
+        assert!(relative_eq!(ray.direction, expected, epsilon = 1.0e-6));
+        let ray = camera.generate_ray(&na::Point2::new(200.0, 100.0), &lens_center());
+        let expected = na::Vector3::new(2.0, -1.0, -1.0).normalize();
+        assert!(relative_eq!(ray.direction, expected, epsilon = 1.0e-6));
+    }
+
+    #[test]
+    fn project_and_unproject() {
+        let camera = PerspectiveCamera::look_at(
+            &na::Point3::new(-3.0, 4.0, 2.0),
+            &na::Point3::new(0.0, 0.0, 0.0),
+            &na::Vector3::z(),
+            0.7,
+            64,
+            48,
+        );
+        for film in [(0.5, 0.5), (10.25, 40.0), (63.5, 12.0)].iter() {
+            let film = na::Point2::new(film.0, film.1);
+            let ray = camera.generate_ray(&film, &lens_center());
+            let point = ray.origin + ray.direction * 5.0;
+            let projected = camera.project_point(&point).unwrap();
+            assert!(relative_eq!(projected, film, epsilon = 1.0e-3));
+        }
+        let behind = camera.pose * na::Point3::new(0.0, 0.0, -1.0);
+        assert_eq!(camera.project_point(&behind), None);
+    }
+
+    #[test]
+    fn thin_lens_focuses_on_plane() {
+        let mut camera = make_camera();
+        camera.aperture_radius = 0.5;
+        camera.focus_distance = 4.0;
+        let film = na::Point2::new(30.0, 70.0);
+        for lens in [(0.0, 0.0), (0.9, 0.2), (0.3, 0.99)].iter() {
+            let ray = camera.generate_ray(&film, &na::Point2::new(lens.0, lens.1));
+            let offset = ray.origin - na::Point3::new(1.0, 2.0, 3.0);
+            assert!(offset.z.abs() < 1.0e-6);
+            assert!(offset.norm() > 0.1 && offset.norm() <= 0.5 + 1.0e-6);
+
+            // Every ray through the lens meets the focus plane where the
+            // pinhole projection used for overlays puts it
+            let distance = 4.0 / -ray.direction.z;
+            let focused = ray.origin + ray.direction * distance;
+            assert!(relative_eq!(focused.z, -1.0, epsilon = 1.0e-5));
+            let projected = camera.project_point(&focused).unwrap();
+            assert!(relative_eq!(projected, film, epsilon = 1.0e-3));
+        }
+    }
+
+    fn make_orthographic() -> OrthographicCamera {
+        OrthographicCamera::look_at(
+            &na::Point3::new(0.0, 0.0, 10.0),
+            &na::Point3::origin(),
+            &na::Vector3::y(),
+            4.0,
+            200,
+            100,
+        )
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = make_orthographic();
+        let center = camera.generate_ray(&na::Point2::new(100.0, 50.0), &lens_center());
+        assert!(relative_eq!(center.origin, na::Point3::new(0.0, 0.0, 10.0)));
+        assert!(relative_eq!(center.direction, -na::Vector3::z()));
+
+        // The image plane is 8 by 4 units, with the top left corner up and to
+        // the left of the eye
+        let corner = camera.generate_ray(&na::Point2::new(0.0, 0.0), &lens_center());
+        assert!(relative_eq!(
+            corner.origin,
+            na::Point3::new(-4.0, 2.0, 10.0),
+            epsilon = 1.0e-6
+        ));
+        assert!(relative_eq!(corner.direction, center.direction));
+    }
+
+    #[test]
+    fn orthographic_project_and_unproject() {
+        let camera = make_orthographic();
+        for film in [(0.5, 0.5), (10.25, 40.0), (199.5, 12.0)].iter() {
+            let film = na::Point2::new(film.0, film.1);
+            let ray = camera.generate_ray(&film, &lens_center());
+            for distance in [0.0, 3.0, 25.0].iter() {
+                let point = ray.origin + ray.direction * *distance;
+                let projected = camera.project_point(&point).unwrap();
+                assert!(relative_eq!(projected, film, epsilon = 1.0e-3));
+            }
+        }
+        assert_eq!(camera.project_point(&na::Point3::new(0.0, 0.0, 11.0)), None);
+    }
+
+    fn brown_conrady() -> Distortion {
+        Distortion::BrownConrady {
+            k1: -0.12,
+            k2: 0.02,
+            p1: 0.0012,
+            p2: -0.0008,
+            k3: -0.001,
+        }
+    }
+
+    fn fisheye() -> Distortion {
+        Distortion::Fisheye {
+            k1: 0.05,
+            k2: -0.01,
+            k3: 0.003,
+            k4: -0.0005,
+        }
+    }
+
+    #[test]
+    fn distort_and_undistort() {
+        for distortion in [Distortion::None, brown_conrady(), fisheye()].iter() {
+            for direction in [(0.0, 0.0), (0.3, -0.2), (-0.6, -0.45), (0.1, 0.7)].iter() {
+                let direction = na::Vector3::new(direction.0, direction.1, 1.0).normalize();
+                let distorted = distortion.distort(&direction).unwrap();
+                let undistorted = distortion.undistort(&distorted);
+                assert!(
+                    relative_eq!(undistorted, direction, epsilon = 1.0e-5),
+                    "{:?} maps {} to {}",
+                    distortion,
+                    direction,
+                    undistorted
+                );
+            }
+            assert_eq!(distortion.distort(&-na::Vector3::z()), None);
+        }
+
+        // Barrel distortion pulls points towards the centre
+        let point = brown_conrady().distort(&na::Vector3::new(0.5, 0.0, 1.0));
+        assert!(point.unwrap().x < 0.5);
+
+        // A fisheye sees beyond 90 degrees from its axis
+        let sideways = na::Vector3::new(1.0, 0.0, -0.2).normalize();
+        let distorted = fisheye().distort(&sideways).unwrap();
+        let undistorted = fisheye().undistort(&distorted);
+        assert!(relative_eq!(undistorted, sideways, epsilon = 1.0e-5));
+    }
+
+    #[test]
+    fn pinhole_matches_perspective() {
+        // Without distortion and with the principal point in the centre, K
+        // describes the same camera as a field of view
+        let perspective = PerspectiveCamera::look_at(
+            &na::Point3::new(1.0, -2.0, 0.5),
+            &na::Point3::new(0.0, 3.0, 0.0),
+            &na::Vector3::z(),
+            std::f32::consts::FRAC_PI_2,
+            200,
+            100,
+        );
+        let k = na::Matrix3::new(50.0, 0.0, 100.0, 0.0, 50.0, 50.0, 0.0, 0.0, 1.0);
+        let pinhole =
+            PinholeCamera::from_intrinsics(perspective.pose, &k, Distortion::None, 200, 100);
+        assert_eq!(pinhole.intrinsics(), k);
+        for film in [(0.5, 0.5), (10.25, 40.0), (199.5, 99.5)].iter() {
+            let film = na::Point2::new(film.0, film.1);
+            let expected = perspective.generate_ray(&film, &lens_center());
+            let ray = pinhole.generate_ray(&film, &lens_center());
+            assert!(relative_eq!(ray.origin, expected.origin));
+            assert!(relative_eq!(
+                ray.direction,
+                expected.direction,
+                epsilon = 1.0e-6
+            ));
+        }
+    }
+
+    #[test]
+    fn pinhole_project_and_unproject() {
+        let pose = look_at(
+            &na::Point3::new(-3.0, 4.0, 2.0),
+            &na::Point3::new(0.0, 0.0, 0.0),
+            &na::Vector3::z(),
+        );
+        for distortion in [Distortion::None, brown_conrady(), fisheye()].iter() {
+            let camera = PinholeCamera {
+                pose,
+                fx: 410.0,
+                fy: 405.0,
+                cx: 322.3,
+                cy: 238.9,
+                distortion: distortion.clone(),
+                width: 640,
+                height: 480,
+            };
+            for film in [(0.5, 0.5), (100.25, 400.0), (320.0, 240.0), (639.5, 12.0)].iter() {
+                let film = na::Point2::new(film.0, film.1);
+                let ray = camera.generate_ray(&film, &lens_center());
+                let point = ray.origin + ray.direction * 5.0;
+                let projected = camera.project_point(&point).unwrap();
+                assert!(
+                    relative_eq!(projected, film, epsilon = 1.0e-2),
+                    "{:?} projects {} to {}",
+                    distortion,
+                    film,
+                    projected
+                );
+            }
+        }
+    }
+}
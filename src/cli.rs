@@ -0,0 +1,668 @@
+extern crate clap;
+extern crate image;
+extern crate log;
+extern crate raymundo;
+
+use std::path::{Path, PathBuf};
+
+use clap::{ArgAction, Parser, ValueEnum};
+use log::LevelFilter;
+
+use raymundo::aov::Aov;
+use raymundo::filter::{BoxFilter, Filter, GaussianFilter, MitchellFilter, TentFilter};
+use raymundo::hdr::{ExrPrecision, HdrFormat};
+use raymundo::integrator::{DirectLighting, Integrator, PathTracer, Whitted};
+use raymundo::point_cloud::{CloudFormat, Encoding};
+use raymundo::sampler::{
+    BlueNoiseSampler, HaltonSampler, IndependentSampler, Sampler, SobolSampler, StratifiedSampler,
+};
+use raymundo::tonemap::{AcesFilmic, Clamp, Hable, Reinhard, ToneMapper, ToneMapping, Transfer};
+
+/// Renders a scene with the raymundo ray tracer.
+#[derive(Debug, Parser)]
+#[command(name = "raymundo", version)]
+pub struct Args {
+    /// Scene description in TOML. Renders the built-in two sphere scene if
+    /// not given.
+    #[arg(long, value_name = "FILE")]
+    pub scene: Option<PathBuf>,
+
+    /// Where to write the rendered image.
+    #[arg(short, long, value_name = "PATH", default_value = "test.png")]
+    pub output: PathBuf,
+
+    /// Image format of the output. Guessed from the output extension if not
+    /// given. EXR, HDR and PFM keep the unclamped radiance, without tone
+    /// mapping or overlays.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Per-pixel outputs to write next to the image, as
+    /// <output stem>.<aov>.png for depth and object IDs or .exr otherwise.
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "AOV")]
+    pub aov: Vec<AovKind>,
+
+    /// Write segmentation masks next to the image: instance IDs as
+    /// <output stem>.instance.png, classes coloured by the scene's palette as
+    /// .class.png, and what the IDs stand for as .segmentation.json.
+    #[arg(long)]
+    pub segmentation: bool,
+
+    /// Scan the scene with each of its lidars, writing the point cloud as
+    /// <output stem>.<lidar name>.ply or .pcd.
+    #[arg(long)]
+    pub lidar: bool,
+
+    /// Write the depth AOV as a point cloud coloured by the image, organized
+    /// in rows like its pixels, to <output stem>.cloud.ply or .pcd.
+    #[arg(long)]
+    pub depth_cloud: bool,
+
+    /// Point cloud in PLY or PCD to draw onto the image, e.g. one written by
+    /// --depth-cloud, to check that it lines up. Its frame is given by
+    /// --cloud-frame, and --reproject-lidar for clouds of a lidar.
+    #[arg(long, value_name = "FILE")]
+    pub reproject_cloud: Option<PathBuf>,
+
+    /// Lidar of the scene that captured the cloud given to --reproject-cloud,
+    /// placing it when in the sensor frame. The camera if not given.
+    #[arg(long, value_name = "NAME", requires = "reproject_cloud")]
+    pub reproject_lidar: Option<String>,
+
+    /// File format of point clouds.
+    #[arg(long, value_enum, default_value_t = CloudFormatKind::Ply)]
+    pub cloud_format: CloudFormatKind,
+
+    /// Encoding of the values in point clouds.
+    #[arg(long, value_enum, default_value_t = CloudEncoding::Binary)]
+    pub cloud_encoding: CloudEncoding,
+
+    /// Frame of the points in point clouds.
+    #[arg(long, value_enum, default_value_t = CloudFrame::Sensor)]
+    pub cloud_frame: CloudFrame,
+
+    /// Depth AOV units per scene unit, e.g. 1000 for millimetres with scenes
+    /// in metres.
+    #[arg(long, default_value_t = 1000.0)]
+    pub depth_scale: f32,
+
+    /// Precision of the values in EXR output.
+    #[arg(long, value_enum, default_value_t = ExrPixelType::Half)]
+    pub exr_pixel_type: ExrPixelType,
+
+    /// Image width in pixels, overriding the scene file.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=65536))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels, overriding the scene file.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=65536))]
+    pub height: Option<u32>,
+
+    /// Vertical field of view in degrees, overriding the scene file.
+    #[arg(long, value_parser = parse_fov)]
+    pub fov: Option<f32>,
+
+    /// How the colour along each camera ray is computed.
+    #[arg(long, value_enum, default_value_t = IntegratorKind::Whitted)]
+    pub integrator: IntegratorKind,
+
+    /// Maximum number of bounces for the whitted and path integrators.
+    #[arg(long, value_name = "BOUNCES")]
+    pub max_depth: Option<u32>,
+
+    /// Number of samples per pixel. More than one are spread across the
+    /// pixel to anti-alias edges.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: u32,
+
+    /// How samples are weighted to reconstruct each pixel.
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    pub filter: FilterKind,
+
+    /// How radiance above one is compressed for display.
+    #[arg(long, value_enum, default_value_t = ToneMapKind::Clamp)]
+    pub tone_map: ToneMapKind,
+
+    /// Exposure adjustment in stops, each doubling the brightness.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+
+    /// Encoding of the tone mapped values written to the image.
+    #[arg(long, value_enum, default_value_t = TransferKind::Srgb)]
+    pub transfer: TransferKind,
+
+    /// Number of worker threads. Defaults to the number of CPUs.
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// How sample values are generated. All but independent spread the
+    /// samples of each pixel evenly, which reduces noise.
+    #[arg(long, value_enum, default_value_t = SamplerKind::Independent)]
+    pub sampler: SamplerKind,
+
+    /// Seed for the sampler. Renders with the same seed are identical.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Most detailed kind of log message to print.
+    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+
+    /// Only print errors. Overrides --log-level.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print debug messages, or trace messages if given twice. Overrides
+    /// --log-level.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Tiff,
+    /// OpenEXR
+    Exr,
+    /// Radiance RGBE
+    Hdr,
+    /// Portable float map
+    Pfm,
+}
+
+impl OutputFormat {
+    /// Format of the tone mapped 8-bit image, None for floating point
+    /// formats.
+    pub fn image_format(self) -> Option<image::ImageFormat> {
+        match self {
+            OutputFormat::Png => Some(image::ImageFormat::Png),
+            OutputFormat::Jpeg => Some(image::ImageFormat::Jpeg),
+            OutputFormat::Bmp => Some(image::ImageFormat::Bmp),
+            OutputFormat::Tga => Some(image::ImageFormat::Tga),
+            OutputFormat::Tiff => Some(image::ImageFormat::Tiff),
+            OutputFormat::Exr | OutputFormat::Hdr | OutputFormat::Pfm => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "tga" => Some(OutputFormat::Tga),
+            "tif" | "tiff" => Some(OutputFormat::Tiff),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ExrPixelType {
+    /// 16-bit half floats.
+    Half,
+    /// 32-bit floats.
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum AovKind {
+    /// Distance along the optical axis, 16-bit.
+    Depth,
+    /// World space normal.
+    Normal,
+    /// World space position.
+    Position,
+    /// Diffuse reflectance.
+    Albedo,
+    /// Per-shape ID numbered by name, 16-bit.
+    ObjectId,
+}
+
+impl AovKind {
+    pub fn aov(self) -> Aov {
+        match self {
+            AovKind::Depth => Aov::Depth,
+            AovKind::Normal => Aov::Normal,
+            AovKind::Position => Aov::Position,
+            AovKind::Albedo => Aov::Albedo,
+            AovKind::ObjectId => Aov::ObjectId,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CloudFormatKind {
+    /// Stanford PLY.
+    Ply,
+    /// Point Cloud Library PCD.
+    Pcd,
+}
+
+impl CloudFormatKind {
+    pub fn format(self) -> CloudFormat {
+        match self {
+            CloudFormatKind::Ply => CloudFormat::Ply,
+            CloudFormatKind::Pcd => CloudFormat::Pcd,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            CloudFormatKind::Ply => "ply",
+            CloudFormatKind::Pcd => "pcd",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CloudEncoding {
+    Binary,
+    Ascii,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CloudFrame {
+    /// Relative to the sensor that captured the points, the lidar or the
+    /// camera.
+    Sensor,
+    /// World coordinates.
+    World,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum IntegratorKind {
+    /// Direct lighting at the first hit only.
+    Direct,
+    /// Direct lighting plus mirror reflections and refraction.
+    Whitted,
+    /// Monte Carlo path tracing for global illumination.
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum FilterKind {
+    /// Average of the samples inside each pixel.
+    Box,
+    /// Linear falloff over one pixel.
+    Tent,
+    /// Smooth falloff over one and a half pixels.
+    Gaussian,
+    /// Mitchell-Netravali cubic over two pixels, sharper than the Gaussian.
+    Mitchell,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ToneMapKind {
+    /// Clip values above one.
+    Clamp,
+    /// Reinhard's operator on luminance.
+    Reinhard,
+    /// Fit of the ACES filmic curve.
+    Aces,
+    /// Hable's filmic curve.
+    Hable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TransferKind {
+    /// The sRGB curve image viewers expect.
+    Srgb,
+    /// Linear values.
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SamplerKind {
+    /// Uniform random values.
+    Independent,
+    /// Jittered strata.
+    Stratified,
+    /// The scrambled Halton sequence.
+    Halton,
+    /// Owen scrambled Sobol points, best with a power of two samples.
+    Sobol,
+    /// Sobol points shifted per pixel by blue noise, leaving fine-grained
+    /// noise.
+    BlueNoise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+fn parse_fov(value: &str) -> Result<f32, String> {
+    let fov: f32 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))?;
+    if fov > 0.0 && fov < 180.0 {
+        Ok(fov)
+    } else {
+        Err("must be between 0 and 180 degrees".to_string())
+    }
+}
+
+impl Args {
+    pub fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            return LevelFilter::Error;
+        }
+        match self.verbose {
+            0 => {}
+            1 => return LevelFilter::Debug,
+            _ => return LevelFilter::Trace,
+        }
+        match self.log_level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+
+    /// Format to write the output in, from `--format` or the output
+    /// extension.
+    pub fn output_format(&self) -> Result<OutputFormat, String> {
+        match self.format {
+            Some(format) => Ok(format),
+            None => OutputFormat::from_path(&self.output).ok_or_else(|| {
+                format!(
+                    "Cannot tell the image format of '{}' from its extension, use --format",
+                    self.output.display()
+                )
+            }),
+        }
+    }
+
+    /// Path next to the output image, with `suffix` and `extension` in place
+    /// of its extension.
+    pub fn sibling_path(&self, suffix: &str, extension: &str) -> PathBuf {
+        let stem = self
+            .output
+            .file_stem()
+            .map_or_else(|| "output".into(), |stem| stem.to_string_lossy());
+        self.output
+            .with_file_name(format!("{}.{}.{}", stem, suffix, extension))
+    }
+
+    /// Where to write `aov`, next to the output image.
+    pub fn aov_path(&self, aov: Aov) -> PathBuf {
+        self.sibling_path(aov.name(), aov.extension())
+    }
+
+    /// Where to write the point cloud named `name`, next to the output image.
+    pub fn cloud_path(&self, name: &str) -> PathBuf {
+        self.sibling_path(name, self.cloud_format.extension())
+    }
+
+    pub fn cloud_encoding(&self) -> Encoding {
+        match self.cloud_encoding {
+            CloudEncoding::Binary => Encoding::Binary,
+            CloudEncoding::Ascii => Encoding::Ascii,
+        }
+    }
+
+    /// Floating point format to write the film in, if `format` is one.
+    pub fn hdr_format(&self, format: OutputFormat) -> Option<HdrFormat> {
+        let precision = match self.exr_pixel_type {
+            ExrPixelType::Half => ExrPrecision::Half,
+            ExrPixelType::Float => ExrPrecision::Float,
+        };
+        match format {
+            OutputFormat::Exr => Some(HdrFormat::Exr(precision)),
+            OutputFormat::Hdr => Some(HdrFormat::Radiance),
+            OutputFormat::Pfm => Some(HdrFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    pub fn filter(&self) -> Box<dyn Filter> {
+        match self.filter {
+            FilterKind::Box => Box::new(BoxFilter::default()),
+            FilterKind::Tent => Box::new(TentFilter::default()),
+            FilterKind::Gaussian => Box::new(GaussianFilter::default()),
+            FilterKind::Mitchell => Box::new(MitchellFilter::default()),
+        }
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        let operator: Box<dyn ToneMapper> = match self.tone_map {
+            ToneMapKind::Clamp => Box::new(Clamp),
+            ToneMapKind::Reinhard => Box::new(Reinhard::default()),
+            ToneMapKind::Aces => Box::new(AcesFilmic),
+            ToneMapKind::Hable => Box::new(Hable::default()),
+        };
+        ToneMapping {
+            exposure: self.exposure,
+            operator,
+            transfer: match self.transfer {
+                TransferKind::Srgb => Transfer::Srgb,
+                TransferKind::Linear => Transfer::Linear,
+            },
+        }
+    }
+
+    pub fn sampler(&self) -> Box<dyn Sampler> {
+        match self.sampler {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(self.seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(self.seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(self.seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(self.seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(self.seed)),
+        }
+    }
+
+    pub fn integrator(&self) -> Box<dyn Integrator> {
+        match self.integrator {
+            IntegratorKind::Direct => Box::new(DirectLighting),
+            IntegratorKind::Whitted => {
+                let mut whitted = Whitted::default();
+                if let Some(max_depth) = self.max_depth {
+                    whitted.max_depth = max_depth;
+                }
+                Box::new(whitted)
+            }
+            IntegratorKind::Path => {
+                let mut path_tracer = PathTracer::default();
+                if let Some(max_depth) = self.max_depth {
+                    path_tracer.max_depth = max_depth;
+                }
+                Box::new(path_tracer)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("raymundo").chain(args.iter().cloned()))
+    }
+
+    #[test]
+    fn command_is_well_formed() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn defaults() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.output, PathBuf::from("test.png"));
+        assert_eq!(args.output_format(), Ok(OutputFormat::Png));
+        assert_eq!(args.spp, 1);
+        assert_eq!(args.filter, FilterKind::Box);
+        assert_eq!(args.sampler, SamplerKind::Independent);
+        assert_eq!(args.tone_map, ToneMapKind::Clamp);
+        assert_eq!(args.tone_mapping().exposure, 0.0);
+        assert_eq!(args.tone_mapping().transfer, Transfer::Srgb);
+        assert_eq!(args.log_level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn output_format() {
+        let args = parse(&["-o", "out.JPG"]).unwrap();
+        assert_eq!(args.output_format(), Ok(OutputFormat::Jpeg));
+        let args = parse(&["-o", "out.img"]).unwrap();
+        assert!(args.output_format().is_err());
+        let args = parse(&["-o", "out.img", "--format", "bmp"]).unwrap();
+        assert_eq!(args.output_format(), Ok(OutputFormat::Bmp));
+        assert!(parse(&["--format", "gif"]).is_err());
+    }
+
+    #[test]
+    fn aovs() {
+        assert!(parse(&[]).unwrap().aov.is_empty());
+        let args = parse(&[
+            "-o",
+            "out/frame.png",
+            "--aov",
+            "depth,object-id",
+            "--aov",
+            "normal",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.aov,
+            [AovKind::Depth, AovKind::ObjectId, AovKind::Normal]
+        );
+        assert_eq!(
+            args.aov_path(Aov::Depth),
+            PathBuf::from("out/frame.depth.png")
+        );
+        assert_eq!(
+            args.aov_path(Aov::Normal),
+            PathBuf::from("out/frame.normal.exr")
+        );
+        assert!(parse(&["--aov", "motion"]).is_err());
+
+        let args = parse(&["-o", "frame", "--segmentation"]).unwrap();
+        assert!(args.segmentation);
+        assert_eq!(
+            args.sibling_path("segmentation", "json"),
+            PathBuf::from("frame.segmentation.json")
+        );
+    }
+
+    #[test]
+    fn point_clouds() {
+        let args = parse(&["-o", "out/frame.png", "--lidar"]).unwrap();
+        assert!(args.lidar);
+        assert_eq!(args.cloud_format.format(), CloudFormat::Ply);
+        assert_eq!(args.cloud_encoding(), Encoding::Binary);
+        assert_eq!(args.cloud_frame, CloudFrame::Sensor);
+        assert_eq!(args.cloud_path("roof"), PathBuf::from("out/frame.roof.ply"));
+
+        let args = parse(&[
+            "--cloud-format",
+            "pcd",
+            "--cloud-encoding",
+            "ascii",
+            "--cloud-frame",
+            "world",
+        ])
+        .unwrap();
+        assert_eq!(args.cloud_path("roof"), PathBuf::from("test.roof.pcd"));
+        assert_eq!(args.cloud_encoding(), Encoding::Ascii);
+        assert_eq!(args.cloud_frame, CloudFrame::World);
+        assert!(parse(&["--cloud-format", "las"]).is_err());
+
+        let args = parse(&["--depth-cloud", "--reproject-cloud", "scan.pcd"]).unwrap();
+        assert!(args.depth_cloud);
+        assert_eq!(args.cloud_path("cloud"), PathBuf::from("test.cloud.ply"));
+        assert_eq!(args.reproject_cloud, Some(PathBuf::from("scan.pcd")));
+        assert_eq!(args.reproject_lidar, None);
+
+        let args = parse(&["--reproject-cloud", "a.ply", "--reproject-lidar", "roof"]).unwrap();
+        assert_eq!(args.reproject_lidar.as_deref(), Some("roof"));
+        assert!(parse(&["--reproject-lidar", "roof"]).is_err());
+    }
+
+    #[test]
+    fn hdr_formats() {
+        let hdr_format = |args: &[&str]| {
+            let args = parse(args).unwrap();
+            args.hdr_format(args.output_format().unwrap())
+        };
+        assert_eq!(hdr_format(&[]), None);
+        assert_eq!(
+            hdr_format(&["-o", "out.exr"]),
+            Some(HdrFormat::Exr(ExrPrecision::Half))
+        );
+        assert_eq!(
+            hdr_format(&["-o", "out.exr", "--exr-pixel-type", "float"]),
+            Some(HdrFormat::Exr(ExrPrecision::Float))
+        );
+        assert_eq!(
+            hdr_format(&["-o", "out.img", "--format", "pfm"]),
+            Some(HdrFormat::Pfm)
+        );
+        assert_eq!(hdr_format(&["-o", "out.HDR"]), Some(HdrFormat::Radiance));
+        assert_eq!(OutputFormat::Hdr.image_format(), None);
+    }
+
+    #[test]
+    fn tone_mapping() {
+        let args = parse(&[
+            "--tone-map",
+            "aces",
+            "--exposure",
+            "-1.5",
+            "--transfer",
+            "linear",
+        ])
+        .unwrap();
+        assert_eq!(args.tone_map, ToneMapKind::Aces);
+        let tone_mapping = args.tone_mapping();
+        assert_eq!(tone_mapping.exposure, -1.5);
+        assert_eq!(tone_mapping.transfer, Transfer::Linear);
+        assert!(parse(&["--exposure", "bright"]).is_err());
+    }
+
+    #[test]
+    fn samplers() {
+        let args = parse(&["--sampler", "blue-noise", "--seed", "4"]).unwrap();
+        assert_eq!(args.sampler, SamplerKind::BlueNoise);
+        assert_eq!(args.seed, 4);
+        assert!(parse(&["--sampler", "sobol"]).is_ok());
+        assert!(parse(&["--sampler", "latin"]).is_err());
+    }
+
+    #[test]
+    fn validation() {
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--spp", "0"]).is_err());
+        assert!(parse(&["--threads", "0"]).is_err());
+        assert!(parse(&["--fov", "180"]).is_err());
+        assert!(parse(&["--fov", "wide"]).is_err());
+        assert!(parse(&["--quiet", "--verbose"]).is_err());
+    }
+
+    #[test]
+    fn log_levels() {
+        assert_eq!(parse(&["-q"]).unwrap().log_level(), LevelFilter::Error);
+        assert_eq!(parse(&["-v"]).unwrap().log_level(), LevelFilter::Debug);
+        assert_eq!(parse(&["-vv"]).unwrap().log_level(), LevelFilter::Trace);
+        assert_eq!(
+            parse(&["--log-level", "warn"]).unwrap().log_level(),
+            LevelFilter::Warn
+        );
+    }
+}
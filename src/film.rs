@@ -0,0 +1,280 @@
+extern crate image;
+extern crate nalgebra as na;
+
+use crate::filter::Filter;
+use crate::hdr::HdrImage;
+use crate::material::Color;
+use crate::tonemap::ToneMapping;
+
+#[derive(Clone, Copy, Debug)]
+struct FilmPixel {
+    /// Sum of the weighted radiance of the samples around the pixel.
+    radiance: Color,
+    /// Sum of the sample weights.
+    weight: f32,
+}
+
+/// Accumulates radiance samples into pixels, weighting each by a
+/// reconstruction filter centred on the pixel.
+///
+/// A film may cover just a region of the image, so that parts of it can be
+/// rendered separately and merged afterwards. Positions and pixel
+/// coordinates are always those of the whole image.
+#[derive(Clone, Debug)]
+pub struct Film {
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Film::with_bounds(0, 0, width, height)
+    }
+
+    /// Film covering the `width` by `height` pixels from (`x0`, `y0`).
+    pub fn with_bounds(x0: u32, y0: u32, width: u32, height: u32) -> Self {
+        Film {
+            x0,
+            y0,
+            width,
+            height,
+            pixels: vec![
+                FilmPixel {
+                    radiance: Color::zeros(),
+                    weight: 0.0,
+                };
+                (width * height) as usize
+            ],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.x0 || y < self.y0 || x >= self.x0 + self.width || y >= self.y0 + self.height {
+            return None;
+        }
+        Some(((y - self.y0) * self.width + x - self.x0) as usize)
+    }
+
+    /// Adds the `radiance` seen at image position `point` to every pixel of
+    /// the film within the filter's radius.
+    pub fn add_sample(&mut self, filter: &dyn Filter, point: &na::Point2<f32>, radiance: &Color) {
+        // Pixel centres are at half integer positions
+        let radius = filter.radius();
+        let min_x = (point.x - 0.5 - radius).ceil().max(self.x0 as f32) as u32;
+        let min_y = (point.y - 0.5 - radius).ceil().max(self.y0 as f32) as u32;
+        let max_x = (point.x - 0.5 + radius).floor();
+        let max_y = (point.y - 0.5 + radius).floor();
+        if max_x < 0.0 || max_y < 0.0 {
+            return;
+        }
+        let max_x = (max_x as u32).min(self.x0 + self.width - 1);
+        let max_y = (max_y as u32).min(self.y0 + self.height - 1);
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let offset =
+                    na::Vector2::new(point.x - (x as f32 + 0.5), point.y - (y as f32 + 0.5));
+                let weight = filter.evaluate(&offset);
+                if weight == 0.0 {
+                    continue;
+                }
+                if let Some(idx) = self.index(x, y) {
+                    let pixel = &mut self.pixels[idx];
+                    pixel.radiance += radiance * weight;
+                    pixel.weight += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds the samples accumulated in `other`, over the region both cover.
+    pub fn merge(&mut self, other: &Film) {
+        for y in other.y0..other.y0 + other.height {
+            for x in other.x0..other.x0 + other.width {
+                if let (Some(idx), Some(other_idx)) = (self.index(x, y), other.index(x, y)) {
+                    let sample = other.pixels[other_idx];
+                    let pixel = &mut self.pixels[idx];
+                    pixel.radiance += sample.radiance;
+                    pixel.weight += sample.weight;
+                }
+            }
+        }
+    }
+
+    /// Filtered radiance of the pixel (`x`, `y`), black if no sample has
+    /// weight there.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        match self.index(x, y) {
+            Some(idx) if self.pixels[idx].weight != 0.0 => {
+                self.pixels[idx].radiance / self.pixels[idx].weight
+            }
+            _ => Color::zeros(),
+        }
+    }
+
+    /// The unclamped radiance of every pixel.
+    pub fn to_hdr(&self) -> HdrImage {
+        HdrImage::from_fn(self.width, self.height, |x, y| {
+            let radiance = self.pixel(self.x0 + x, self.y0 + y);
+            image::Rgb([radiance.x, radiance.y, radiance.z])
+        })
+    }
+
+    /// The film as an 8-bit image for display, with `tone_mapping` applied
+    /// to every pixel.
+    pub fn tone_map(&self, tone_mapping: &ToneMapping) -> image::RgbImage {
+        image::RgbImage::from_fn(self.width, self.height, |x, y| {
+            tone_mapping.to_rgb(&self.pixel(self.x0 + x, self.y0 + y))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    use crate::filter::{BoxFilter, GaussianFilter, MitchellFilter, TentFilter};
+    use crate::tonemap::{AcesFilmic, Transfer};
+
+    #[test]
+    fn box_filter_averages_pixel() {
+        let filter = BoxFilter::default();
+        let mut film = Film::new(4, 3);
+        film.add_sample(
+            &filter,
+            &na::Point2::new(1.2, 2.7),
+            &Color::new(1.0, 0.0, 0.0),
+        );
+        film.add_sample(
+            &filter,
+            &na::Point2::new(1.8, 2.1),
+            &Color::new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(film.pixel(1, 2), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(film.pixel(0, 2), Color::zeros());
+        assert_eq!(film.pixel(2, 2), Color::zeros());
+    }
+
+    #[test]
+    fn constant_radiance_is_preserved() {
+        let filters: [&dyn Filter; 4] = [
+            &BoxFilter::default(),
+            &TentFilter::default(),
+            &GaussianFilter::default(),
+            &MitchellFilter::default(),
+        ];
+        let radiance = Color::new(0.25, 0.5, 0.75);
+        for filter in filters.iter() {
+            let mut film = Film::new(5, 5);
+            for y in 0..20 {
+                for x in 0..20 {
+                    let point = na::Point2::new(x as f32 * 0.25 + 0.1, y as f32 * 0.25 + 0.1);
+                    film.add_sample(*filter, &point, &radiance);
+                }
+            }
+            for y in 0..5 {
+                for x in 0..5 {
+                    assert!(relative_eq!(film.pixel(x, y), radiance, epsilon = 1.0e-5));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wide_filters_spread_samples() {
+        let filter = TentFilter { radius: 1.5 };
+        let mut film = Film::new(4, 4);
+        film.add_sample(
+            &filter,
+            &na::Point2::new(0.5, 0.5),
+            &Color::new(1.0, 1.0, 1.0),
+        );
+        assert_eq!(film.pixel(1, 1), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(film.pixel(2, 0), Color::zeros());
+    }
+
+    #[test]
+    fn merged_regions_match_whole_film() {
+        let filter = GaussianFilter::default();
+        let samples: Vec<(na::Point2<f32>, Color)> = (0..50)
+            .map(|i| {
+                let t = i as f32;
+                (
+                    na::Point2::new((t * 0.37) % 6.0, (t * 0.71) % 4.0),
+                    Color::new(t / 50.0, 1.0 - t / 50.0, 0.5),
+                )
+            })
+            .collect();
+
+        let mut whole = Film::new(6, 4);
+        for (point, radiance) in &samples {
+            whole.add_sample(&filter, point, radiance);
+        }
+
+        let mut merged = Film::new(6, 4);
+        for &(x0, width) in [(0, 3), (3, 3)].iter() {
+            let mut region = Film::with_bounds(x0, 0, width, 4);
+            for (point, radiance) in &samples {
+                region.add_sample(&filter, point, radiance);
+            }
+            merged.merge(&region);
+        }
+        for y in 0..4 {
+            for x in 0..6 {
+                assert!(relative_eq!(
+                    merged.pixel(x, y),
+                    whole.pixel(x, y),
+                    epsilon = 1.0e-6
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_is_kept_unclamped() {
+        let filter = BoxFilter::default();
+        let mut film = Film::new(2, 1);
+        film.add_sample(
+            &filter,
+            &na::Point2::new(0.5, 0.5),
+            &Color::new(4.0, 0.5, 0.0),
+        );
+        film.add_sample(
+            &filter,
+            &na::Point2::new(1.5, 0.5),
+            &Color::new(0.2, 0.4, 0.6),
+        );
+        assert_eq!(film.pixel(0, 0), Color::new(4.0, 0.5, 0.0));
+        assert_eq!(*film.to_hdr().get_pixel(0, 0), image::Rgb([4.0, 0.5, 0.0]));
+
+        let linear = ToneMapping {
+            transfer: Transfer::Linear,
+            ..Default::default()
+        };
+        assert_eq!(
+            *film.tone_map(&linear).get_pixel(0, 0),
+            image::Rgb([255, 128, 0])
+        );
+        // A filmic curve keeps the highlight below white
+        let filmic = ToneMapping {
+            operator: Box::new(AcesFilmic),
+            ..Default::default()
+        };
+        assert!(film.tone_map(&filmic).get_pixel(0, 0).0[0] < 255);
+        // sRGB encoding brightens mid tones
+        assert!(film.tone_map(&ToneMapping::default()).get_pixel(1, 0).0[1] > 102);
+    }
+}
@@ -0,0 +1,207 @@
+extern crate nalgebra as na;
+
+/// Weights the samples around a pixel when reconstructing the image.
+///
+/// Filters are separable, the weight of a sample is the product of the 1D
+/// weights of its horizontal and vertical offset from the pixel centre.
+pub trait Filter: Send + Sync {
+    /// Distance in pixels beyond which samples have no weight.
+    fn radius(&self) -> f32;
+
+    /// Weight of a sample `x` pixels from the centre along one axis.
+    fn weight(&self, x: f32) -> f32;
+
+    /// Weight of a sample at `offset` from the pixel centre.
+    fn evaluate(&self, offset: &na::Vector2<f32>) -> f32 {
+        self.weight(offset.x) * self.weight(offset.y)
+    }
+}
+
+/// Averages the samples inside each pixel with equal weight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxFilter {
+    pub radius: f32,
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        BoxFilter { radius: 0.5 }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        if x.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Weights fall off linearly from the pixel centre.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TentFilter {
+    pub radius: f32,
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        TentFilter { radius: 1.0 }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+/// A Gaussian shifted down to reach zero at the radius. Larger `alpha`
+/// makes it narrower.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaussianFilter {
+    pub radius: f32,
+    pub alpha: f32,
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        GaussianFilter {
+            radius: 1.5,
+            alpha: 2.0,
+        }
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let gaussian = |x: f32| (-self.alpha * x * x).exp();
+        (gaussian(x) - gaussian(self.radius)).max(0.0)
+    }
+}
+
+/// Mitchell and Netravali's cubic filter. Its negative lobes sharpen edges,
+/// `b` and `c` trade blurring against ringing, and b + 2c = 1 is recommended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MitchellFilter {
+    pub radius: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        MitchellFilter {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        // The cubic is defined on [-2, 2]
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        let weight = if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            (-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b)
+        };
+        weight / 6.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    /// Integral of the 1D weight over the filter's support.
+    fn integral(filter: &dyn Filter) -> f32 {
+        let steps = 10000;
+        let dx = 2.0 * filter.radius() / steps as f32;
+        (0..steps)
+            .map(|i| filter.weight(-filter.radius() + (i as f32 + 0.5) * dx) * dx)
+            .sum()
+    }
+
+    #[test]
+    fn weights_vanish_beyond_radius() {
+        let filters: [&dyn Filter; 4] = [
+            &BoxFilter::default(),
+            &TentFilter::default(),
+            &GaussianFilter::default(),
+            &MitchellFilter::default(),
+        ];
+        for filter in filters.iter() {
+            let radius = filter.radius();
+            assert!(filter.weight(0.0) > 0.0);
+            assert_eq!(filter.weight(radius * 1.01), 0.0);
+            assert_eq!(filter.weight(-radius * 1.01), 0.0);
+            assert!(relative_eq!(filter.weight(0.3), filter.weight(-0.3)));
+            assert!(relative_eq!(
+                filter.evaluate(&na::Vector2::new(0.3, -0.2)),
+                filter.weight(0.3) * filter.weight(0.2)
+            ));
+        }
+    }
+
+    #[test]
+    fn filter_shapes() {
+        assert!(relative_eq!(
+            integral(&BoxFilter::default()),
+            1.0,
+            epsilon = 1.0e-3
+        ));
+        assert!(relative_eq!(
+            integral(&TentFilter::default()),
+            1.0,
+            epsilon = 1.0e-3
+        ));
+        assert!(relative_eq!(TentFilter::default().weight(0.5), 0.5));
+        assert!(GaussianFilter::default().weight(1.5).abs() < 1.0e-6);
+
+        // The Mitchell filter is normalized and dips below zero in the outer
+        // half of its support
+        let mitchell = MitchellFilter::default();
+        assert!(relative_eq!(integral(&mitchell), 1.0, epsilon = 1.0e-3));
+        assert!(relative_eq!(
+            mitchell.weight(0.0),
+            8.0 / 9.0,
+            epsilon = 1.0e-6
+        ));
+        assert!(mitchell.weight(1.5) < 0.0);
+        assert!(relative_eq!(
+            mitchell.weight(1.0),
+            1.0 / 18.0,
+            epsilon = 1.0e-6
+        ));
+    }
+}
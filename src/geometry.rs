@@ -1,7 +1,9 @@
 extern crate nalgebra as na;
 
 use std::fmt;
+use std::sync::Arc;
 
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: na::Point3<f32>,
     pub direction: na::Vector3<f32>,
@@ -11,6 +13,9 @@ pub struct RayHit {
     pub near: na::Point3<f32>,
     pub far: na::Point3<f32>,
     pub normal: na::Vector3<f32>,
+    /// Name of the scene entry that was hit, filled in by `Scene::ray_cast`.
+    /// Shared with the scene's BVH so that tagging a hit does not allocate.
+    pub shape: Option<Arc<str>>,
 }
 
 impl fmt::Display for RayHit {
@@ -34,3 +39,234 @@ impl fmt::Display for Ray {
         )
     }
 }
+
+/// Axis-aligned bounding box in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: na::Point3<f32>, max: na::Point3<f32>) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Box containing all of space, used for shapes such as planes that have
+    /// no finite extent.
+    pub fn infinite() -> Self {
+        Aabb {
+            min: na::Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: na::Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+
+    /// Box containing nothing; the identity element for `union`.
+    pub fn empty() -> Self {
+        Aabb {
+            min: na::Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: na::Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<'a, I>(points: I) -> Self
+    where
+        I: IntoIterator<Item = &'a na::Point3<f32>>,
+    {
+        points
+            .into_iter()
+            .fold(Aabb::empty(), |bounds, point| bounds.grow(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// True if the box is non-empty and bounded along every axis. Only finite
+    /// boxes have a meaningful centroid and surface area.
+    pub fn is_finite(&self) -> bool {
+        !self.is_empty()
+            && self.min.coords.iter().all(|v| v.is_finite())
+            && self.max.coords.iter().all(|v| v.is_finite())
+    }
+
+    pub fn contains(&self, point: &na::Point3<f32>) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    /// Bounds of this box after being moved by `tf`.
+    pub fn transform(&self, tf: &na::Isometry3<f32>) -> Aabb {
+        if !self.is_finite() {
+            return *self;
+        }
+        let corners = (0..8).map(|idx| {
+            na::Point3::new(
+                if idx & 1 == 0 { self.min.x } else { self.max.x },
+                if idx & 2 == 0 { self.min.y } else { self.max.y },
+                if idx & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        });
+        corners.fold(Aabb::empty(), |bounds, corner| bounds.grow(&(tf * corner)))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: na::Point3::from(self.min.coords.zip_map(&other.min.coords, f32::min)),
+            max: na::Point3::from(self.max.coords.zip_map(&other.max.coords, f32::max)),
+        }
+    }
+
+    pub fn grow(&self, point: &na::Point3<f32>) -> Aabb {
+        Aabb {
+            min: na::Point3::from(self.min.coords.zip_map(&point.coords, f32::min)),
+            max: na::Point3::from(self.max.coords.zip_map(&point.coords, f32::max)),
+        }
+    }
+
+    pub fn diagonal(&self) -> na::Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> na::Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.diagonal();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Index of the axis along which the box is longest.
+    pub fn max_extent_axis(&self) -> usize {
+        self.diagonal().imax()
+    }
+
+    /// Slab test, returning the parametric entry and exit distances of `ray`
+    /// (clipped to start at the ray origin) if it passes through the box.
+    pub fn ray_intersect(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let mut t_near = 0.0_f32;
+        let mut t_far = f32::INFINITY;
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (ray in the slab plane with a zero direction) keeps the old bound
+            if t0 > t_near {
+                t_near = t0;
+            }
+            if t1 < t_far {
+                t_far = t1;
+            }
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some((t_near, t_far))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn make_box(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb::new(
+            na::Point3::new(min[0], min[1], min[2]),
+            na::Point3::new(max[0], max[1], max[2]),
+        )
+    }
+
+    fn make_ray(ray_origin: [f32; 3], ray_direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: na::Point3::new(ray_origin[0], ray_origin[1], ray_origin[2]),
+            direction: na::Vector3::new(ray_direction[0], ray_direction[1], ray_direction[2]),
+        }
+    }
+
+    #[test]
+    fn union_and_grow() {
+        let lhs = make_box([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+        let rhs = make_box([-1.0, 0.5, 0.5], [0.5, 2.0, 0.75]);
+        assert_eq!(lhs.union(&rhs), make_box([-1.0, 0.0, 0.0], [1.0, 2.0, 1.0]));
+        assert_eq!(Aabb::empty().union(&lhs), lhs);
+        assert_eq!(
+            lhs.grow(&na::Point3::new(3.0, -1.0, 0.5)),
+            make_box([0.0, -1.0, 0.0], [3.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn surface_area_and_centroid() {
+        let bounds = make_box([-1.0, 0.0, 2.0], [1.0, 1.0, 5.0]);
+        assert_eq!(
+            bounds.surface_area(),
+            2.0 * (2.0 * 1.0 + 1.0 * 3.0 + 3.0 * 2.0)
+        );
+        assert_eq!(bounds.centroid(), na::Point3::new(0.0, 0.5, 3.5));
+        assert_eq!(bounds.max_extent_axis(), 2);
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+
+    #[test]
+    fn finite_and_infinite() {
+        assert!(make_box([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]).is_finite());
+        assert!(!Aabb::empty().is_finite());
+        assert!(!Aabb::infinite().is_finite());
+        assert!(Aabb::infinite().contains(&na::Point3::new(1.0e30, -1.0e30, 0.0)));
+    }
+
+    #[test]
+    fn slab_intersection() {
+        let bounds = make_box([-1.0, -1.0, 4.0], [1.0, 1.0, 6.0]);
+
+        let (t_near, t_far) = bounds
+            .ray_intersect(&make_ray([0.0, 0.0, 0.0], [0.0, 0.0, 1.0]))
+            .unwrap();
+        assert_eq!((t_near, t_far), (4.0, 6.0));
+
+        // Origin inside the box is clipped to the ray origin
+        let (t_near, t_far) = bounds
+            .ray_intersect(&make_ray([0.0, 0.0, 5.0], [0.0, 0.0, -2.0]))
+            .unwrap();
+        assert_eq!((t_near, t_far), (0.0, 0.5));
+
+        assert!(bounds
+            .ray_intersect(&make_ray([2.0, 0.0, 0.0], [0.0, 0.0, 1.0]))
+            .is_none());
+        assert!(bounds
+            .ray_intersect(&make_ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]))
+            .is_none());
+        assert!(Aabb::infinite()
+            .ray_intersect(&make_ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]))
+            .is_some());
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let bounds = make_box([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+        let tf = na::Isometry3::new(
+            na::Vector3::new(0.0, 0.0, 10.0),
+            na::Vector3::z() * std::f32::consts::FRAC_PI_4,
+        );
+        let moved = bounds.transform(&tf);
+        let half_diagonal = 2.0_f32.sqrt();
+        assert!(relative_eq!(
+            moved.min,
+            na::Point3::new(-half_diagonal, -half_diagonal, 9.0),
+            epsilon = 1.0e-5
+        ));
+        assert!(relative_eq!(
+            moved.max,
+            na::Point3::new(half_diagonal, half_diagonal, 11.0),
+            epsilon = 1.0e-5
+        ));
+    }
+}
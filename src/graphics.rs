@@ -3,57 +3,41 @@ extern crate log;
 extern crate nalgebra as na;
 extern crate simple_logging;
 
-use crate::geometry;
+use crate::camera::Camera;
+use crate::point_cloud::PointCloud;
 
 use log::{error, info};
 use std::mem::swap;
+use std::path::Path;
 
-#[derive(Debug)]
+/// An image together with the camera it is seen through.
 pub struct GraphicsContext {
-    pub tf_root: na::Isometry3<f32>,
-    pub projection: na::Perspective3<f32>,
-    //pub projection: na::Orthographic3<f32>,
+    pub camera: Box<dyn Camera>,
     pub img_width: u32,
     pub img_height: u32,
     pub imgbuf: image::RgbImage,
 }
 
 impl GraphicsContext {
-    pub fn unproject_point(&self, p: na::Point2<u32>) -> geometry::Ray {
-        // Normalize pixel range from [0, width] t0 [-1, 1]
-        let norm_px_x = (p.x as i64 - (self.img_width as i64 / 2)) as f32
-            / (self.img_width as f32 / 2.0) as f32;
-        let norm_px_y = (p.y as i64 - (self.img_height as i64 / 2)) as f32
-            / (self.img_height as f32 / 2.0) as f32;
-
-        // Compute two points in clip-space.
-        // "ndc" = normalized device coordinates.
-        let near_ndc_point = na::Point3::new(norm_px_x, norm_px_y, -1.0);
-        let far_ndc_point = na::Point3::new(norm_px_x, norm_px_y, 1.0);
-
-        // Unproject them to view-space.
-        let near_view_point = self.projection.unproject_point(&near_ndc_point);
-        let far_view_point = self.projection.unproject_point(&far_ndc_point);
-
-        // Compute the view-space line parameters.
-        let line_direction = far_view_point - near_view_point;
-
-        geometry::Ray {
-            origin: na::Point3::new(0.0, 0.0, 0.0),
-            direction: line_direction.normalize(),
+    /// Context with a blank image of the camera's resolution.
+    pub fn new(camera: Box<dyn Camera>) -> Self {
+        let (img_width, img_height) = camera.resolution();
+        GraphicsContext {
+            camera,
+            img_width,
+            img_height,
+            imgbuf: image::RgbImage::new(img_width, img_height),
         }
     }
 
-    pub fn project_point(&self, point: &na::Point3<f32>) -> na::Point2<i64> {
-        // Project 3D point into Normalized Device Coordinates (-1, 1)
-        let ndc_pt = self.projection.project_point(&point);
-        // Transform to image space (0, 1).
-        let img_pt = (ndc_pt + na::Vector3::new(1.0, 1.0, 1.0)) / 2.0;
-        // Transform (0, 1) to (0, img_width) and (0, img_height)
-        let px_x = (img_pt[0] * (self.img_width as f32)).round() as i64;
-        let px_y = (img_pt[1] * (self.img_height as f32)).round() as i64;
-
-        na::Point2::new(px_x, px_y)
+    /// Pixel the world space `point` is seen in, or `None` if it is behind
+    /// the camera. The pixel may lie outside the image.
+    pub fn project_point(&self, point: &na::Point3<f32>) -> Option<na::Point2<i64>> {
+        let film = self.camera.project_point(point)?;
+        Some(na::Point2::new(
+            film.x.floor() as i64,
+            film.y.floor() as i64,
+        ))
     }
 
     pub fn put_pixel(&mut self, x: i64, y: i64, color: image::Rgb<u8>) {
@@ -63,12 +47,19 @@ impl GraphicsContext {
     }
 
     pub fn put_pixel_unchecked(&mut self, x: i64, y: i64, color: image::Rgb<u8>) {
-        // NOTE: Invert the Y axis because we're not savages
-        self.imgbuf.put_pixel(x as u32, self.img_height - y as u32, color);
+        self.imgbuf.put_pixel(x as u32, y as u32, color);
+    }
+
+    pub fn save(&self, image_file: &str) -> image::ImageResult<()> {
+        self.imgbuf.save(image_file)
     }
 
-    pub fn save(&self, image_file: &str) {
-        &self.imgbuf.save(image_file);
+    pub fn save_with_format(
+        &self,
+        image_file: &Path,
+        format: image::ImageFormat,
+    ) -> image::ImageResult<()> {
+        self.imgbuf.save_with_format(image_file, format)
     }
 }
 
@@ -78,11 +69,16 @@ pub fn draw_line(
     color: image::Rgb<u8>,
     context: &mut GraphicsContext,
 ) {
-    let p0_px = context.project_point(&p0);
-    let p1_px = context.project_point(&p1);
+    let (p0_px, p1_px) = match (context.project_point(p0), context.project_point(p1)) {
+        (Some(p0_px), Some(p1_px)) => (p0_px, p1_px),
+        _ => {
+            info!("Line falls behind the camera:\n  {}\n  {}", p0, p1);
+            return;
+        }
+    };
 
-    let img_x_axis = 0 as i64..context.img_width as i64;
-    let img_y_axis = 0 as i64..context.img_height as i64;
+    let img_x_axis = 0_i64..context.img_width as i64;
+    let img_y_axis = 0_i64..context.img_height as i64;
 
     if !(img_x_axis.contains(&p0_px[0]) && img_y_axis.contains(&p0_px[1])) {
         info!(
@@ -129,31 +125,50 @@ pub fn draw_line(
     }
 }
 
-pub fn draw_axes(tf: &na::Isometry3<f32>, size: f32, mut context: &mut GraphicsContext) {
+pub fn draw_axes(tf: &na::Isometry3<f32>, size: f32, context: &mut GraphicsContext) {
     let r = image::Rgb([255, 0, 0]);
     let g = image::Rgb([0, 255, 0]);
     let b = image::Rgb([0, 0, 255]);
     let unit_x_w = tf * na::Point3::new(size, 0.0, 0.0);
     let unit_y_w = tf * na::Point3::new(0.0, size, 0.0);
     let unit_z_w = tf * na::Point3::new(0.0, 0.0, size);
-    draw_line(
-        &(tf * na::Point3::<f32>::origin()),
-        &unit_x_w,
-        r,
-        &mut context,
-    );
-    draw_line(
-        &(tf * na::Point3::<f32>::origin()),
-        &unit_y_w,
-        g,
-        &mut context,
-    );
-    draw_line(
-        &(tf * na::Point3::<f32>::origin()),
-        &unit_z_w,
-        b,
-        &mut context,
-    );
+    draw_line(&(tf * na::Point3::<f32>::origin()), &unit_x_w, r, context);
+    draw_line(&(tf * na::Point3::<f32>::origin()), &unit_y_w, g, context);
+    draw_line(&(tf * na::Point3::<f32>::origin()), &unit_z_w, b, context);
+}
+
+/// Draws every point of `cloud`, whose frame has `pose` in the world, in its
+/// own colour or in `color` if it has none, e.g. to check that an exported
+/// cloud lines up with the image. Returns the number of points drawn.
+pub fn draw_point_cloud(
+    cloud: &PointCloud,
+    pose: &na::Isometry3<f32>,
+    color: image::Rgb<u8>,
+    context: &mut GraphicsContext,
+) -> usize {
+    let mut drawn = 0;
+    for (idx, point) in cloud.positions.iter().enumerate() {
+        if !point.coords.iter().all(|c| c.is_finite()) {
+            continue;
+        }
+        let px = match context.project_point(&(pose * point)) {
+            Some(px) => px,
+            None => continue,
+        };
+        if px.x >= 0
+            && px.x < context.img_width as i64
+            && px.y >= 0
+            && px.y < context.img_height as i64
+        {
+            let color = cloud
+                .colors
+                .as_ref()
+                .map_or(color, |colors| image::Rgb(colors[idx]));
+            context.put_pixel_unchecked(px.x, px.y, color);
+            drawn += 1;
+        }
+    }
+    drawn
 }
 
 pub fn _draw_circle(tf: &na::Isometry3<f32>, radius: f32, context: &mut GraphicsContext) {
@@ -162,13 +177,19 @@ pub fn _draw_circle(tf: &na::Isometry3<f32>, radius: f32, context: &mut Graphics
         let angle = 2.0 * ::std::f32::consts::PI * (idx as f32 / circle_pt_count as f32);
         let world_pt_d = na::Point3::new(radius * angle.cos(), radius * angle.sin(), 0.0);
         let world_pt = tf * world_pt_d;
-        let px = context.project_point(&world_pt);
+        let px = match context.project_point(&world_pt) {
+            Some(px) => px,
+            None => {
+                error!("Point behind camera! {}", world_pt);
+                continue;
+            }
+        };
         if 0 < px[0]
             && px[0] < context.img_width as i64
             && 0 < px[1]
             && px[1] < context.img_height as i64
         {
-            context.put_pixel(px[0] as i64, px[1] as i64, image::Rgb([255, 255, 255]));
+            context.put_pixel(px[0], px[1], image::Rgb([255, 255, 255]));
         } else {
             error!("Pixels outside image! {}, {}", px[0], px[1]);
         }
@@ -179,7 +200,7 @@ pub fn _draw_cube(
     tf: &na::Isometry3<f32>,
     size: f32,
     color: image::Rgb<u8>,
-    mut context: &mut GraphicsContext,
+    context: &mut GraphicsContext,
 ) {
     let dp = size / 2.0;
     let dn = size / -2.0;
@@ -194,57 +215,91 @@ pub fn _draw_cube(
         tf * na::Point3::<f32>::new(dp, dn, dn), // 7
     ];
     // Top
-    draw_line(&corners[0], &corners[1], color, &mut context);
-    draw_line(&corners[1], &corners[2], color, &mut context);
-    draw_line(&corners[2], &corners[3], color, &mut context);
-    draw_line(&corners[3], &corners[0], color, &mut context);
+    draw_line(&corners[0], &corners[1], color, context);
+    draw_line(&corners[1], &corners[2], color, context);
+    draw_line(&corners[2], &corners[3], color, context);
+    draw_line(&corners[3], &corners[0], color, context);
     // Bottom
-    draw_line(&corners[4], &corners[5], color, &mut context);
-    draw_line(&corners[5], &corners[6], color, &mut context);
-    draw_line(&corners[6], &corners[7], color, &mut context);
-    draw_line(&corners[7], &corners[4], color, &mut context);
+    draw_line(&corners[4], &corners[5], color, context);
+    draw_line(&corners[5], &corners[6], color, context);
+    draw_line(&corners[6], &corners[7], color, context);
+    draw_line(&corners[7], &corners[4], color, context);
     // Sides
-    draw_line(&corners[0], &corners[4], color, &mut context);
-    draw_line(&corners[1], &corners[5], color, &mut context);
-    draw_line(&corners[2], &corners[6], color, &mut context);
-    draw_line(&corners[3], &corners[7], color, &mut context);
+    draw_line(&corners[0], &corners[4], color, context);
+    draw_line(&corners[1], &corners[5], color, context);
+    draw_line(&corners[2], &corners[6], color, context);
+    draw_line(&corners[3], &corners[7], color, context);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::camera::{OrthographicCamera, PerspectiveCamera};
+
     fn make_context() -> GraphicsContext {
         simple_logging::log_to_stderr(log::LevelFilter::Trace);
-        let img_width = 1000;
-        let img_height = 1000;
-        let aspect = img_width as f32 / img_height as f32;
-        //let proj = na::Perspective3::new(aspect, 3.14 / 2.0, 1.0, 11.0);
-        let size = 3.0;
-        let proj = na::Orthographic3::new(-size, size, -size, size, -size, size);
-        GraphicsContext {
-            tf_root: na::Isometry3::<f32>::identity(),
-            projection: proj,
-            img_width: img_width,
-            img_height: img_height,
-            imgbuf: image::RgbImage::new(img_width, img_height),
-        }
+        GraphicsContext::new(Box::new(PerspectiveCamera {
+            pose: na::Isometry3::translation(1.0, 0.0, 0.0),
+            fov: std::f32::consts::FRAC_PI_2,
+            width: 1000,
+            height: 1000,
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+        }))
     }
 
     #[test]
     fn project_and_unproject() {
         let ctx = make_context();
         info!("Hello, world");
-        let point2 = na::Point2::<u32>::new(0, ctx.img_height / 2);
-        //let point2 = na::Point2::<u32>::new(0, 0);
-        let ray = ctx.unproject_point(point2);
-        let distance = 0.0;
+        let point2 = na::Point2::<i64>::new(0, ctx.img_height as i64 / 2);
+        let film = na::Point2::new(point2.x as f32 + 0.5, point2.y as f32 + 0.5);
+        let ray = ctx.camera.generate_ray(&film, &na::Point2::new(0.5, 0.5));
+        let distance = 5.0;
         let point3 = ray.origin + (ray.direction * distance);
-        let reproj = ctx.project_point(&point3);
-        let reproj = na::Point2::<u32>::new(reproj[0] as u32, reproj[1] as u32);
+        let reproj = ctx.project_point(&point3).unwrap();
         info!("Ray:     {}", ray);
         info!("Point 3: {}", point3);
         info!("Reproj:  {}", reproj);
         assert_eq!(point2, reproj);
     }
+
+    #[test]
+    fn lines_behind_camera_are_skipped() {
+        let mut ctx = make_context();
+        let white = image::Rgb([255, 255, 255]);
+        draw_line(
+            &na::Point3::new(1.0, 0.0, 1.0),
+            &na::Point3::new(1.0, 0.0, -1.0),
+            white,
+            &mut ctx,
+        );
+        assert!(ctx.imgbuf.pixels().all(|p| *p == image::Rgb([0, 0, 0])));
+
+        draw_line(
+            &na::Point3::new(1.0, 0.0, 1.0),
+            &na::Point3::new(1.5, 0.0, 1.0),
+            white,
+            &mut ctx,
+        );
+        assert_eq!(*ctx.imgbuf.get_pixel(600, 500), white);
+    }
+
+    #[test]
+    fn orthographic_overlay() {
+        let mut ctx = GraphicsContext::new(Box::new(OrthographicCamera {
+            pose: na::Isometry3::identity(),
+            view_height: 2.0,
+            width: 100,
+            height: 100,
+        }));
+        // Lines are drawn at the same size at any distance
+        for z in [1.0, 50.0].iter() {
+            draw_axes(&na::Isometry3::translation(0.0, 0.0, *z), 0.5, &mut ctx);
+        }
+        assert_eq!(*ctx.imgbuf.get_pixel(74, 50), image::Rgb([255, 0, 0]));
+        assert_eq!(*ctx.imgbuf.get_pixel(75, 50), image::Rgb([0, 0, 0]));
+        assert_eq!(*ctx.imgbuf.get_pixel(50, 74), image::Rgb([0, 255, 0]));
+    }
 }
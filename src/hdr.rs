@@ -0,0 +1,425 @@
+extern crate image;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
+
+/// An image of linear, unclamped RGB radiance.
+pub type HdrImage = image::ImageBuffer<image::Rgb<f32>, Vec<f32>>;
+
+/// File formats that keep floating point values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HdrFormat {
+    /// OpenEXR, storing values as 16-bit half or 32-bit floats.
+    Exr(ExrPrecision),
+    /// Radiance RGBE, with an 8-bit mantissa per channel and a shared
+    /// exponent.
+    Radiance,
+    /// Portable float map, uncompressed 32-bit floats.
+    Pfm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+/// A named plane of values, one per pixel in row-major order from the top
+/// left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+impl Channel {
+    pub fn new(name: &str, values: Vec<f32>) -> Self {
+        Channel {
+            name: name.to_string(),
+            values,
+        }
+    }
+}
+
+/// The R, G and B channels of `image`, under `prefix` if not empty, e.g.
+/// `normal.R`.
+pub fn rgb_channels(image: &HdrImage, prefix: &str) -> Vec<Channel> {
+    ["R", "G", "B"]
+        .iter()
+        .enumerate()
+        .map(|(c, name)| {
+            let name = if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", prefix, name)
+            };
+            Channel {
+                name,
+                values: image.pixels().map(|p| p.0[c]).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Writes `image` to `path` in `format`.
+pub fn save(image: &HdrImage, path: &Path, format: HdrFormat) -> image::ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        HdrFormat::Exr(precision) => write_exr(
+            &mut writer,
+            image.width(),
+            image.height(),
+            &rgb_channels(image, ""),
+            precision,
+        )?,
+        HdrFormat::Radiance => write_radiance(&mut writer, image)?,
+        HdrFormat::Pfm => write_pfm(&mut writer, image)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `channels` of a `width` by `height` image to an uncompressed
+/// scanline OpenEXR file. Every channel needs a value per pixel.
+pub fn write_exr<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    channels: &[Channel],
+    precision: ExrPrecision,
+) -> image::ImageResult<()> {
+    let pixel_count = width as usize * height as usize;
+    if channels.iter().any(|c| c.values.len() != pixel_count) {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+    // Readers expect the channels sorted by name
+    let mut channels: Vec<&Channel> = channels.iter().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    let (pixel_type, sample_size) = match precision {
+        ExrPrecision::Half => (1_i32, 2),
+        ExrPrecision::Float => (2_i32, 4),
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20_000_630_i32.to_le_bytes());
+    // Version 2, single part scanline image
+    header.extend_from_slice(&2_i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+        // Not perceptually linear, three reserved bytes, no subsampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1_i32.to_le_bytes());
+        channel_list.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    attribute("channels", "chlist", &channel_list);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // Offsets of the scanlines, each stored as its own chunk
+    let line_size = width as usize * channels.len() * sample_size;
+    let chunk_size = 8 + line_size;
+    let first_chunk = header.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        writer.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(chunk_size);
+    for y in 0..height as usize {
+        line.clear();
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in &channels {
+            let row = &channel.values[y * width as usize..(y + 1) * width as usize];
+            for &value in row {
+                match precision {
+                    ExrPrecision::Half => line.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    ExrPrecision::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Rounds `value` to the nearest 16-bit half float, saturating to infinity.
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // Keep the top ten bits of the mantissa, rounding half to even. A carry
+    // out of the mantissa correctly bumps the exponent.
+    let round = |value: u32, shift: u32| {
+        let kept = value >> shift;
+        let rest = value & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        kept + (rest > halfway || (rest == halfway && kept & 1 == 1)) as u32
+    };
+    if exponent <= 0 {
+        // Subnormal, or zero when even rounding cannot reach the smallest
+        if exponent < -10 {
+            return sign;
+        }
+        return sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16;
+    }
+    sign | round((exponent as u32) << 23 | mantissa, 13) as u16
+}
+
+/// Writes `image` as Radiance RGBE.
+pub fn write_radiance<W: Write>(writer: &mut W, image: &HdrImage) -> image::ImageResult<()> {
+    let pixels: Vec<image::Rgb<f32>> = image.pixels().cloned().collect();
+    image::hdr::HdrEncoder::new(writer).encode(
+        &pixels,
+        image.width() as usize,
+        image.height() as usize,
+    )
+}
+
+/// Writes `image` as a little-endian portable float map.
+pub fn write_pfm<W: Write>(writer: &mut W, image: &HdrImage) -> image::ImageResult<()> {
+    // A negative scale marks little-endian data
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    // Rows go from the bottom up
+    let mut row = Vec::with_capacity(image.width() as usize * 12);
+    for y in (0..image.height()).rev() {
+        row.clear();
+        for x in 0..image.width() {
+            for value in &image.get_pixel(x, y).0 {
+                row.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+
+    fn make_image() -> HdrImage {
+        HdrImage::from_fn(5, 3, |x, y| {
+            image::Rgb([
+                x as f32 * 0.75,
+                y as f32 * 20.0,
+                1.0 / (1.0 + (x * y) as f32),
+            ])
+        })
+    }
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_cstr(bytes: &[u8], at: &mut usize) -> String {
+        let end = *at + bytes[*at..].iter().position(|&b| b == 0).unwrap();
+        let value = String::from_utf8(bytes[*at..end].to_vec()).unwrap();
+        *at = end + 1;
+        value
+    }
+
+    /// Channel names, pixel types and float values of an uncompressed EXR.
+    fn read_exr(bytes: &[u8]) -> Vec<(String, i32, Vec<f32>)> {
+        assert_eq!(read_i32(bytes, 0), 20_000_630);
+        assert_eq!(read_i32(bytes, 4), 2);
+        let mut at = 8;
+        let mut channels = Vec::new();
+        let mut window = [0; 4];
+        loop {
+            let name = read_cstr(bytes, &mut at);
+            if name.is_empty() {
+                break;
+            }
+            let kind = read_cstr(bytes, &mut at);
+            let size = read_i32(bytes, at) as usize;
+            let value = &bytes[at + 4..at + 4 + size];
+            match kind.as_str() {
+                "chlist" => {
+                    let mut c = 0;
+                    while value[c] != 0 {
+                        let name = read_cstr(value, &mut c);
+                        channels.push((name, read_i32(value, c), Vec::new()));
+                        c += 16;
+                    }
+                }
+                "box2i" if name == "dataWindow" => {
+                    for (i, v) in window.iter_mut().enumerate() {
+                        *v = read_i32(value, 4 * i);
+                    }
+                }
+                "compression" => assert_eq!(value, &[0]),
+                _ => {}
+            }
+            at += 4 + size;
+        }
+        let (width, height) = (window[2] + 1, window[3] + 1);
+        for y in 0..height as usize {
+            let offset = u64::from_le_bytes(bytes[at + 8 * y..at + 8 * y + 8].try_into().unwrap());
+            let mut p = offset as usize;
+            assert_eq!(read_i32(bytes, p), y as i32);
+            p += 8;
+            for channel in channels.iter_mut() {
+                for _ in 0..width {
+                    if channel.1 == 1 {
+                        let half = u16::from_le_bytes(bytes[p..p + 2].try_into().unwrap());
+                        channel.2.push(half_to_f32(half));
+                        p += 2;
+                    } else {
+                        channel
+                            .2
+                            .push(f32::from_le_bytes(bytes[p..p + 4].try_into().unwrap()));
+                        p += 4;
+                    }
+                }
+            }
+        }
+        channels
+    }
+
+    fn half_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = i32::from((half >> 10) & 0x1f);
+        let mantissa = f32::from(half & 0x3ff);
+        match exponent {
+            0 => sign * mantissa * 2.0_f32.powi(-24),
+            0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1f => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2.0_f32.powi(exponent - 15),
+        }
+    }
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_half(0.0), 0);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(-2.0), 0xc000);
+        assert_eq!(f32_to_half(0.1), 0x2e66);
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+        assert_eq!(f32_to_half(1.0e6), 0x7c00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert!(half_to_f32(f32_to_half(f32::NAN)).is_nan());
+        assert_eq!(f32_to_half(2.0_f32.powi(-24)), 1);
+        assert_eq!(f32_to_half(2.0_f32.powi(-26)), 0);
+        // Halfway between 1 and the next half rounds to even
+        assert_eq!(f32_to_half(1.0 + 2.0_f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + 3.0 * 2.0_f32.powi(-11)), 0x3c02);
+        for i in 0..1000 {
+            let value = (i as f32 - 500.0) * 0.37;
+            let error = (half_to_f32(f32_to_half(value)) - value).abs();
+            assert!(error <= value.abs() * 2.0_f32.powi(-11));
+        }
+    }
+
+    #[test]
+    fn exr_channels() {
+        let image = make_image();
+        let mut channels = rgb_channels(&image, "");
+        channels.push(Channel::new(
+            "Z",
+            (0..15).map(|i| i as f32 * 100.0).collect(),
+        ));
+        channels.insert(0, Channel::new("A", vec![1.0; 15]));
+
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 5, 3, &channels, ExrPrecision::Float).unwrap();
+        let read = read_exr(&bytes);
+        let names: Vec<&str> = read.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(names, ["A", "B", "G", "R", "Z"]);
+        for (name, pixel_type, values) in &read {
+            assert_eq!(*pixel_type, 2);
+            let expected = &channels.iter().find(|c| &c.name == name).unwrap().values;
+            assert_eq!(values, expected);
+        }
+
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 5, 3, &channels, ExrPrecision::Half).unwrap();
+        for (name, pixel_type, values) in read_exr(&bytes) {
+            assert_eq!(pixel_type, 1);
+            let expected = &channels.iter().find(|c| c.name == name).unwrap().values;
+            for (value, expected) in values.iter().zip(expected) {
+                assert!((value - expected).abs() <= expected.abs() * 1.0e-3);
+            }
+        }
+
+        channels.push(Channel::new("short", vec![0.0; 14]));
+        assert!(write_exr(&mut Vec::new(), 5, 3, &channels, ExrPrecision::Float).is_err());
+    }
+
+    #[test]
+    fn pfm_rows_go_up() {
+        let image = make_image();
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, &image).unwrap();
+        let header = b"PF\n5 3\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 5 * 3 * 12);
+        let value = |x: usize, y: usize, c: usize| {
+            let at = header.len() + ((2 - y) * 5 + x) * 12 + c * 4;
+            f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+        };
+        assert_eq!(value(3, 0, 0), 2.25);
+        assert_eq!(value(4, 2, 1), 40.0);
+        assert_eq!(value(2, 1, 2), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn radiance_round_trip() {
+        let image = make_image();
+        let mut bytes = Vec::new();
+        write_radiance(&mut bytes, &image).unwrap();
+        let decoded = image::hdr::HdrDecoder::new(&bytes[..])
+            .unwrap()
+            .read_image_hdr()
+            .unwrap();
+        for (pixel, expected) in decoded.iter().zip(image.pixels()) {
+            // RGBE keeps about two significant digits of the brightest
+            // channel
+            let brightest = expected.0.iter().cloned().fold(0.0, f32::max);
+            for c in 0..3 {
+                assert!((pixel.0[c] - expected.0[c]).abs() <= brightest * 0.01);
+            }
+        }
+    }
+}
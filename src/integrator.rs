@@ -0,0 +1,545 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use crate::geometry::{Ray, RayHit};
+use crate::material::Color;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::scene::Scene;
+
+/// Secondary rays start this far off the surface they leave, so that they do
+/// not hit it again.
+const RAY_EPSILON: f32 = 1.0e-4;
+
+/// Computes the light arriving back along a camera ray.
+pub trait Integrator: Send + Sync {
+    fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color;
+}
+
+/// Shading of the first surface hit only, without any secondary rays besides
+/// shadow rays.
+pub struct DirectLighting;
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        match scene.ray_cast(ray) {
+            Some(hit) => scene.shade(ray, &hit, sampler),
+            None => scene.background,
+        }
+    }
+}
+
+/// Whitted-style recursive ray tracer. On top of direct lighting, follows
+/// perfect mirror reflections and refractions through dielectrics for up to
+/// `max_depth` bounces.
+pub struct Whitted {
+    pub max_depth: u32,
+}
+
+impl Default for Whitted {
+    fn default() -> Self {
+        Whitted { max_depth: 5 }
+    }
+}
+
+/// Mirrors `direction` about `normal`.
+pub fn reflect(direction: &na::Vector3<f32>, normal: &na::Vector3<f32>) -> na::Vector3<f32> {
+    direction - normal * (2.0 * direction.dot(normal))
+}
+
+/// Bends unit `direction` through a surface with unit `normal` facing against
+/// it, where `eta` is the ratio of the refractive indices on the incident and
+/// transmitted sides. Returns None on total internal reflection.
+pub fn refract(
+    direction: &na::Vector3<f32>,
+    normal: &na::Vector3<f32>,
+    eta: f32,
+) -> Option<na::Vector3<f32>> {
+    let cos_i = -direction.dot(normal);
+    let sin_t_squared = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin_t_squared >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin_t_squared).sqrt();
+    Some(direction * eta + normal * (eta * cos_i - cos_t))
+}
+
+/// Exact Fresnel reflectance of unpolarized light at a dielectric boundary,
+/// for incident angle cosine `cos_i` going from index `eta_i` to `eta_t`.
+pub fn fresnel_dielectric(cos_i: f32, eta_i: f32, eta_t: f32) -> f32 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin_t = eta_i / eta_t * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+    let parallel = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let perpendicular = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Ray leaving `hit` along `direction`, nudged off the surface on the side it
+/// travels towards.
+pub fn spawn_ray(hit: &RayHit, direction: na::Vector3<f32>) -> Ray {
+    let offset = if direction.dot(&hit.normal) < 0.0 {
+        -RAY_EPSILON
+    } else {
+        RAY_EPSILON
+    };
+    Ray {
+        origin: hit.near + hit.normal * offset,
+        direction,
+    }
+}
+
+impl Whitted {
+    fn trace(&self, scene: &Scene, ray: &Ray, depth: u32, sampler: &mut dyn Sampler) -> Color {
+        let hit = match scene.ray_cast(ray) {
+            Some(hit) => hit,
+            None => return scene.background,
+        };
+        let material = scene.material_of(&hit);
+        let transparency = material.transparency(&hit).clamp(0.0, 1.0);
+
+        let mut color = scene.shade(ray, &hit, sampler) * (1.0 - transparency);
+        if depth >= self.max_depth {
+            return color;
+        }
+
+        let direction = ray.direction.normalize();
+        let reflectivity = material.reflectivity(&hit);
+        if reflectivity != Color::zeros() {
+            let reflected = spawn_ray(&hit, reflect(&direction, &hit.normal));
+            color += reflectivity.component_mul(&self.trace(scene, &reflected, depth + 1, sampler));
+        }
+
+        if transparency > 0.0 {
+            // Normals face out of the shape, so a ray along the normal is
+            // leaving the medium
+            let ior = material.ior(&hit);
+            let cos_i = -direction.dot(&hit.normal);
+            let (normal, eta_i, eta_t) = if cos_i > 0.0 {
+                (hit.normal, 1.0, ior)
+            } else {
+                (-hit.normal, ior, 1.0)
+            };
+
+            let reflected = spawn_ray(&hit, reflect(&direction, &normal));
+            let transmitted = match refract(&direction, &normal, eta_i / eta_t) {
+                Some(refracted) => {
+                    let fresnel = fresnel_dielectric(cos_i, eta_i, eta_t);
+                    let refracted = spawn_ray(&hit, refracted);
+                    self.trace(scene, &reflected, depth + 1, sampler) * fresnel
+                        + self.trace(scene, &refracted, depth + 1, sampler) * (1.0 - fresnel)
+                }
+                // Total internal reflection
+                None => self.trace(scene, &reflected, depth + 1, sampler),
+            };
+            color += transmitted * transparency;
+        }
+        color
+    }
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        self.trace(scene, ray, 0, sampler)
+    }
+}
+
+/// Unidirectional Monte Carlo path tracer for global illumination.
+///
+/// Diffuse surfaces are Lambertian and sampled with a cosine-weighted
+/// hemisphere; at every diffuse bounce each light is also sampled directly,
+/// and the two strategies are combined with multiple importance sampling.
+/// Mirrors and dielectrics are followed as in `Whitted`. Phong highlights are
+/// not part of the model. Paths are terminated after `max_depth` bounces, or
+/// earlier by Russian roulette once they are `roulette_depth` bounces long.
+pub struct PathTracer {
+    pub max_depth: u32,
+    pub roulette_depth: u32,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer {
+            max_depth: 16,
+            roulette_depth: 3,
+        }
+    }
+}
+
+fn mean(color: &Color) -> f32 {
+    (color.x + color.y + color.z) / 3.0
+}
+
+impl PathTracer {
+    /// Light reaching `point` directly from every light in the scene, for a
+    /// diffuse surface with `reflectance` facing along `normal`. `bsdf_pdf`
+    /// gives the density with which the path itself would continue in a
+    /// direction, for weighting against hitting area lights by chance.
+    fn direct_lighting<F>(
+        scene: &Scene,
+        point: &na::Point3<f32>,
+        normal: &na::Vector3<f32>,
+        reflectance: &Color,
+        bsdf_pdf: F,
+        sampler: &mut dyn Sampler,
+    ) -> Color
+    where
+        F: Fn(f32) -> f32,
+    {
+        let mut color = Color::zeros();
+        for light in scene.lights.values() {
+            let u = sampler.get_2d();
+            let sample = match light.sample(point, &u) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => continue,
+            };
+            let cos_theta = normal.dot(&sample.direction);
+            if cos_theta <= 0.0 {
+                continue;
+            }
+            let shadow_ray = Ray {
+                origin: point + normal * RAY_EPSILON,
+                direction: sample.direction,
+            };
+            if scene.occluded(&shadow_ray, sample.distance) {
+                continue;
+            }
+
+            let weight = if light.is_delta() {
+                1.0
+            } else {
+                sampling::power_heuristic(sample.pdf, bsdf_pdf(cos_theta))
+            };
+            color += reflectance.component_mul(&sample.radiance)
+                * (cos_theta / PI * weight / sample.pdf);
+        }
+        color
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let mut color = Color::zeros();
+        let mut throughput = Color::repeat(1.0);
+        let mut ray = *ray;
+        // Density of the direction the path took at its last diffuse bounce,
+        // None when it was chosen deterministically
+        let mut last_pdf: Option<f32> = None;
+
+        for depth in 0.. {
+            let hit = scene.ray_cast(&ray);
+            let hit_distance = hit
+                .as_ref()
+                .map_or(f32::INFINITY, |hit| (hit.near - ray.origin).norm());
+
+            // Area lights in front of the surface, weighted against having
+            // been sampled directly at the previous bounce
+            for light in scene.lights.values() {
+                if let Some(light_hit) = light.ray_cast(&ray) {
+                    if light_hit.distance < hit_distance {
+                        let weight = last_pdf
+                            .map_or(1.0, |pdf| sampling::power_heuristic(pdf, light_hit.pdf));
+                        color += throughput.component_mul(&light_hit.radiance) * weight;
+                    }
+                }
+            }
+
+            let hit = match hit {
+                Some(hit) => hit,
+                None => {
+                    color += throughput.component_mul(&scene.background);
+                    break;
+                }
+            };
+            let material = scene.material_of(&hit);
+            color += throughput.component_mul(&material.emission(&hit));
+            if depth >= self.max_depth {
+                break;
+            }
+
+            let direction = ray.direction.normalize();
+            // Shade both sides of a surface alike
+            let facing = if hit.normal.dot(&direction) > 0.0 {
+                -hit.normal
+            } else {
+                hit.normal
+            };
+
+            // Choose one of the diffuse, mirror and transmission lobes
+            let transparency = material.transparency(&hit).clamp(0.0, 1.0);
+            let diffuse = material.diffuse(&hit) * (1.0 - transparency);
+            let reflectivity = material.reflectivity(&hit);
+            let weights = [mean(&diffuse), mean(&reflectivity), transparency];
+            let total: f32 = weights.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            let diffuse_probability = weights[0] / total;
+
+            if weights[0] > 0.0 {
+                let bsdf_pdf = |cos_theta: f32| {
+                    diffuse_probability * sampling::cosine_hemisphere_pdf(cos_theta)
+                };
+                let direct =
+                    Self::direct_lighting(scene, &hit.near, &facing, &diffuse, bsdf_pdf, sampler);
+                color += throughput.component_mul(&direct);
+            }
+
+            let choice = sampler.get_1d() * total;
+            let next_direction = if choice < weights[0] {
+                let u = sampler.get_2d();
+                let local = sampling::cosine_hemisphere(&u);
+                last_pdf = Some(diffuse_probability * sampling::cosine_hemisphere_pdf(local.z));
+                throughput = throughput.component_mul(&diffuse) / diffuse_probability;
+                sampling::coordinate_system(&facing) * local
+            } else if choice < weights[0] + weights[1] {
+                last_pdf = None;
+                throughput = throughput.component_mul(&reflectivity) * (total / weights[1]);
+                reflect(&direction, &facing)
+            } else {
+                last_pdf = None;
+                throughput *= transparency * total / weights[2];
+                let ior = material.ior(&hit);
+                let (eta_i, eta_t) = if hit.normal.dot(&direction) < 0.0 {
+                    (1.0, ior)
+                } else {
+                    (ior, 1.0)
+                };
+                let cos_i = -direction.dot(&facing);
+                let fresnel = fresnel_dielectric(cos_i, eta_i, eta_t);
+                match refract(&direction, &facing, eta_i / eta_t) {
+                    Some(refracted) if sampler.get_1d() >= fresnel => refracted,
+                    _ => reflect(&direction, &facing),
+                }
+            };
+            ray = spawn_ray(&hit, next_direction);
+
+            if depth + 1 >= self.roulette_depth {
+                let survival = throughput.max().min(0.95);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use approx::relative_eq;
+
+    use crate::material::Surface;
+    use crate::sampler::IndependentSampler;
+    use crate::shape;
+
+    fn radiance(integrator: &dyn Integrator, scene: &Scene, ray: &Ray) -> Color {
+        let mut sampler = IndependentSampler::new(0);
+        sampler.start_sample(&na::Point2::origin(), 0, 1);
+        integrator.radiance(scene, ray, &mut sampler)
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // Normal incidence from air into glass
+        assert!(relative_eq!(
+            fresnel_dielectric(1.0, 1.0, 1.5),
+            0.04,
+            epsilon = 1.0e-6
+        ));
+        // Grazing incidence reflects everything
+        assert!(relative_eq!(
+            fresnel_dielectric(0.0, 1.0, 1.5),
+            1.0,
+            epsilon = 1.0e-6
+        ));
+        // Past the critical angle inside glass
+        assert_eq!(fresnel_dielectric(0.5, 1.5, 1.0), 1.0);
+    }
+
+    #[test]
+    fn snells_law() {
+        let direction = na::Vector3::new(1.0, 0.0, -1.0).normalize();
+        let normal = na::Vector3::z();
+        let refracted = refract(&direction, &normal, 1.0 / 1.5).unwrap();
+        assert!(relative_eq!(refracted.norm(), 1.0, epsilon = 1.0e-6));
+        assert!(relative_eq!(
+            refracted.x * 1.5,
+            direction.x,
+            epsilon = 1.0e-6
+        ));
+
+        // Leaving glass at 45 degrees is past the critical angle
+        assert!(refract(&direction, &normal, 1.5).is_none());
+    }
+
+    #[test]
+    fn mirror_reflects_background() {
+        let mut scene = Scene::new();
+        scene.background = Color::new(0.2, 0.4, 0.6);
+        scene.add_shape(
+            "mirror",
+            Box::new(shape::Plane {
+                pose: na::Isometry3::identity(),
+            }),
+        );
+        scene.set_material(
+            "mirror",
+            Arc::new(Surface::mirror(Color::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray {
+            origin: na::Point3::new(-1.0, 0.0, 1.0),
+            direction: na::Vector3::new(1.0, 0.0, -1.0),
+        };
+
+        let color = radiance(&Whitted::default(), &scene, &ray);
+        assert!(relative_eq!(color, scene.background * 0.5));
+        let color = radiance(&Whitted { max_depth: 0 }, &scene, &ray);
+        assert_eq!(color, Color::zeros());
+    }
+
+    #[test]
+    fn glass_sphere_conserves_light() {
+        let mut scene = Scene::new();
+        scene.background = Color::new(1.0, 1.0, 1.0);
+        scene.add_shape(
+            "ball",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(0.0, 0.0, -5.0),
+                radius: 1.0,
+            }),
+        );
+        scene.set_material("ball", Arc::new(Surface::glass(1.5)));
+        let integrator = Whitted { max_depth: 8 };
+
+        // Every path through or off the ball ends in the background
+        for x in [0.0, 0.3, 0.6, 0.9].iter() {
+            let ray = Ray {
+                origin: na::Point3::new(*x, 0.0, 0.0),
+                direction: -na::Vector3::z(),
+            };
+            let color = radiance(&integrator, &scene, &ray);
+            assert!(
+                relative_eq!(color, scene.background, epsilon = 1.0e-3),
+                "Ray at x = {} returned {}",
+                x,
+                color
+            );
+        }
+    }
+
+    fn average_radiance(integrator: &dyn Integrator, scene: &Scene, ray: &Ray, n: u32) -> Color {
+        let mut sampler = IndependentSampler::new(7);
+        let total: Color = (0..n)
+            .map(|i| {
+                sampler.start_sample(&na::Point2::origin(), i, n);
+                integrator.radiance(scene, ray, &mut sampler)
+            })
+            .sum();
+        total / n as f32
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Inside a closed sphere that emits Le and reflects a fraction rho
+        // everywhere, radiance converges to Le / (1 - rho)
+        let mut scene = Scene::new();
+        scene.background = Color::zeros();
+        scene.add_shape(
+            "furnace",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::identity(),
+                radius: 10.0,
+            }),
+        );
+        scene.set_material(
+            "furnace",
+            Arc::new(Surface {
+                diffuse: Color::repeat(0.6),
+                emission: Color::repeat(0.4),
+                ..Default::default()
+            }),
+        );
+        let ray = Ray {
+            origin: na::Point3::new(1.0, 2.0, 3.0),
+            direction: na::Vector3::new(0.3, -1.0, 0.2),
+        };
+
+        let integrator = PathTracer {
+            max_depth: 64,
+            roulette_depth: 3,
+        };
+        let color = average_radiance(&integrator, &scene, &ray, 4000);
+        assert!(
+            relative_eq!(color, Color::repeat(1.0), max_relative = 0.03),
+            "Furnace converged to {}",
+            color
+        );
+    }
+
+    #[test]
+    fn white_sphere_in_white_environment() {
+        let mut scene = Scene::new();
+        scene.background = Color::repeat(1.0);
+        scene.add_shape(
+            "ball",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(0.0, 0.0, -5.0),
+                radius: 1.0,
+            }),
+        );
+        scene.set_material("ball", Arc::new(Surface::matte(Color::repeat(1.0))));
+        let ray = Ray {
+            origin: na::Point3::new(0.2, 0.1, 0.0),
+            direction: -na::Vector3::z(),
+        };
+        let color = average_radiance(&PathTracer::default(), &scene, &ray, 64);
+        assert!(relative_eq!(color, Color::repeat(1.0), epsilon = 1.0e-4));
+    }
+
+    #[test]
+    fn area_light_matches_reference() {
+        // Lambertian floor below a disk light, seen from above: radiance is
+        // rho / pi times the analytic irradiance pi * L * r^2 / (r^2 + h^2)
+        let mut scene = Scene::new();
+        scene.background = Color::zeros();
+        scene.add_shape(
+            "floor",
+            Box::new(shape::Plane {
+                pose: na::Isometry3::identity(),
+            }),
+        );
+        scene.set_material("floor", Arc::new(Surface::matte(Color::repeat(0.5))));
+        scene.add_light(
+            "disk",
+            Box::new(crate::light::DiskLight {
+                pose: na::Isometry3::new(na::Vector3::new(0.0, 0.0, 1.0), na::Vector3::x() * PI),
+                radius: 1.0,
+                color: Color::repeat(1.0),
+                intensity: 2.0,
+            }),
+        );
+        let ray = Ray {
+            origin: na::Point3::new(0.0, 0.3, 0.5),
+            direction: na::Vector3::new(0.0, -0.3, -0.5),
+        };
+
+        let expected = 0.5 / PI * (PI * 2.0 * 1.0 / (1.0 + 1.0));
+        let color = average_radiance(&PathTracer::default(), &scene, &ray, 4000);
+        assert!(
+            relative_eq!(color, Color::repeat(expected), max_relative = 0.03),
+            "Expected {} but got {}",
+            expected,
+            color
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Raymundo, a ray tracer written in Rust.
+//!
+//! A `Scene` holds named shapes, lights and materials. It is rendered as seen
+//! through a `Camera` with `render`, using one of the integrators in
+//! `integrator` to compute the colour seen along each ray. `render_film`
+//! keeps the unclamped radiance in a `Film`, which a `ToneMapping` turns
+//! into a display image. A `GraphicsContext` pairs the rendered image with
+//! its camera to draw overlays on top. A `Lidar` scans the same scene into a
+//! `PointCloud`, which can also be built from a depth AOV.
+
+extern crate approx;
+extern crate image;
+extern crate log;
+extern crate nalgebra as na;
+extern crate num;
+extern crate rand;
+extern crate rand_pcg;
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+pub mod film;
+pub mod filter;
+pub mod geometry;
+pub mod graphics;
+pub mod hdr;
+pub mod integrator;
+pub mod lidar;
+pub mod light;
+pub mod material;
+pub mod obj;
+pub mod point_cloud;
+pub mod render;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+pub mod scene_file;
+pub mod segmentation;
+pub mod shape;
+pub mod tonemap;
+
+#[cfg(test)]
+mod test_util;
+
+pub use camera::{Camera, PerspectiveCamera};
+pub use film::Film;
+pub use filter::Filter;
+pub use geometry::{Aabb, Ray, RayHit};
+pub use graphics::GraphicsContext;
+pub use integrator::Integrator;
+pub use lidar::Lidar;
+pub use light::Light;
+pub use material::{Color, Material, Surface};
+pub use point_cloud::PointCloud;
+pub use render::{render, render_film, RenderSettings};
+pub use sampler::Sampler;
+pub use scene::Scene;
+pub use shape::Shape;
+pub use tonemap::ToneMapping;
@@ -0,0 +1,190 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use log::info;
+
+use crate::geometry::Ray;
+use crate::point_cloud::PointCloud;
+use crate::scene::Scene;
+
+/// A spinning lidar, with one laser per channel at a fixed elevation. The
+/// sensor frame has +X forward, +Y left and +Z up along the spin axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lidar {
+    /// Pose of the sensor frame in the world.
+    pub pose: na::Isometry3<f32>,
+    /// Elevation of each channel above the sensor's XY plane, in radians.
+    /// Channels are numbered in this order as the ring of each point.
+    pub elevations: Vec<f32>,
+    /// Angle the sensor turns between firings, in radians.
+    pub horizontal_resolution: f32,
+    /// Returns closer than this are dropped, as by a sensor's blind zone.
+    pub min_range: f32,
+    pub max_range: f32,
+}
+
+/// `count` elevations evenly spaced from `lower` to `upper`, both included.
+pub fn uniform_elevations(count: usize, lower: f32, upper: f32) -> Vec<f32> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![(lower + upper) / 2.0],
+        _ => (0..count)
+            .map(|i| lower + (upper - lower) * i as f32 / (count - 1) as f32)
+            .collect(),
+    }
+}
+
+impl Lidar {
+    /// Number of firings per revolution.
+    pub fn columns(&self) -> usize {
+        ((2.0 * PI / self.horizontal_resolution).round() as usize).max(1)
+    }
+
+    /// Direction of the laser of a channel at `elevation` fired at `azimuth`,
+    /// counter-clockwise from +X, in the sensor frame.
+    pub fn direction(elevation: f32, azimuth: f32) -> na::Vector3<f32> {
+        na::Vector3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        )
+    }
+
+    /// One revolution of every channel, as points in the sensor frame in
+    /// firing order. Each point has its ring and azimuth, and an intensity
+    /// of the cosine between the laser and the surface normal. Lasers that
+    /// hit nothing within range give no point.
+    pub fn scan(&self, scene: &Scene) -> PointCloud {
+        let columns = self.columns();
+        info!(
+            "Scanning {} channels by {} columns",
+            self.elevations.len(),
+            columns
+        );
+        let mut cloud = PointCloud {
+            intensities: Some(Vec::new()),
+            rings: Some(Vec::new()),
+            azimuths: Some(Vec::new()),
+            ..Default::default()
+        };
+        let origin = na::Point3::from(self.pose.translation.vector);
+        for column in 0..columns {
+            let azimuth = 2.0 * PI * column as f32 / columns as f32;
+            for (ring, &elevation) in self.elevations.iter().enumerate() {
+                let direction = Lidar::direction(elevation, azimuth);
+                let ray = Ray {
+                    origin,
+                    direction: self.pose.rotation * direction,
+                };
+                let hit = match scene.ray_cast(&ray) {
+                    Some(hit) => hit,
+                    None => continue,
+                };
+                let range = (hit.near - origin).norm();
+                if range < self.min_range || range > self.max_range {
+                    continue;
+                }
+                let normal = hit.normal.normalize();
+                cloud.positions.push(na::Point3::from(direction * range));
+                if let Some(intensities) = cloud.intensities.as_mut() {
+                    intensities.push(normal.dot(&ray.direction).abs());
+                }
+                if let Some(rings) = cloud.rings.as_mut() {
+                    rings.push(ring as u16);
+                }
+                if let Some(azimuths) = cloud.azimuths.as_mut() {
+                    azimuths.push(azimuth);
+                }
+            }
+        }
+        cloud
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    use crate::test_util::floor_and_balls;
+
+    /// A sensor 2 m above a floor, with a ball 5 m ahead of it.
+    fn make_scene() -> (Scene, Lidar) {
+        let scene = floor_and_balls(&[("ball", [5.0, 0.0, 2.0])]);
+        let lidar = Lidar {
+            pose: na::Isometry3::translation(0.0, 0.0, 2.0),
+            elevations: vec![-PI / 4.0, 0.0],
+            horizontal_resolution: PI / 2.0,
+            min_range: 0.5,
+            max_range: 100.0,
+        };
+        (scene, lidar)
+    }
+
+    #[test]
+    fn scans_the_floor_and_the_ball() {
+        let (scene, lidar) = make_scene();
+        assert_eq!(lidar.columns(), 4);
+        let cloud = lidar.scan(&scene);
+        // Four floor returns, and the level channel only hits the ball
+        assert_eq!(cloud.len(), 5);
+        assert_eq!(cloud.rings.as_ref().unwrap(), &[0, 1, 0, 0, 0]);
+        assert!(relative_eq!(
+            cloud.positions[1],
+            na::Point3::new(4.0, 0.0, 0.0),
+            epsilon = 1.0e-4
+        ));
+        let azimuths = cloud.azimuths.as_ref().unwrap();
+        assert!(relative_eq!(azimuths[2], PI / 2.0));
+        // Down-looking lasers are in the sensor frame, 2 m below it
+        assert!(relative_eq!(
+            cloud.positions[2],
+            na::Point3::new(0.0, 2.0, -2.0),
+            epsilon = 1.0e-4
+        ));
+
+        let intensities = cloud.intensities.as_ref().unwrap();
+        assert!(relative_eq!(
+            intensities[0],
+            0.5_f32.sqrt(),
+            epsilon = 1.0e-4
+        ));
+        assert!(relative_eq!(intensities[1], 1.0, epsilon = 1.0e-4));
+    }
+
+    #[test]
+    fn returns_are_limited_to_the_range() {
+        let (scene, mut lidar) = make_scene();
+        lidar.max_range = 3.5;
+        let cloud = lidar.scan(&scene);
+        assert_eq!(cloud.len(), 4);
+        assert!(cloud.rings.unwrap().iter().all(|&ring| ring == 0));
+
+        lidar.max_range = 100.0;
+        lidar.min_range = 3.5;
+        assert_eq!(lidar.scan(&scene).len(), 1);
+    }
+
+    #[test]
+    fn pose_rotates_the_lasers() {
+        let (scene, mut lidar) = make_scene();
+        // Turned to face the ball along its +Y axis
+        lidar.pose.rotation =
+            na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), -PI / 2.0);
+        let cloud = lidar.scan(&scene);
+        let ball = cloud.rings.as_ref().unwrap().iter().position(|&r| r == 1);
+        let azimuth = cloud.azimuths.as_ref().unwrap()[ball.unwrap()];
+        assert!(relative_eq!(azimuth, PI / 2.0));
+        let world = cloud.transformed(&lidar.pose);
+        assert!(relative_eq!(
+            world.positions[ball.unwrap()],
+            na::Point3::new(4.0, 0.0, 2.0),
+            epsilon = 1.0e-4
+        ));
+
+        assert_eq!(uniform_elevations(3, -0.2, 0.2), vec![-0.2, 0.0, 0.2]);
+        assert_eq!(uniform_elevations(1, -0.2, 0.4), vec![0.1]);
+    }
+}
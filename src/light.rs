@@ -0,0 +1,603 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use crate::geometry::Ray;
+use crate::material::Color;
+use crate::sampling;
+
+/// Light arriving at a point from one sample on a light.
+pub struct LightSample {
+    /// Unit vector from the lit point towards the sample.
+    pub direction: na::Vector3<f32>,
+    /// Distance to the sample, infinite for directional lights.
+    pub distance: f32,
+    /// Radiance arriving along `direction`. For lights without area this is
+    /// the incident irradiance instead.
+    pub radiance: Color,
+    /// Probability density of the sample with respect to solid angle, or 1
+    /// for lights without area.
+    pub pdf: f32,
+}
+
+/// Where a ray meets the emitting surface of an area light.
+pub struct LightHit {
+    /// Distance from the ray origin to the light.
+    pub distance: f32,
+    /// Radiance emitted back along the ray.
+    pub radiance: Color,
+    /// Density with which `Light::sample` picks this direction from the ray
+    /// origin, with respect to solid angle.
+    pub pdf: f32,
+}
+
+/// All lights emit along the +Z axis of their pose, except point and sphere
+/// lights which emit in every direction.
+pub trait Light: Send + Sync {
+    fn origin(&self) -> &na::Isometry3<f32>;
+
+    /// Samples the light as seen from `point`. `u` is a uniform sample in
+    /// [0, 1)^2 selecting the point on area lights; lights with a single
+    /// position or direction ignore it.
+    fn sample(&self, point: &na::Point3<f32>, u: &na::Point2<f32>) -> Option<LightSample>;
+
+    /// True for lights without area, which only ever need a single sample and
+    /// cast hard shadows.
+    fn is_delta(&self) -> bool;
+
+    /// Finds where `ray` meets the emitting side of the light. Lights without
+    /// area can never be hit.
+    fn ray_cast(&self, _ray: &Ray) -> Option<LightHit> {
+        None
+    }
+}
+
+/// Isotropic point light emitting `intensity` in every direction, tinted by
+/// `color`.
+pub struct PointLight {
+    pub pose: na::Isometry3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl PointLight {
+    pub fn position(&self) -> na::Point3<f32> {
+        self.pose * na::Point3::origin()
+    }
+}
+
+impl Light for PointLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, point: &na::Point3<f32>, _u: &na::Point2<f32>) -> Option<LightSample> {
+        let to_light = self.position() - point;
+        let distance = to_light.norm();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            // Inverse square falloff
+            radiance: self.color * (self.intensity / (distance * distance)),
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Light from infinitely far away, such as the sun. `intensity` is the
+/// irradiance on a surface facing the light.
+pub struct DirectionalLight {
+    pub pose: na::Isometry3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Light for DirectionalLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, _point: &na::Point3<f32>, _u: &na::Point2<f32>) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -(self.pose * na::Vector3::z()).normalize(),
+            distance: f32::INFINITY,
+            radiance: self.color * self.intensity,
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Point light restricted to a cone. Light falls off smoothly between
+/// `falloff_angle` and `cone_angle`, both measured from the cone axis.
+pub struct SpotLight {
+    pub pose: na::Isometry3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+    pub cone_angle: f32,
+    pub falloff_angle: f32,
+}
+
+impl SpotLight {
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        let cos_total = self.cone_angle.cos();
+        let cos_start = self.falloff_angle.min(self.cone_angle).cos();
+        if cos_theta <= cos_total {
+            return 0.0;
+        }
+        if cos_theta >= cos_start {
+            return 1.0;
+        }
+        let t = (cos_theta - cos_total) / (cos_start - cos_total);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, point: &na::Point3<f32>, _u: &na::Point2<f32>) -> Option<LightSample> {
+        let to_light = self.pose * na::Point3::origin() - point;
+        let distance = to_light.norm();
+        let direction = to_light / distance;
+        let cos_theta = -direction.dot(&(self.pose * na::Vector3::z()));
+        let falloff = self.falloff(cos_theta);
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.color * (self.intensity * falloff / (distance * distance)),
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Builds the sample for a point `sampled` on a one-sided area light with
+/// normal `light_normal`, chosen with probability density `pdf_area`.
+fn area_sample(
+    point: &na::Point3<f32>,
+    sampled: &na::Point3<f32>,
+    light_normal: &na::Vector3<f32>,
+    radiance: Color,
+    pdf_area: f32,
+) -> Option<LightSample> {
+    let to_light = sampled - point;
+    let distance = to_light.norm();
+    let direction = to_light / distance;
+    let cos_light = -direction.dot(light_normal);
+    if cos_light <= 0.0 {
+        return None;
+    }
+    Some(LightSample {
+        direction,
+        distance,
+        radiance,
+        // Convert the area density to solid angle as seen from `point`
+        pdf: pdf_area * distance * distance / cos_light,
+    })
+}
+
+/// Intersects `ray` with the emitting side of a planar light lying in the XY
+/// plane of `pose`. Returns the hit in the light's frame along with the
+/// distance and the cosine at the light.
+fn planar_hit(pose: &na::Isometry3<f32>, ray: &Ray) -> Option<(na::Point3<f32>, f32, f32)> {
+    let origin = pose.inverse_transform_point(&ray.origin);
+    let direction = pose.inverse_transform_vector(&ray.direction).normalize();
+    if origin.z <= 0.0 || direction.z >= 0.0 {
+        return None;
+    }
+    let distance = -origin.z / direction.z;
+    Some((origin + direction * distance, distance, -direction.z))
+}
+
+/// Rectangular area light of `width` along X and `height` along Y, centred
+/// on its pose. `intensity` is the emitted radiance.
+pub struct RectLight {
+    pub pose: na::Isometry3<f32>,
+    pub width: f32,
+    pub height: f32,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Light for RectLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, point: &na::Point3<f32>, u: &na::Point2<f32>) -> Option<LightSample> {
+        let local = na::Point3::new((u.x - 0.5) * self.width, (u.y - 0.5) * self.height, 0.0);
+        area_sample(
+            point,
+            &(self.pose * local),
+            &(self.pose * na::Vector3::z()),
+            self.color * self.intensity,
+            1.0 / (self.width * self.height),
+        )
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn ray_cast(&self, ray: &Ray) -> Option<LightHit> {
+        let (local, distance, cos_light) = planar_hit(&self.pose, ray)?;
+        if local.x.abs() > self.width / 2.0 || local.y.abs() > self.height / 2.0 {
+            return None;
+        }
+        Some(LightHit {
+            distance,
+            radiance: self.color * self.intensity,
+            pdf: distance * distance / (cos_light * self.width * self.height),
+        })
+    }
+}
+
+/// Disk-shaped area light centred on its pose. `intensity` is the emitted
+/// radiance.
+pub struct DiskLight {
+    pub pose: na::Isometry3<f32>,
+    pub radius: f32,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Light for DiskLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, point: &na::Point3<f32>, u: &na::Point2<f32>) -> Option<LightSample> {
+        let disk = sampling::concentric_disk(u);
+        let local = na::Point3::new(disk.x * self.radius, disk.y * self.radius, 0.0);
+        area_sample(
+            point,
+            &(self.pose * local),
+            &(self.pose * na::Vector3::z()),
+            self.color * self.intensity,
+            1.0 / (PI * self.radius * self.radius),
+        )
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn ray_cast(&self, ray: &Ray) -> Option<LightHit> {
+        let (local, distance, cos_light) = planar_hit(&self.pose, ray)?;
+        if local.x * local.x + local.y * local.y > self.radius * self.radius {
+            return None;
+        }
+        Some(LightHit {
+            distance,
+            radiance: self.color * self.intensity,
+            pdf: distance * distance / (cos_light * PI * self.radius * self.radius),
+        })
+    }
+}
+
+/// Spherical area light centred on its pose, emitting outwards. `intensity`
+/// is the emitted radiance.
+pub struct SphereLight {
+    pub pose: na::Isometry3<f32>,
+    pub radius: f32,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl SphereLight {
+    /// Cosine of the half-angle of the cone the sphere subtends from a point
+    /// `distance_squared` away from its centre, and the density of sampling
+    /// directions uniformly within it.
+    fn subtended_cone(&self, distance_squared: f32) -> (f32, f32) {
+        let sin_max_squared = self.radius * self.radius / distance_squared;
+        let cos_max = (1.0 - sin_max_squared).max(0.0).sqrt();
+        // 1 - cos_max rounds to zero for small or distant spheres, so compute
+        // it from the sine instead
+        let one_minus_cos = sin_max_squared / (1.0 + cos_max);
+        (cos_max, 1.0 / (2.0 * PI * one_minus_cos))
+    }
+}
+
+impl Light for SphereLight {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn sample(&self, point: &na::Point3<f32>, u: &na::Point2<f32>) -> Option<LightSample> {
+        let to_center = self.pose * na::Point3::origin() - point;
+        let center_distance = to_center.norm();
+        if center_distance <= self.radius {
+            return None;
+        }
+
+        // Sample the cone of directions subtended by the sphere
+        let (cos_max, pdf) = self.subtended_cone(center_distance * center_distance);
+        let basis = sampling::coordinate_system(&(to_center / center_distance));
+        let direction = basis * sampling::uniform_cone(u, cos_max);
+
+        // Distance to the near side of the sphere along the sampled direction
+        let cos_theta = direction.dot(&to_center) / center_distance;
+        let sin_theta_squared = (1.0 - cos_theta * cos_theta).max(0.0);
+        let distance = center_distance * cos_theta
+            - (self.radius.powi(2) - center_distance.powi(2) * sin_theta_squared)
+                .max(0.0)
+                .sqrt();
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.color * self.intensity,
+            pdf,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn ray_cast(&self, ray: &Ray) -> Option<LightHit> {
+        let to_center = self.pose * na::Point3::origin() - ray.origin;
+        let center_distance_squared = to_center.norm_squared();
+        let radius_squared = self.radius * self.radius;
+        if center_distance_squared <= radius_squared {
+            return None;
+        }
+
+        let direction = ray.direction.normalize();
+        let t_ca = to_center.dot(&direction);
+        let d_squared = center_distance_squared - t_ca * t_ca;
+        if t_ca <= 0.0 || d_squared > radius_squared {
+            return None;
+        }
+
+        Some(LightHit {
+            distance: t_ca - (radius_squared - d_squared).sqrt(),
+            radiance: self.color * self.intensity,
+            pdf: self.subtended_cone(center_distance_squared).1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn white() -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn stratified(n: usize) -> Vec<na::Point2<f32>> {
+        let mut samples = Vec::new();
+        for x in 0..n {
+            for y in 0..n {
+                samples.push(na::Point2::new(
+                    (x as f32 + 0.5) / n as f32,
+                    (y as f32 + 0.5) / n as f32,
+                ));
+            }
+        }
+        samples
+    }
+
+    // Monte Carlo estimate of the irradiance on an upward facing surface at
+    // the origin
+    fn estimate_irradiance(light: &dyn Light) -> f32 {
+        let point = na::Point3::origin();
+        let samples = stratified(64);
+        let total: f32 = samples
+            .iter()
+            .filter_map(|u| light.sample(&point, u))
+            .map(|s| s.radiance.x * s.direction.z.max(0.0) / s.pdf)
+            .sum();
+        total / samples.len() as f32
+    }
+
+    // Light facing down onto the origin from height `h`
+    fn facing_down(h: f32) -> na::Isometry3<f32> {
+        na::Isometry3::new(na::Vector3::new(0.0, 0.0, h), na::Vector3::x() * PI)
+    }
+
+    #[test]
+    fn directional_light() {
+        let light = DirectionalLight {
+            pose: facing_down(10.0),
+            color: white(),
+            intensity: 2.0,
+        };
+        let sample = light
+            .sample(&na::Point3::origin(), &na::Point2::origin())
+            .unwrap();
+        assert!(relative_eq!(
+            sample.direction,
+            na::Vector3::z(),
+            epsilon = 1.0e-6
+        ));
+        assert_eq!(sample.distance, f32::INFINITY);
+        assert_eq!(sample.radiance, white() * 2.0);
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        let light = SpotLight {
+            pose: facing_down(1.0),
+            color: white(),
+            intensity: 1.0,
+            cone_angle: 0.5,
+            falloff_angle: 0.25,
+        };
+        let u = na::Point2::origin();
+        let center = light.sample(&na::Point3::origin(), &u).unwrap();
+        assert!(relative_eq!(center.radiance, white(), epsilon = 1.0e-6));
+
+        // Halfway through the falloff, which is smooth in the cosine
+        let cos_theta = (0.5_f32.cos() + 0.25_f32.cos()) / 2.0;
+        let edge = na::Point3::new(cos_theta.acos().tan(), 0.0, 0.0);
+        let sample = light.sample(&edge, &u).unwrap();
+        let unattenuated = 1.0 / (1.0 + edge.x * edge.x);
+        assert!(relative_eq!(
+            sample.radiance.x,
+            0.5 * unattenuated,
+            epsilon = 1.0e-4
+        ));
+
+        let outside = na::Point3::new(1.0, 0.0, 0.0);
+        assert!(light.sample(&outside, &u).is_none());
+    }
+
+    #[test]
+    fn disk_light_irradiance() {
+        // Irradiance below a Lambertian disk: pi * L * r^2 / (r^2 + h^2)
+        let light = DiskLight {
+            pose: facing_down(2.0),
+            radius: 1.0,
+            color: white(),
+            intensity: 3.0,
+        };
+        let expected = PI * 3.0 * 1.0 / (1.0 + 4.0);
+        assert!(relative_eq!(
+            estimate_irradiance(&light),
+            expected,
+            max_relative = 0.01
+        ));
+    }
+
+    #[test]
+    fn rect_light_is_one_sided() {
+        let light = RectLight {
+            pose: na::Isometry3::translation(0.0, 0.0, 2.0),
+            width: 1.0,
+            height: 1.0,
+            color: white(),
+            intensity: 1.0,
+        };
+        let u = na::Point2::new(0.5, 0.5);
+        assert!(light.sample(&na::Point3::origin(), &u).is_none());
+        assert!(light.sample(&na::Point3::new(0.0, 0.0, 4.0), &u).is_some());
+    }
+
+    #[test]
+    fn sphere_light_irradiance() {
+        // Irradiance facing a Lambertian sphere: pi * L * (r / d)^2
+        let light = SphereLight {
+            pose: na::Isometry3::translation(0.0, 0.0, 3.0),
+            radius: 1.0,
+            color: white(),
+            intensity: 2.0,
+        };
+        let expected = PI * 2.0 / 9.0;
+        assert!(relative_eq!(
+            estimate_irradiance(&light),
+            expected,
+            max_relative = 0.01
+        ));
+
+        let sample = light
+            .sample(&na::Point3::origin(), &na::Point2::new(0.0, 0.0))
+            .unwrap();
+        assert!(relative_eq!(sample.distance, 2.0, epsilon = 1.0e-4));
+    }
+
+    // Casting a ray along every sampled direction must find the light again,
+    // at the same distance and with the same density
+    fn check_ray_cast_matches_sample(light: &dyn Light, point: &na::Point3<f32>) {
+        for u in stratified(8) {
+            let sample = light.sample(point, &u).unwrap();
+            let ray = Ray {
+                origin: *point,
+                direction: sample.direction,
+            };
+            let hit = light.ray_cast(&ray).unwrap();
+            assert!(relative_eq!(
+                hit.distance,
+                sample.distance,
+                max_relative = 1.0e-3
+            ));
+            assert!(relative_eq!(hit.pdf, sample.pdf, max_relative = 1.0e-3));
+            assert_eq!(hit.radiance, sample.radiance);
+        }
+    }
+
+    #[test]
+    fn area_lights_can_be_hit() {
+        let point = na::Point3::new(0.3, -0.2, 0.0);
+        check_ray_cast_matches_sample(
+            &RectLight {
+                pose: facing_down(2.0),
+                width: 1.0,
+                height: 0.5,
+                color: white(),
+                intensity: 1.0,
+            },
+            &point,
+        );
+        check_ray_cast_matches_sample(
+            &DiskLight {
+                pose: facing_down(2.0),
+                radius: 0.5,
+                color: white(),
+                intensity: 1.0,
+            },
+            &point,
+        );
+        check_ray_cast_matches_sample(
+            &SphereLight {
+                pose: na::Isometry3::translation(0.0, 0.0, 3.0),
+                radius: 1.0,
+                color: white(),
+                intensity: 1.0,
+            },
+            &point,
+        );
+
+        // Point lights have no area to hit
+        let light = PointLight {
+            pose: na::Isometry3::identity(),
+            color: white(),
+            intensity: 1.0,
+        };
+        let ray = Ray {
+            origin: na::Point3::new(0.0, 0.0, 1.0),
+            direction: -na::Vector3::z(),
+        };
+        assert!(light.ray_cast(&ray).is_none());
+    }
+
+    #[test]
+    fn distant_sphere_light() {
+        let light = SphereLight {
+            pose: na::Isometry3::translation(0.0, 0.0, 1.0e4),
+            radius: 0.25,
+            color: white(),
+            intensity: 1.0,
+        };
+        let sample = light
+            .sample(&na::Point3::origin(), &na::Point2::new(0.5, 0.5))
+            .unwrap();
+        assert!(sample.pdf.is_finite());
+        // Irradiance of a small sphere approaches pi * L * (r / d)^2
+        let expected = PI * (0.25_f32 / 1.0e4).powi(2);
+        assert!(relative_eq!(
+            sample.radiance.x / sample.pdf,
+            expected,
+            max_relative = 1.0e-2
+        ));
+    }
+}
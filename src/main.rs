@@ -1,121 +1,204 @@
+extern crate clap;
 extern crate image;
 extern crate log;
 extern crate nalgebra as na;
-extern crate num;
+extern crate raymundo;
 extern crate simple_logging;
 
-use log::info;
+use clap::{CommandFactory, Parser};
 use log::LevelFilter;
+use log::{error, info, warn};
+use std::path::Path;
+use std::process;
 
-mod geometry;
-mod graphics;
-use graphics::GraphicsContext;
-mod scene;
-mod shape;
+use raymundo::point_cloud::PointCloud;
+use raymundo::segmentation::Segmentation;
+use raymundo::{aov, graphics, hdr, render, scene_file};
 
-fn init_logging() {
-    simple_logging::log_to_stderr(LevelFilter::Info);
+mod cli;
+
+fn init_logging(level: LevelFilter) {
+    simple_logging::log_to_stderr(level);
     info!("Logging initalized!");
 }
 
-fn build_graphics_context(iso: na::Isometry3<f32>) -> GraphicsContext {
-    //let scale = 0.25;
-    //let scale = 0.5;
-    let scale = 1.0;
-    let img_width = (1024.0 * scale) as u32;
-    let img_height = (1024.0 * scale) as u32;
-    let imgbuf = image::RgbImage::new(img_width, img_height);
-
-    let aspect = img_width as f32 / img_height as f32;
-    let proj = na::Perspective3::new(aspect, 3.14 / 5.0, 0.001, 900.0);
-    //let size = 3.5;
-    //let proj = na::Orthographic3::new(-size, size, -size, size, -size, size);
-
-    return GraphicsContext {
-        tf_root: iso,
-        projection: proj,
-        img_width: img_width,
-        img_height: img_height,
-        imgbuf: imgbuf,
-    };
-}
+/// Scene rendered when no scene file is given.
+const DEFAULT_SCENE: &str = include_str!("../scenes/default.toml");
 
 fn main() {
-    init_logging();
-
-    let target = na::Isometry3::translation(0.0, 0.0, -10.0)
-        * na::Isometry3::new(
-            na::Vector3::y() * -0.85,
-            na::Vector3::x() * std::f32::consts::PI / -2.4,
-        )
-        * na::Isometry3::rotation(na::Vector3::z() * std::f32::consts::PI * 3.5 / 4.0);
-
-    let eye = na::Point3::new(0.0, 0.0, 0.0);
-    let up = -na::Vector3::y();
-
-    let iso = na::Isometry3::face_towards(&eye, &(&target * na::Point3::origin()), &up);
-
-    let mut ctx = build_graphics_context(iso);
-
-    let mut scene = scene::Scene::new();
-
-    scene.add_light(
-        "light",
-        shape::PointLight {
-            pose: target * na::Isometry3::<f32>::translation(-4.0, 1.0, 4.0),
-        },
-    );
-
-    scene.add_shape(
-        "floor",
-        Box::new(shape::Plane {
-            pose: target * na::Isometry3::translation(0.0, 0.0, -1.0),
-        }),
-    );
-
-    let spacing = 1.15;
-    scene.add_shape(
-        "sphere_one",
-        Box::new(shape::Sphere {
-            pose: target * na::Isometry3::translation(1.0 * spacing, 0.0, 0.0),
-            radius: 1.0,
-        }),
-    );
-    scene.add_shape(
-        "sphere_two",
-        Box::new(shape::Sphere {
-            pose: target * na::Isometry3::translation(-1.0 * spacing, 0.0, 0.0),
-            radius: 1.0,
-        }),
-    );
+    let args = cli::Args::parse();
+    let format = args.output_format().unwrap_or_else(|message| {
+        cli::Args::command()
+            .error(clap::error::ErrorKind::ValueValidation, message)
+            .exit()
+    });
+    init_logging(args.log_level());
+
+    let overrides = scene_file::Overrides {
+        width: args.width,
+        height: args.height,
+        fov: args.fov,
+    };
+    let loaded = match &args.scene {
+        Some(path) => scene_file::load_scene_with(path, &overrides),
+        None => scene_file::parse_scene_with(
+            DEFAULT_SCENE,
+            Path::new("scenes/default.toml"),
+            &overrides,
+        ),
+    };
+    let scene_file::LoadedScene {
+        scene,
+        context: mut ctx,
+        palette,
+        lidars,
+    } = loaded.unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(1);
+    });
+
+    let mut settings = render::RenderSettings {
+        integrator: args.integrator(),
+        samples_per_pixel: args.spp,
+        filter: args.filter(),
+        sampler: args.sampler(),
+        ..Default::default()
+    };
+    if let Some(threads) = args.threads {
+        settings.threads = threads as usize;
+    }
 
     info!("Sampling image");
 
-    ctx.imgbuf = image::RgbImage::from_fn(ctx.img_width, ctx.img_height, |x, y| {
-        // NOTE: Invert the Y axis because we're not savages
-        let ray = ctx.unproject_point(na::Point2::new(x, ctx.img_height - y));
-        match scene.ray_cast(&ray) {
-            Some(hit) => {
-                return scene.paint(&hit);
+    let film = render::render_film(&scene, ctx.camera.as_ref(), &settings);
+    ctx.imgbuf = film.tone_map(&args.tone_mapping());
+
+    if !args.aov.is_empty() || args.segmentation || args.depth_cloud {
+        let buffers = aov::render_aovs(&scene, ctx.camera.as_ref());
+        for kind in &args.aov {
+            let path = args.aov_path(kind.aov());
+            info!("Saving {} to {}", kind.aov().name(), path.display());
+            if let Err(err) = buffers.save(kind.aov(), &path, args.depth_scale) {
+                error!("Failed to save {}: {}", path.display(), err);
+                process::exit(1);
             }
-            None => image::Rgb([0, 150, 200]),
         }
-    });
 
-    info!("Drawing axes");
-    //graphics::draw_axes(scene.get_shape("floor").unwrap().origin(), 0.5, &mut ctx);
-    graphics::draw_axes(
-        scene.get_shape("sphere_one").unwrap().origin(),
-        0.5,
-        &mut ctx,
-    );
-    graphics::draw_axes(
-        scene.get_shape("sphere_two").unwrap().origin(),
-        0.5,
-        &mut ctx,
-    );
-    graphics::draw_axes(&scene.get_light("light").unwrap().pose, 0.5, &mut ctx);
-
-    info!("Saving image");
-    ctx.save("test.png");
+        if args.segmentation {
+            let segmentation = Segmentation::new(&scene, &buffers);
+            let instance_path = args.sibling_path("instance", "png");
+            let class_path = args.sibling_path("class", "png");
+            let json_path = args.sibling_path("segmentation", "json");
+            info!("Saving segmentation to {}", json_path.display());
+            let saved = segmentation
+                .instance_mask()
+                .save(&instance_path)
+                .and_then(|_| segmentation.class_mask(&palette).save(&class_path))
+                .map_err(|err| err.to_string())
+                .and_then(|_| {
+                    segmentation
+                        .save_json(&json_path, &palette)
+                        .map_err(|err| err.to_string())
+                });
+            if let Err(err) = saved {
+                error!("Failed to save segmentation: {}", err);
+                process::exit(1);
+            }
+        }
+
+        if args.depth_cloud {
+            let mut cloud = PointCloud::from_depth(&buffers.depth, &ctx).unwrap_or_else(|err| {
+                error!("{}", err);
+                process::exit(1);
+            });
+            if args.cloud_frame == cli::CloudFrame::World {
+                cloud = cloud.transformed(ctx.camera.pose());
+            }
+            let path = args.cloud_path("cloud");
+            info!("Saving depth cloud to {}", path.display());
+            if let Err(err) = cloud.save(&path, args.cloud_format.format(), args.cloud_encoding()) {
+                error!("Failed to save {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if args.lidar {
+        if lidars.is_empty() {
+            error!("The scene has no lidars to scan with");
+            process::exit(1);
+        }
+        for (name, lidar) in &lidars {
+            let mut cloud = lidar.scan(&scene);
+            if args.cloud_frame == cli::CloudFrame::World {
+                cloud = cloud.transformed(&lidar.pose);
+            }
+            let path = args.cloud_path(name);
+            info!(
+                "Saving {} points of {} to {}",
+                cloud.len(),
+                name,
+                path.display()
+            );
+            if let Err(err) = cloud.save(&path, args.cloud_format.format(), args.cloud_encoding()) {
+                error!("Failed to save {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    let saved = match (format.image_format(), args.hdr_format(format)) {
+        (_, Some(hdr_format)) => {
+            if args.reproject_cloud.is_some() {
+                warn!("Point clouds are only drawn onto tone mapped images");
+            }
+            info!("Saving radiance to {}", args.output.display());
+            hdr::save(&film.to_hdr(), &args.output, hdr_format)
+        }
+        (Some(image_format), None) => {
+            info!("Drawing axes");
+            for shape in scene.shapes.values() {
+                if shape.bounds().is_finite() {
+                    graphics::draw_axes(shape.origin(), 0.5, &mut ctx);
+                }
+            }
+            for light in scene.lights.values() {
+                graphics::draw_axes(light.origin(), 0.5, &mut ctx);
+            }
+
+            if let Some(path) = &args.reproject_cloud {
+                let cloud = PointCloud::load(path).unwrap_or_else(|err| {
+                    error!("{}", err);
+                    process::exit(1);
+                });
+                let pose = match (args.cloud_frame, &args.reproject_lidar) {
+                    (cli::CloudFrame::Sensor, Some(name)) => match lidars.get(name) {
+                        Some(lidar) => lidar.pose,
+                        None => {
+                            error!("The scene has no lidar named '{}'", name);
+                            process::exit(1);
+                        }
+                    },
+                    (cli::CloudFrame::Sensor, None) => *ctx.camera.pose(),
+                    (cli::CloudFrame::World, _) => na::Isometry3::identity(),
+                };
+                let drawn =
+                    graphics::draw_point_cloud(&cloud, &pose, image::Rgb([0, 255, 0]), &mut ctx);
+                info!(
+                    "Drew {} of {} points of {}",
+                    drawn,
+                    cloud.len(),
+                    path.display()
+                );
+            }
+
+            info!("Saving image to {}", args.output.display());
+            ctx.save_with_format(&args.output, image_format)
+        }
+        (None, None) => unreachable!("Every output format is an image or HDR format"),
+    };
+    if let Err(err) = saved {
+        error!("Failed to save {}: {}", args.output.display(), err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,110 @@
+extern crate nalgebra as na;
+
+use crate::geometry::RayHit;
+
+/// Linear RGB colour. Components are usually in [0, 1], but light and
+/// emission values may exceed that.
+pub type Color = na::Vector3<f32>;
+
+/// Describes how a surface responds to light at a given hit.
+pub trait Material: Send + Sync {
+    /// Fraction of incoming light scattered diffusely, per channel.
+    fn diffuse(&self, hit: &RayHit) -> Color;
+    /// Colour of specular highlights.
+    fn specular(&self, hit: &RayHit) -> Color;
+    /// Phong exponent controlling the size of specular highlights.
+    fn shininess(&self, hit: &RayHit) -> f32;
+    /// Light emitted by the surface itself.
+    fn emission(&self, hit: &RayHit) -> Color;
+    /// Fraction of incoming light reflected as by a perfect mirror.
+    fn reflectivity(&self, hit: &RayHit) -> Color;
+    /// Fraction of light passing through the surface, in [0, 1]. Transmitted
+    /// light is split between reflection and refraction by the Fresnel terms.
+    fn transparency(&self, hit: &RayHit) -> f32;
+    /// Index of refraction of the medium behind the surface.
+    fn ior(&self, hit: &RayHit) -> f32;
+}
+
+/// Surface appearance of a shape, following the Phong-style parameters used
+/// by Wavefront MTL files. Colours are linear RGB in [0, 1].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Surface {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f32,
+    pub emission: Color,
+    pub reflective: Color,
+    pub ior: f32,
+    pub opacity: f32,
+}
+
+impl Surface {
+    pub fn matte(color: Color) -> Self {
+        Surface {
+            diffuse: color,
+            ..Default::default()
+        }
+    }
+
+    pub fn mirror(color: Color) -> Self {
+        Surface {
+            diffuse: Color::zeros(),
+            reflective: color,
+            ..Default::default()
+        }
+    }
+
+    /// Clear dielectric such as glass or water.
+    pub fn glass(ior: f32) -> Self {
+        Surface {
+            diffuse: Color::zeros(),
+            ior,
+            opacity: 0.0,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Surface {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::zeros(),
+            shininess: 0.0,
+            emission: Color::zeros(),
+            reflective: Color::zeros(),
+            ior: 1.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl Material for Surface {
+    fn diffuse(&self, _hit: &RayHit) -> Color {
+        self.diffuse
+    }
+
+    fn specular(&self, _hit: &RayHit) -> Color {
+        self.specular
+    }
+
+    fn shininess(&self, _hit: &RayHit) -> f32 {
+        self.shininess
+    }
+
+    fn emission(&self, _hit: &RayHit) -> Color {
+        self.emission
+    }
+
+    fn reflectivity(&self, _hit: &RayHit) -> Color {
+        self.reflective
+    }
+
+    fn transparency(&self, _hit: &RayHit) -> f32 {
+        1.0 - self.opacity
+    }
+
+    fn ior(&self, _hit: &RayHit) -> f32 {
+        self.ior
+    }
+}
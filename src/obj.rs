@@ -0,0 +1,731 @@
+extern crate log;
+extern crate nalgebra as na;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::material::{Material, Surface};
+use crate::scene::Scene;
+use crate::shape::TriangleMesh;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// A group would replace a shape of the same name, either another group
+    /// of the model or one already in the scene.
+    DuplicateShape {
+        name: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::DuplicateShape { name } => {
+                write!(f, "duplicate shape name '{}'", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } | ObjError::DuplicateShape { .. } => None,
+        }
+    }
+}
+
+/// One named entry of an OBJ file: all faces of an object/group that share a
+/// material.
+pub struct ObjGroup {
+    pub name: String,
+    pub material: Option<String>,
+    pub mesh: TriangleMesh,
+}
+
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+    pub material_libraries: Vec<String>,
+    pub materials: HashMap<String, Surface>,
+}
+
+impl ObjModel {
+    /// Adds every group to `scene` as a shape placed at `pose`, along with its
+    /// material. Returns the names of the added shapes. Nothing is added if
+    /// any group has the name of a shape already in the scene.
+    pub fn add_to_scene(
+        self,
+        scene: &mut Scene,
+        pose: &na::Isometry3<f32>,
+    ) -> Result<Vec<String>, ObjError> {
+        let mut seen = HashSet::new();
+        if let Some(group) = self.groups.iter().find(|group| {
+            scene.shapes.contains_key(&group.name) || !seen.insert(group.name.as_str())
+        }) {
+            return Err(ObjError::DuplicateShape {
+                name: group.name.clone(),
+            });
+        }
+
+        let materials: HashMap<String, Arc<dyn Material>> = self
+            .materials
+            .into_iter()
+            .map(|(name, surface)| (name, Arc::new(surface) as Arc<dyn Material>))
+            .collect();
+
+        let mut names = Vec::new();
+        for group in self.groups {
+            let mut mesh = group.mesh;
+            mesh.pose = pose * mesh.pose;
+            scene.add_shape(&group.name, Box::new(mesh));
+
+            if let Some(material) = &group.material {
+                match materials.get(material) {
+                    Some(material) => scene.set_material(&group.name, material.clone()),
+                    None => warn!(
+                        "Material '{}' used by '{}' is not defined in any material library",
+                        material, group.name
+                    ),
+                }
+            }
+            names.push(group.name);
+        }
+        Ok(names)
+    }
+}
+
+/// Loads an OBJ file, along with the MTL libraries it references. Libraries
+/// that cannot be read are skipped with a warning, leaving their groups with
+/// the scene's default material.
+pub fn load_obj(path: &Path) -> Result<ObjModel, ObjError> {
+    let file = File::open(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut model = parse_obj(BufReader::new(file), path)?;
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for library in &model.material_libraries {
+        match load_mtl(&base_dir.join(library)) {
+            Ok(materials) => model.materials.extend(materials),
+            Err(err @ ObjError::Io { .. }) => warn!("Skipping material library: {}", err),
+            Err(err) => return Err(err),
+        }
+    }
+
+    info!(
+        "Loaded {} groups and {} materials from {}",
+        model.groups.len(),
+        model.materials.len(),
+        path.display()
+    );
+    Ok(model)
+}
+
+/// Loads an OBJ file and adds its groups to `scene` at `pose`.
+pub fn load_into_scene(
+    path: &Path,
+    scene: &mut Scene,
+    pose: &na::Isometry3<f32>,
+) -> Result<Vec<String>, ObjError> {
+    load_obj(path)?.add_to_scene(scene, pose)
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Surface>, ObjError> {
+    let file = File::open(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_mtl(BufReader::new(file), path)
+}
+
+/// Splits a line into its keyword and arguments, dropping comments.
+fn tokenize(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = match line.find('#') {
+        Some(idx) => &line[..idx],
+        None => line,
+    };
+    let mut tokens = line.split_whitespace();
+    let keyword = tokens.next()?;
+    Some((keyword, tokens.collect()))
+}
+
+fn parse_floats(keyword: &str, args: &[&str]) -> Result<Vec<f32>, String> {
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f32>()
+                .map_err(|_| format!("invalid number '{}' in '{}' statement", arg, keyword))
+        })
+        .collect()
+}
+
+fn parse_vector(keyword: &str, args: &[&str]) -> Result<na::Vector3<f32>, String> {
+    let values = parse_floats(keyword, args)?;
+    match values.len() {
+        3 => Ok(na::Vector3::new(values[0], values[1], values[2])),
+        1 => Ok(na::Vector3::repeat(values[0])),
+        n => Err(format!("'{}' expects 1 or 3 values, got {}", keyword, n)),
+    }
+}
+
+fn parse_scalar(keyword: &str, args: &[&str]) -> Result<f32, String> {
+    let values = parse_floats(keyword, args)?;
+    match values.len() {
+        1 => Ok(values[0]),
+        n => Err(format!("'{}' expects 1 value, got {}", keyword, n)),
+    }
+}
+
+/// Resolves a 1-based (or negative, relative) OBJ index into a 0-based one.
+fn resolve_index(token: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let idx = token
+        .parse::<i64>()
+        .map_err(|_| format!("invalid {} index '{}'", kind, token))?;
+    let resolved = if idx > 0 {
+        idx - 1
+    } else if idx < 0 {
+        count as i64 + idx
+    } else {
+        return Err(format!("{} index must not be 0", kind));
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range ({} defined so far)",
+            kind, idx, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+type Corner = (usize, Option<usize>);
+
+struct GroupBuilder {
+    name: String,
+    material: Option<String>,
+    faces: Vec<[Corner; 3]>,
+}
+
+impl GroupBuilder {
+    fn build(
+        self,
+        positions: &[na::Point3<f32>],
+        normals: &[na::Vector3<f32>],
+    ) -> (String, Option<String>, TriangleMesh) {
+        // Only keep per-vertex normals if every corner of the group has one
+        let smooth = self
+            .faces
+            .iter()
+            .all(|face| face.iter().all(|corner| corner.1.is_some()));
+
+        let mut remap: HashMap<Corner, usize> = HashMap::new();
+        let mut mesh = TriangleMesh {
+            pose: na::Isometry3::identity(),
+            vertices: Vec::new(),
+            indices: Vec::with_capacity(self.faces.len()),
+            normals: if smooth { Some(Vec::new()) } else { None },
+        };
+        for face in &self.faces {
+            let mut triangle = [0; 3];
+            for (slot, corner) in face.iter().enumerate() {
+                let key = if smooth { *corner } else { (corner.0, None) };
+                triangle[slot] = *remap.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(positions[key.0]);
+                    if let (Some(mesh_normals), Some(normal)) = (&mut mesh.normals, key.1) {
+                        mesh_normals.push(normals[normal].normalize());
+                    }
+                    mesh.vertices.len() - 1
+                });
+            }
+            mesh.indices.push(triangle);
+        }
+        (self.name, self.material, mesh)
+    }
+}
+
+struct ObjParser {
+    positions: Vec<na::Point3<f32>>,
+    normals: Vec<na::Vector3<f32>>,
+    texcoord_count: usize,
+    object: Option<String>,
+    group: Option<String>,
+    material: Option<String>,
+    builders: Vec<GroupBuilder>,
+    lookup: HashMap<(String, Option<String>), usize>,
+    material_libraries: Vec<String>,
+}
+
+impl ObjParser {
+    fn new() -> Self {
+        ObjParser {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            texcoord_count: 0,
+            object: None,
+            group: None,
+            material: None,
+            builders: Vec::new(),
+            lookup: HashMap::new(),
+            material_libraries: Vec::new(),
+        }
+    }
+
+    fn current_name(&self) -> String {
+        match (&self.object, &self.group) {
+            (Some(object), Some(group)) if object != group => format!("{}/{}", object, group),
+            (Some(object), _) => object.clone(),
+            (None, Some(group)) => group.clone(),
+            (None, None) => "default".to_string(),
+        }
+    }
+
+    fn current_builder(&mut self) -> &mut GroupBuilder {
+        let key = (self.current_name(), self.material.clone());
+        let builders = &mut self.builders;
+        let idx = *self.lookup.entry(key.clone()).or_insert_with(|| {
+            builders.push(GroupBuilder {
+                name: key.0,
+                material: key.1,
+                faces: Vec::new(),
+            });
+            builders.len() - 1
+        });
+        &mut self.builders[idx]
+    }
+
+    fn parse_corner(&self, token: &str) -> Result<Corner, String> {
+        let parts: Vec<&str> = token.split('/').collect();
+        if parts.len() > 3 {
+            return Err(format!("malformed face vertex '{}'", token));
+        }
+        let vertex = resolve_index(parts[0], self.positions.len(), "vertex")?;
+        if parts.len() > 1 && !parts[1].is_empty() {
+            resolve_index(parts[1], self.texcoord_count, "texture coordinate")?;
+        }
+        let normal = match parts.get(2) {
+            Some(part) if !part.is_empty() => {
+                Some(resolve_index(part, self.normals.len(), "normal")?)
+            }
+            _ => None,
+        };
+        Ok((vertex, normal))
+    }
+
+    fn parse_line(&mut self, keyword: &str, args: &[&str]) -> Result<(), String> {
+        match keyword {
+            "v" => {
+                let values = parse_floats(keyword, args)?;
+                // Allow an optional w component, or the common x y z r g b extension
+                if !(values.len() == 3 || values.len() == 4 || values.len() == 6) {
+                    return Err(format!("'v' expects 3 coordinates, got {}", values.len()));
+                }
+                self.positions
+                    .push(na::Point3::new(values[0], values[1], values[2]));
+            }
+            "vn" => {
+                let values = parse_floats(keyword, args)?;
+                if values.len() != 3 {
+                    return Err(format!("'vn' expects 3 components, got {}", values.len()));
+                }
+                self.normals
+                    .push(na::Vector3::new(values[0], values[1], values[2]));
+            }
+            "vt" => {
+                let values = parse_floats(keyword, args)?;
+                if values.is_empty() || values.len() > 3 {
+                    return Err(format!(
+                        "'vt' expects 1 to 3 components, got {}",
+                        values.len()
+                    ));
+                }
+                self.texcoord_count += 1;
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(format!(
+                        "face needs at least 3 vertices, got {}",
+                        args.len()
+                    ));
+                }
+                let corners = args
+                    .iter()
+                    .map(|token| self.parse_corner(token))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // Triangulate polygons as a fan around the first corner
+                let builder = self.current_builder();
+                for idx in 1..corners.len() - 1 {
+                    builder
+                        .faces
+                        .push([corners[0], corners[idx], corners[idx + 1]]);
+                }
+            }
+            "o" => {
+                if args.is_empty() {
+                    return Err("'o' statement needs a name".to_string());
+                }
+                self.object = Some(args.join(" "));
+                self.group = None;
+            }
+            "g" => {
+                self.group = if args.is_empty() {
+                    None
+                } else {
+                    Some(args.join(" "))
+                };
+            }
+            "usemtl" => {
+                if args.len() != 1 {
+                    return Err("'usemtl' expects a single material name".to_string());
+                }
+                self.material = Some(args[0].to_string());
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err("'mtllib' needs at least one file name".to_string());
+                }
+                self.material_libraries
+                    .extend(args.iter().map(|arg| arg.to_string()));
+            }
+            "s" => {}
+            _ => warn!("Ignoring unsupported OBJ statement '{}'", keyword),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ObjModel {
+        let mut name_counts: HashMap<String, usize> = HashMap::new();
+        for builder in self.builders.iter().filter(|b| !b.faces.is_empty()) {
+            *name_counts.entry(builder.name.clone()).or_insert(0) += 1;
+        }
+
+        let positions = self.positions;
+        let normals = self.normals;
+        let groups = self
+            .builders
+            .into_iter()
+            .filter(|builder| !builder.faces.is_empty())
+            .map(|builder| {
+                let (mut name, material, mesh) = builder.build(&positions, &normals);
+                // Groups that switch material are split into one entry per material
+                if name_counts[&name] > 1 {
+                    name = format!("{}:{}", name, material.as_deref().unwrap_or("default"));
+                }
+                ObjGroup {
+                    name,
+                    material,
+                    mesh,
+                }
+            })
+            .collect();
+
+        ObjModel {
+            groups,
+            material_libraries: self.material_libraries,
+            materials: HashMap::new(),
+        }
+    }
+}
+
+/// Parses OBJ statements from `reader`. `path` is only used for error
+/// messages; referenced material libraries are listed but not loaded.
+pub fn parse_obj<R: BufRead>(reader: R, path: &Path) -> Result<ObjModel, ObjError> {
+    let mut parser = ObjParser::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line.map_err(|source| ObjError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if let Some((keyword, args)) = tokenize(&line) {
+            parser
+                .parse_line(keyword, &args)
+                .map_err(|message| ObjError::Parse {
+                    path: path.to_path_buf(),
+                    line: idx + 1,
+                    message,
+                })?;
+        }
+    }
+    Ok(parser.finish())
+}
+
+/// Parses MTL statements from `reader` into surfaces keyed by material name.
+pub fn parse_mtl<R: BufRead>(reader: R, path: &Path) -> Result<HashMap<String, Surface>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Surface)> = None;
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line.map_err(|source| ObjError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let (keyword, args) = match tokenize(&line) {
+            Some(tokens) => tokens,
+            None => continue,
+        };
+        let parse_error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: idx + 1,
+            message,
+        };
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(parse_error(
+                    "'newmtl' expects a single material name".to_string(),
+                ));
+            }
+            if let Some((name, surface)) = current.take() {
+                materials.insert(name, surface);
+            }
+            current = Some((args[0].to_string(), Surface::default()));
+            continue;
+        }
+
+        let surface = match &mut current {
+            Some((_, surface)) => surface,
+            None => {
+                return Err(parse_error(format!(
+                    "'{}' statement before any 'newmtl'",
+                    keyword
+                )))
+            }
+        };
+        let result = match keyword {
+            "Kd" => parse_vector(keyword, &args).map(|v| surface.diffuse = v),
+            "Ks" => parse_vector(keyword, &args).map(|v| surface.specular = v),
+            "Ke" => parse_vector(keyword, &args).map(|v| surface.emission = v),
+            "Ns" => parse_scalar(keyword, &args).map(|v| surface.shininess = v),
+            "Ni" => parse_scalar(keyword, &args).map(|v| surface.ior = v),
+            "d" => parse_scalar(keyword, &args).map(|v| surface.opacity = v),
+            "Tr" => parse_scalar(keyword, &args).map(|v| surface.opacity = 1.0 - v),
+            "Ka" | "Tf" => parse_vector(keyword, &args).map(|_| ()),
+            "illum" => parse_scalar(keyword, &args).map(|_| ()),
+            _ => {
+                warn!("Ignoring unsupported MTL statement '{}'", keyword);
+                Ok(())
+            }
+        };
+        result.map_err(parse_error)?;
+    }
+    if let Some((name, surface)) = current {
+        materials.insert(name, surface);
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn parse(source: &str) -> Result<ObjModel, ObjError> {
+        parse_obj(source.as_bytes(), Path::new("test.obj"))
+    }
+
+    fn parse_error(source: &str) -> (usize, String) {
+        match parse(source) {
+            Err(ObjError::Parse { line, message, .. }) => (line, message),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Parsing should fail:\n{}", source),
+        }
+    }
+
+    const QUADS: &str = "
+        # Two quads in different groups
+        mtllib quads.mtl
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vn 0 0 1
+        o quads
+        g front
+        usemtl red
+        f 1//1 2//1 3//1 4//1
+        g back
+        usemtl blue
+        f -1 -2 -3
+    ";
+
+    #[test]
+    fn groups_become_named_meshes() {
+        let model = parse(QUADS).unwrap();
+        assert_eq!(model.material_libraries, vec!["quads.mtl"]);
+        assert_eq!(model.groups.len(), 2);
+
+        let front = &model.groups[0];
+        assert_eq!(front.name, "quads/front");
+        assert_eq!(front.material.as_deref(), Some("red"));
+        assert_eq!(front.mesh.triangle_count(), 2);
+        assert!(front.mesh.normals.is_some());
+
+        let back = &model.groups[1];
+        assert_eq!(back.name, "quads/back");
+        assert_eq!(back.material.as_deref(), Some("blue"));
+        assert_eq!(back.mesh.indices, vec![[0, 1, 2]]);
+        assert!(back.mesh.normals.is_none());
+        assert!(relative_eq!(
+            back.mesh.vertices[0],
+            na::Point3::new(0.0, 1.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn material_switch_splits_group() {
+        let model =
+            parse("v 0 0 0\nv 1 0 0\nv 0 1 0\ng part\nusemtl a\nf 1 2 3\nusemtl b\nf 1 3 2\n")
+                .unwrap();
+        let names: Vec<&str> = model.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["part:a", "part:b"]);
+    }
+
+    #[test]
+    fn added_to_scene() {
+        let mut model = parse(QUADS).unwrap();
+        model.materials = parse_mtl(
+            "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n".as_bytes(),
+            Path::new("quads.mtl"),
+        )
+        .unwrap();
+
+        let mut scene = Scene::new();
+        let pose = na::Isometry3::translation(0.0, 0.0, -5.0);
+        let names = model.add_to_scene(&mut scene, &pose).unwrap();
+        assert_eq!(names, vec!["quads/front", "quads/back"]);
+
+        let front = scene.get_shape("quads/front").unwrap();
+        let ray = crate::geometry::Ray {
+            origin: na::Point3::new(0.25, 0.25, 0.0),
+            direction: na::Vector3::new(0.0, 0.0, -1.0),
+        };
+        let hit = front.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(hit.near, na::Point3::new(0.25, 0.25, -5.0)));
+
+        let hit = scene.ray_cast(&ray).unwrap();
+        assert_eq!(hit.shape.as_deref(), Some("quads/front"));
+        assert_eq!(
+            scene.material_of(&hit).diffuse(&hit),
+            na::Vector3::new(1.0, 0.0, 0.0)
+        );
+
+        // Adding the model again would replace its shapes
+        let err = parse(QUADS)
+            .unwrap()
+            .add_to_scene(&mut scene, &pose)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "duplicate shape name 'quads/front'");
+        assert_eq!(scene.shapes.len(), 2);
+
+        // A group named like the split of another, which has no material
+        let model = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\ng part\nf 1 2 3\nusemtl default\nf 1 3 2\n\
+             g part:default\nf 1 2 3\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = model.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["part:default", "part:default", "part:default"]);
+        let err = model.add_to_scene(&mut Scene::new(), &pose).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate shape name 'part:default'");
+    }
+
+    #[test]
+    fn missing_material_library_is_skipped() {
+        let dir = std::env::temp_dir().join(format!("raymundo-obj-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("quads.obj");
+        std::fs::write(&path, QUADS).unwrap();
+        let model = load_obj(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let model = model.unwrap();
+        assert_eq!(model.groups.len(), 2);
+        assert!(model.materials.is_empty());
+    }
+
+    #[test]
+    fn mtl_properties() {
+        let materials = parse_mtl(
+            "
+            newmtl glass
+            Ka 0 0 0
+            Kd 0.1 0.2 0.3
+            Ks 1
+            Ns 250
+            Ni 1.5
+            d 0.25
+            illum 7
+            newmtl lamp
+            Ke 4 4 4
+            "
+            .as_bytes(),
+            Path::new("test.mtl"),
+        )
+        .unwrap();
+
+        let glass = &materials["glass"];
+        assert_eq!(glass.diffuse, na::Vector3::new(0.1, 0.2, 0.3));
+        assert_eq!(glass.specular, na::Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(glass.shininess, 250.0);
+        assert_eq!(glass.ior, 1.5);
+        assert_eq!(glass.opacity, 0.25);
+        assert_eq!(materials["lamp"].emission, na::Vector3::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn malformed_lines_report_location() {
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 zero 0\n"),
+            (2, "invalid number 'zero' in 'v' statement".to_string())
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 0 0\nf 1 2\n"),
+            (3, "face needs at least 3 vertices, got 2".to_string())
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"),
+            (
+                4,
+                "vertex index 4 is out of range (3 defined so far)".to_string()
+            )
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n"),
+            (4, "vertex index must not be 0".to_string())
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//1 2//1 3//1\n"),
+            (
+                4,
+                "normal index 1 is out of range (0 defined so far)".to_string()
+            )
+        );
+
+        let err = parse_mtl("Kd 1 1 1\n".as_bytes(), Path::new("test.mtl")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test.mtl:1: 'Kd' statement before any 'newmtl'"
+        );
+    }
+}
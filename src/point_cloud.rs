@@ -0,0 +1,1044 @@
+extern crate image;
+extern crate nalgebra as na;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::graphics::GraphicsContext;
+
+/// Points with optional per-point attributes, each attribute holding one
+/// value per point.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointCloud {
+    pub positions: Vec<na::Point3<f32>>,
+    /// Width and height of an organized cloud, whose points are laid out in
+    /// rows like the pixels of the image they were measured in. Points
+    /// without a measurement are NaN.
+    pub dimensions: Option<(u32, u32)>,
+    pub colors: Option<Vec<[u8; 3]>>,
+    /// Strength of each return, e.g. of a lidar.
+    pub intensities: Option<Vec<f32>>,
+    /// Index of the lidar channel that measured each point.
+    pub rings: Option<Vec<u16>>,
+    /// Angle of each point around the sensor's vertical axis, in radians.
+    pub azimuths: Option<Vec<f32>>,
+}
+
+/// File formats for point clouds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloudFormat {
+    /// The Stanford polygon format. Colours are `red`, `green` and `blue`
+    /// bytes, and organized clouds note their size as `obj_info width` and
+    /// `obj_info height` in the header.
+    Ply,
+    /// The Point Cloud Library's format, with colours packed into an `rgb`
+    /// float as PCL does.
+    Pcd,
+}
+
+impl CloudFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ply" => Some(CloudFormat::Ply),
+            "pcd" => Some(CloudFormat::Pcd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    /// Little-endian binary.
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum CloudError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A malformed file, or one using features the reader lacks, e.g. big
+    /// endian or compressed data.
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    /// A depth AOV with a pixel count other than that of the camera.
+    Resolution {
+        pixels: usize,
+        camera: (u32, u32),
+    },
+}
+
+impl fmt::Display for CloudError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CloudError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            CloudError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            CloudError::Resolution { pixels, camera } => write!(
+                f,
+                "depth has {} pixels but the camera renders {}x{}",
+                pixels, camera.0, camera.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CloudError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CloudError::Io { source, .. } => Some(source),
+            CloudError::Parse { .. } | CloudError::Resolution { .. } => None,
+        }
+    }
+}
+
+/// A value to write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    Float(f32),
+    UShort(u16),
+    UChar(u8),
+    /// A colour as 0x00RRGGBB, stored in the bits of a float.
+    Packed(u32),
+}
+
+impl Scalar {
+    fn ply_type(self) -> &'static str {
+        match self {
+            Scalar::Float(_) => "float",
+            Scalar::UShort(_) => "ushort",
+            Scalar::UChar(_) => "uchar",
+            Scalar::Packed(_) => "uint",
+        }
+    }
+
+    /// Size and type as in a PCD header.
+    fn pcd_type(self) -> (usize, char) {
+        match self {
+            Scalar::Float(_) | Scalar::Packed(_) => (4, 'F'),
+            Scalar::UShort(_) => (2, 'U'),
+            Scalar::UChar(_) => (1, 'U'),
+        }
+    }
+
+    fn write_binary<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Scalar::Float(value) => writer.write_all(&value.to_le_bytes()),
+            Scalar::UShort(value) => writer.write_all(&value.to_le_bytes()),
+            Scalar::UChar(value) => writer.write_all(&[value]),
+            Scalar::Packed(value) => writer.write_all(&value.to_le_bytes()),
+        }
+    }
+
+    /// Text of the value, with packed colours as integers like PCL writes
+    /// them.
+    fn to_ascii(self) -> String {
+        match self {
+            Scalar::Float(value) if value.is_nan() => "nan".to_string(),
+            Scalar::Float(value) => value.to_string(),
+            Scalar::UShort(value) => value.to_string(),
+            Scalar::UChar(value) => value.to_string(),
+            Scalar::Packed(value) => value.to_string(),
+        }
+    }
+}
+
+/// Type of a field of a file being read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+    /// A PCD colour, read as the integer in the float's bits.
+    Packed,
+}
+
+impl FieldType {
+    fn from_ply(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => FieldType::I8,
+            "uchar" | "uint8" => FieldType::U8,
+            "short" | "int16" => FieldType::I16,
+            "ushort" | "uint16" => FieldType::U16,
+            "int" | "int32" => FieldType::I32,
+            "uint" | "uint32" => FieldType::U32,
+            "float" | "float32" => FieldType::F32,
+            "double" | "float64" => FieldType::F64,
+            _ => return None,
+        })
+    }
+
+    fn from_pcd(kind: &str, size: &str) -> Option<Self> {
+        Some(match (kind, size) {
+            ("I", "1") => FieldType::I8,
+            ("U", "1") => FieldType::U8,
+            ("I", "2") => FieldType::I16,
+            ("U", "2") => FieldType::U16,
+            ("I", "4") => FieldType::I32,
+            ("U", "4") => FieldType::U32,
+            ("F", "4") => FieldType::F32,
+            ("F", "8") => FieldType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            FieldType::I8 | FieldType::U8 => 1,
+            FieldType::I16 | FieldType::U16 => 2,
+            FieldType::I32 | FieldType::U32 | FieldType::F32 | FieldType::Packed => 4,
+            FieldType::F64 => 8,
+        }
+    }
+
+    /// Value of little-endian `bytes`, `size` of them.
+    fn decode(self, bytes: &[u8]) -> f64 {
+        let mut buf = [0; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let word = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        match self {
+            FieldType::I8 => f64::from(buf[0] as i8),
+            FieldType::U8 => f64::from(buf[0]),
+            FieldType::I16 => f64::from(i16::from_le_bytes([buf[0], buf[1]])),
+            FieldType::U16 => f64::from(u16::from_le_bytes([buf[0], buf[1]])),
+            FieldType::I32 => f64::from(word as i32),
+            FieldType::U32 | FieldType::Packed => f64::from(word),
+            FieldType::F32 => f64::from(f32::from_bits(word)),
+            FieldType::F64 => f64::from_le_bytes(buf),
+        }
+    }
+
+    fn parse(self, text: &str) -> Option<f64> {
+        match self {
+            // Floats are parsed at their own precision, to round-trip exactly
+            FieldType::F32 => text.parse::<f32>().ok().map(f64::from),
+            FieldType::F64 => text.parse().ok(),
+            FieldType::Packed => text
+                .parse::<u32>()
+                .or_else(|_| text.parse::<f32>().map(f32::to_bits))
+                .ok()
+                .map(f64::from),
+            _ => text.parse::<i64>().ok().map(|value| value as f64),
+        }
+    }
+}
+
+/// Most values a single PCD field may hold, to bound what a header can ask
+/// the reader to allocate.
+const MAX_FIELD_COUNT: usize = 1 << 16;
+
+/// What a header says about the points that follow it.
+struct Header {
+    fields: Vec<(String, FieldType)>,
+    points: usize,
+    encoding: Encoding,
+    dimensions: Option<(u32, u32)>,
+}
+
+fn pack_color(color: &[u8; 3]) -> u32 {
+    (u32::from(color[0]) << 16) | (u32::from(color[1]) << 8) | u32::from(color[2])
+}
+
+fn unpack_color(packed: u32) -> [u8; 3] {
+    [(packed >> 16) as u8, (packed >> 8) as u8, packed as u8]
+}
+
+impl PointCloud {
+    /// Organized cloud of the pixels of `depth`, a depth AOV of the frame
+    /// seen by the camera of `context`, coloured by its image. Points are
+    /// in the camera's frame, and NaN where the depth is not finite. Fails if
+    /// `depth` does not have a value per pixel of the camera.
+    pub fn from_depth(depth: &[f32], context: &GraphicsContext) -> Result<Self, CloudError> {
+        let camera = context.camera.as_ref();
+        let (width, height) = camera.resolution();
+        if depth.len() != width as usize * height as usize {
+            return Err(CloudError::Resolution {
+                pixels: depth.len(),
+                camera: (width, height),
+            });
+        }
+        let world_to_camera = camera.pose().inverse();
+        let lens = na::Point2::new(0.5, 0.5);
+        let mut positions = Vec::with_capacity(depth.len());
+        let mut colors = Vec::with_capacity(depth.len());
+        for y in 0..height {
+            for x in 0..width {
+                let z = depth[(y * width + x) as usize];
+                let film = na::Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let ray = camera.generate_ray(&film, &lens);
+                let origin = world_to_camera * ray.origin;
+                let direction = world_to_camera * ray.direction;
+                positions.push(if z.is_finite() && direction.z > 0.0 {
+                    origin + direction * ((z - origin.z) / direction.z)
+                } else {
+                    na::Point3::new(f32::NAN, f32::NAN, f32::NAN)
+                });
+                colors.push(if x < context.img_width && y < context.img_height {
+                    context.imgbuf.get_pixel(x, y).0
+                } else {
+                    [0, 0, 0]
+                });
+            }
+        }
+        Ok(PointCloud {
+            positions,
+            dimensions: Some((width, height)),
+            colors: Some(colors),
+            ..Default::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Width and height as in a PCD header, a single row if the cloud is
+    /// not organized.
+    fn width_and_height(&self) -> (usize, usize) {
+        match self.dimensions {
+            Some((width, height)) => (width as usize, height as usize),
+            None => (self.len(), 1),
+        }
+    }
+
+    /// The cloud with every point moved by `transform`, e.g. from the
+    /// sensor's frame to the world.
+    pub fn transformed(&self, transform: &na::Isometry3<f32>) -> Self {
+        PointCloud {
+            positions: self.positions.iter().map(|p| transform * p).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Names and values of the fields of point `idx` in `format`, in file
+    /// order.
+    fn fields(&self, idx: usize, format: CloudFormat) -> Vec<(&'static str, Scalar)> {
+        let p = &self.positions[idx];
+        let mut fields = vec![
+            ("x", Scalar::Float(p.x)),
+            ("y", Scalar::Float(p.y)),
+            ("z", Scalar::Float(p.z)),
+        ];
+        if let Some(colors) = &self.colors {
+            let color = colors[idx];
+            match format {
+                CloudFormat::Ply => fields.extend_from_slice(&[
+                    ("red", Scalar::UChar(color[0])),
+                    ("green", Scalar::UChar(color[1])),
+                    ("blue", Scalar::UChar(color[2])),
+                ]),
+                CloudFormat::Pcd => fields.push(("rgb", Scalar::Packed(pack_color(&color)))),
+            }
+        }
+        if let Some(intensities) = &self.intensities {
+            fields.push(("intensity", Scalar::Float(intensities[idx])));
+        }
+        if let Some(rings) = &self.rings {
+            fields.push(("ring", Scalar::UShort(rings[idx])));
+        }
+        if let Some(azimuths) = &self.azimuths {
+            fields.push(("azimuth", Scalar::Float(azimuths[idx])));
+        }
+        fields
+    }
+
+    /// Names and types of the fields in `format`, taken from a sample point.
+    fn field_types(&self, format: CloudFormat) -> Vec<(&'static str, Scalar)> {
+        PointCloud {
+            positions: vec![na::Point3::origin()],
+            dimensions: None,
+            colors: self.colors.as_ref().map(|_| vec![[0; 3]]),
+            intensities: self.intensities.as_ref().map(|_| vec![0.0]),
+            rings: self.rings.as_ref().map(|_| vec![0]),
+            azimuths: self.azimuths.as_ref().map(|_| vec![0.0]),
+        }
+        .fields(0, format)
+    }
+
+    fn write_points<W: Write>(
+        &self,
+        writer: &mut W,
+        format: CloudFormat,
+        encoding: Encoding,
+    ) -> io::Result<()> {
+        for idx in 0..self.len() {
+            let fields = self.fields(idx, format);
+            match encoding {
+                Encoding::Binary => {
+                    for (_, value) in fields {
+                        value.write_binary(writer)?;
+                    }
+                }
+                Encoding::Ascii => {
+                    let line: Vec<String> = fields
+                        .into_iter()
+                        .map(|(_, value)| value.to_ascii())
+                        .collect();
+                    writeln!(writer, "{}", line.join(" "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_ply<W: Write>(&self, writer: &mut W, encoding: Encoding) -> io::Result<()> {
+        writeln!(writer, "ply")?;
+        match encoding {
+            Encoding::Ascii => writeln!(writer, "format ascii 1.0")?,
+            Encoding::Binary => writeln!(writer, "format binary_little_endian 1.0")?,
+        }
+        if let Some((width, height)) = self.dimensions {
+            writeln!(writer, "obj_info width {}", width)?;
+            writeln!(writer, "obj_info height {}", height)?;
+        }
+        writeln!(writer, "element vertex {}", self.len())?;
+        for (name, kind) in self.field_types(CloudFormat::Ply) {
+            writeln!(writer, "property {} {}", kind.ply_type(), name)?;
+        }
+        writeln!(writer, "end_header")?;
+        self.write_points(writer, CloudFormat::Ply, encoding)
+    }
+
+    pub fn write_pcd<W: Write>(&self, writer: &mut W, encoding: Encoding) -> io::Result<()> {
+        let fields = self.field_types(CloudFormat::Pcd);
+        let column = |value: &dyn Fn(Scalar) -> String| {
+            let values: Vec<String> = fields.iter().map(|(_, kind)| value(*kind)).collect();
+            values.join(" ")
+        };
+        let names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        let (width, height) = self.width_and_height();
+        writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+        writeln!(writer, "VERSION 0.7")?;
+        writeln!(writer, "FIELDS {}", names.join(" "))?;
+        writeln!(writer, "SIZE {}", column(&|t| t.pcd_type().0.to_string()))?;
+        writeln!(writer, "TYPE {}", column(&|t| t.pcd_type().1.to_string()))?;
+        writeln!(writer, "COUNT {}", column(&|_| "1".to_string()))?;
+        writeln!(writer, "WIDTH {}", width)?;
+        writeln!(writer, "HEIGHT {}", height)?;
+        writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+        writeln!(writer, "POINTS {}", self.len())?;
+        match encoding {
+            Encoding::Ascii => writeln!(writer, "DATA ascii")?,
+            Encoding::Binary => writeln!(writer, "DATA binary")?,
+        }
+        self.write_points(writer, CloudFormat::Pcd, encoding)
+    }
+
+    pub fn save(&self, path: &Path, format: CloudFormat, encoding: Encoding) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            CloudFormat::Ply => self.write_ply(&mut writer, encoding)?,
+            CloudFormat::Pcd => self.write_pcd(&mut writer, encoding)?,
+        }
+        writer.flush()
+    }
+
+    /// Loads a PLY or PCD file as written by `save`, telling the format
+    /// from the start of the file. Fields other than those of `PointCloud`
+    /// are skipped.
+    pub fn load(path: &Path) -> Result<Self, CloudError> {
+        let file = File::open(path).map_err(io_error(path))?;
+        PointCloud::read(&mut BufReader::new(file), path)
+    }
+
+    /// Reads a PLY or PCD file. `path` is used for error messages.
+    pub fn read<R: BufRead>(reader: &mut R, path: &Path) -> Result<Self, CloudError> {
+        let mut reader = CloudReader { reader, path };
+        let start = reader.reader.fill_buf().map_err(io_error(reader.path))?;
+        let header = if start.starts_with(b"ply") {
+            reader.ply_header()?
+        } else {
+            reader.pcd_header()?
+        };
+        if header.fields.is_empty() {
+            return Err(reader.invalid("points have no fields".to_string()));
+        }
+        reader.body(&header)
+    }
+}
+
+fn io_error(path: &Path) -> impl Fn(io::Error) -> CloudError + '_ {
+    move |source| CloudError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+struct CloudReader<'a, R> {
+    reader: &'a mut R,
+    path: &'a Path,
+}
+
+impl<'a, R: BufRead> CloudReader<'a, R> {
+    fn invalid(&self, message: String) -> CloudError {
+        CloudError::Parse {
+            path: self.path.to_path_buf(),
+            message,
+        }
+    }
+
+    /// Next header line split into words, skipping blank ones.
+    fn header_line(&mut self) -> Result<Vec<String>, CloudError> {
+        loop {
+            let mut line = String::new();
+            if self
+                .reader
+                .read_line(&mut line)
+                .map_err(io_error(self.path))?
+                == 0
+            {
+                return Err(self.invalid("header ends early".to_string()));
+            }
+            let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+            if !words.is_empty() {
+                return Ok(words);
+            }
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self, words: &[String], idx: usize) -> Result<T, CloudError> {
+        words
+            .get(idx)
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| self.invalid(format!("bad header line '{}'", words.join(" "))))
+    }
+
+    fn ply_header(&mut self) -> Result<Header, CloudError> {
+        let mut header = Header {
+            fields: Vec::new(),
+            points: 0,
+            encoding: Encoding::Ascii,
+            dimensions: None,
+        };
+        let (mut width, mut height) = (None, None);
+        // Only the vertex element is read, which must come first
+        let mut element = None;
+        self.header_line()?;
+        loop {
+            let words = self.header_line()?;
+            match words[0].as_str() {
+                "format" => {
+                    header.encoding = match words.get(1).map(String::as_str) {
+                        Some("ascii") => Encoding::Ascii,
+                        Some("binary_little_endian") => Encoding::Binary,
+                        _ => return Err(self.invalid(format!("unsupported {}", words.join(" ")))),
+                    }
+                }
+                "obj_info" if words.get(1).map(String::as_str) == Some("width") => {
+                    width = Some(self.number(&words, 2)?)
+                }
+                "obj_info" if words.get(1).map(String::as_str) == Some("height") => {
+                    height = Some(self.number(&words, 2)?)
+                }
+                "element" => {
+                    let name = words.get(1).cloned().unwrap_or_default();
+                    if element.is_none() && name != "vertex" {
+                        return Err(self.invalid("vertices must be the first element".to_string()));
+                    }
+                    if name == "vertex" {
+                        header.points = self.number(&words, 2)?;
+                    }
+                    element = Some(name);
+                }
+                "property" if element.as_deref() == Some("vertex") => {
+                    let kind = words.get(1).and_then(|kind| FieldType::from_ply(kind));
+                    match (kind, words.get(2)) {
+                        (Some(kind), Some(name)) => header.fields.push((name.clone(), kind)),
+                        _ => return Err(self.invalid(format!("unsupported {}", words.join(" ")))),
+                    }
+                }
+                "end_header" => break,
+                _ => {}
+            }
+        }
+        if let (Some(width), Some(height)) = (width, height) {
+            header.dimensions = Some((width, height));
+        }
+        Ok(header)
+    }
+
+    fn pcd_header(&mut self) -> Result<Header, CloudError> {
+        let mut names = Vec::new();
+        let (mut sizes, mut kinds, mut counts) = (Vec::new(), Vec::new(), Vec::new());
+        let (mut width, mut height, mut points) = (None, 1, None);
+        let encoding = loop {
+            let words = self.header_line()?;
+            let values = words[1..].to_vec();
+            match words[0].as_str() {
+                "FIELDS" => names = values,
+                "SIZE" => sizes = values,
+                "TYPE" => kinds = values,
+                "COUNT" => counts = values,
+                "WIDTH" => width = Some(self.number(&words, 1)?),
+                "HEIGHT" => height = self.number(&words, 1)?,
+                "POINTS" => points = Some(self.number(&words, 1)?),
+                "DATA" => match words.get(1).map(String::as_str) {
+                    Some("ascii") => break Encoding::Ascii,
+                    Some("binary") => break Encoding::Binary,
+                    _ => return Err(self.invalid(format!("unsupported {}", words.join(" ")))),
+                },
+                _ => {}
+            }
+        };
+        if sizes.len() != names.len() || kinds.len() != names.len() {
+            return Err(self.invalid("FIELDS, SIZE and TYPE differ in length".to_string()));
+        }
+
+        let mut fields = Vec::new();
+        for (idx, name) in names.iter().enumerate() {
+            let kind = match FieldType::from_pcd(&kinds[idx], &sizes[idx]) {
+                Some(FieldType::F32) if name == "rgb" || name == "rgba" => FieldType::Packed,
+                Some(kind) => kind,
+                None => {
+                    return Err(self.invalid(format!(
+                        "field {} has unsupported type {}{}",
+                        name, kinds[idx], sizes[idx]
+                    )))
+                }
+            };
+            let count: usize = counts.get(idx).and_then(|c| c.parse().ok()).unwrap_or(1);
+            if count == 0 || count > MAX_FIELD_COUNT {
+                return Err(self.invalid(format!("field {} has COUNT {}", name, count)));
+            }
+            for _ in 0..count {
+                fields.push((name.clone(), kind));
+            }
+        }
+        let width: u32 = width.ok_or_else(|| self.invalid("WIDTH is missing".to_string()))?;
+        let points = match points {
+            Some(points) => points,
+            None => width
+                .checked_mul(height)
+                .ok_or_else(|| self.invalid("WIDTH by HEIGHT is too large".to_string()))?
+                as usize,
+        };
+        Ok(Header {
+            fields,
+            points,
+            encoding,
+            dimensions: if height > 1 {
+                Some((width, height))
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Values of every field of every point, in file order. Storage grows
+    /// with the data actually read, not with the point count the header
+    /// claims.
+    fn values(&mut self, header: &Header) -> Result<Vec<f64>, CloudError> {
+        let count = header
+            .points
+            .checked_mul(header.fields.len())
+            .ok_or_else(|| self.invalid(format!("{} points are too many", header.points)))?;
+        let mut values = Vec::new();
+        match header.encoding {
+            Encoding::Binary => {
+                let stride: usize = header.fields.iter().map(|(_, kind)| kind.size()).sum();
+                let mut point = vec![0; stride];
+                for _ in 0..header.points {
+                    self.reader
+                        .read_exact(&mut point)
+                        .map_err(io_error(self.path))?;
+                    let mut offset = 0;
+                    for (_, kind) in &header.fields {
+                        values.push(kind.decode(&point[offset..offset + kind.size()]));
+                        offset += kind.size();
+                    }
+                }
+                Ok(values)
+            }
+            Encoding::Ascii => {
+                let mut text = String::new();
+                self.reader
+                    .read_to_string(&mut text)
+                    .map_err(io_error(self.path))?;
+                for (idx, word) in text.split_whitespace().take(count).enumerate() {
+                    let (name, kind) = &header.fields[idx % header.fields.len()];
+                    match kind.parse(word) {
+                        Some(value) => values.push(value),
+                        None => {
+                            return Err(self.invalid(format!("bad value '{}' of {}", word, name)))
+                        }
+                    }
+                }
+                if values.len() < count {
+                    return Err(self.invalid(format!("expected {} points", header.points)));
+                }
+                Ok(values)
+            }
+        }
+    }
+
+    fn body(&mut self, header: &Header) -> Result<PointCloud, CloudError> {
+        let values = self.values(header)?;
+        let stride = header.fields.len();
+        let field = |name: &str| header.fields.iter().position(|(n, _)| n == name);
+        let column =
+            |idx: usize| -> Vec<f64> { values.iter().skip(idx).step_by(stride).cloned().collect() };
+
+        let (x, y, z) = match (field("x"), field("y"), field("z")) {
+            (Some(x), Some(y), Some(z)) => (column(x), column(y), column(z)),
+            _ => return Err(self.invalid("points have no x, y and z".to_string())),
+        };
+        let colors = match (field("red"), field("green"), field("blue"), field("rgb")) {
+            (Some(r), Some(g), Some(b), _) => {
+                let (r, g, b) = (column(r), column(g), column(b));
+                Some(
+                    (0..header.points)
+                        .map(|i| [r[i] as u8, g[i] as u8, b[i] as u8])
+                        .collect(),
+                )
+            }
+            (_, _, _, Some(rgb)) => Some(
+                column(rgb)
+                    .into_iter()
+                    .map(|packed| unpack_color(packed as u32))
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let floats =
+            |name: &str| field(name).map(|idx| column(idx).iter().map(|&v| v as f32).collect());
+        Ok(PointCloud {
+            positions: (0..header.points)
+                .map(|i| na::Point3::new(x[i] as f32, y[i] as f32, z[i] as f32))
+                .collect(),
+            dimensions: header.dimensions.filter(|(width, height)| {
+                width.checked_mul(*height).map(|points| points as usize) == Some(header.points)
+            }),
+            colors,
+            intensities: floats("intensity"),
+            rings: field("ring").map(|idx| column(idx).iter().map(|&v| v as u16).collect()),
+            azimuths: floats("azimuth"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn make_cloud() -> PointCloud {
+        PointCloud {
+            positions: vec![
+                na::Point3::new(1.0, 2.0, 3.0),
+                na::Point3::new(-0.5, 0.0, 4.25),
+            ],
+            intensities: Some(vec![0.5, 1.0]),
+            rings: Some(vec![3, 15]),
+            ..Default::default()
+        }
+    }
+
+    /// A 2 by 2 organized cloud with one point missing and every attribute.
+    fn make_organized_cloud() -> PointCloud {
+        PointCloud {
+            positions: vec![
+                na::Point3::new(1.0, 2.0, 3.0),
+                na::Point3::new(f32::NAN, f32::NAN, f32::NAN),
+                na::Point3::new(-0.1, 0.2, 1.0e-7),
+                na::Point3::new(0.0, -4.5, 100.25),
+            ],
+            dimensions: Some((2, 2)),
+            colors: Some(vec![[255, 0, 0], [0, 0, 0], [1, 2, 3], [200, 100, 50]]),
+            intensities: Some(vec![0.5, 0.0, 0.1, 1.0]),
+            rings: Some(vec![0, 1, 2, 65535]),
+            azimuths: Some(vec![0.0, 1.0, 2.0, 3.0]),
+        }
+    }
+
+    /// Whether the clouds are equal, with NaN positions matching each other.
+    fn same_cloud(a: &PointCloud, b: &PointCloud) -> bool {
+        let nan_to_inf = |cloud: &PointCloud| PointCloud {
+            positions: cloud
+                .positions
+                .iter()
+                .map(|p| {
+                    na::Point3::from(p.coords.map(|c| if c.is_nan() { f32::INFINITY } else { c }))
+                })
+                .collect(),
+            ..cloud.clone()
+        };
+        nan_to_inf(a) == nan_to_inf(b)
+    }
+
+    #[test]
+    fn ply_output() {
+        let cloud = make_cloud();
+        let mut ascii = Vec::new();
+        cloud.write_ply(&mut ascii, Encoding::Ascii).unwrap();
+        assert_eq!(
+            String::from_utf8(ascii).unwrap(),
+            "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\n\
+             property float z\nproperty float intensity\nproperty ushort ring\nend_header\n\
+             1 2 3 0.5 3\n-0.5 0 4.25 1 15\n"
+        );
+
+        let mut binary = Vec::new();
+        cloud.write_ply(&mut binary, Encoding::Binary).unwrap();
+        let header_end = b"end_header\n";
+        let start = binary
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .unwrap()
+            + header_end.len();
+        assert_eq!(binary.len() - start, 2 * (4 * 4 + 2));
+        assert_eq!(&binary[start + 16..start + 18], &3_u16.to_le_bytes());
+        assert_eq!(&binary[start + 26..start + 30], &4.25_f32.to_le_bytes());
+    }
+
+    #[test]
+    fn pcd_output() {
+        let mut cloud = make_cloud();
+        cloud.rings = None;
+        cloud.azimuths = Some(vec![0.0, 3.5]);
+        let mut ascii = Vec::new();
+        cloud.write_pcd(&mut ascii, Encoding::Ascii).unwrap();
+        let text = String::from_utf8(ascii).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[2], "FIELDS x y z intensity azimuth");
+        assert_eq!(lines[3], "SIZE 4 4 4 4 4");
+        assert_eq!(lines[4], "TYPE F F F F F");
+        assert_eq!(lines[9], "POINTS 2");
+        assert_eq!(lines[10], "DATA ascii");
+        assert_eq!(lines[12], "-0.5 0 4.25 1 3.5");
+
+        let mut binary = Vec::new();
+        cloud.write_pcd(&mut binary, Encoding::Binary).unwrap();
+        let data = b"DATA binary\n";
+        let start = binary.windows(data.len()).position(|w| w == data).unwrap() + data.len();
+        assert_eq!(binary.len() - start, 2 * 5 * 4);
+    }
+
+    #[test]
+    fn transform_keeps_attributes() {
+        let cloud = make_cloud();
+        let moved = cloud.transformed(&na::Isometry3::translation(1.0, 0.0, 0.0));
+        assert_eq!(moved.positions[0], na::Point3::new(2.0, 2.0, 3.0));
+        assert_eq!(moved.rings, cloud.rings);
+        assert_eq!(
+            CloudFormat::from_path(Path::new("scan.PCD")),
+            Some(CloudFormat::Pcd)
+        );
+        assert_eq!(CloudFormat::from_path(Path::new("scan.xyz")), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let cloud = make_organized_cloud();
+        for &format in &[CloudFormat::Ply, CloudFormat::Pcd] {
+            for &encoding in &[Encoding::Ascii, Encoding::Binary] {
+                let mut data = Vec::new();
+                match format {
+                    CloudFormat::Ply => cloud.write_ply(&mut data, encoding).unwrap(),
+                    CloudFormat::Pcd => cloud.write_pcd(&mut data, encoding).unwrap(),
+                }
+                let read = PointCloud::read(&mut data.as_slice(), Path::new("test")).unwrap();
+                assert!(
+                    same_cloud(&read, &cloud),
+                    "{:?} {:?}: {:?}",
+                    format,
+                    encoding,
+                    read
+                );
+            }
+        }
+
+        // Unorganized clouds without optional fields
+        let cloud = PointCloud {
+            positions: vec![na::Point3::new(1.0, 2.0, 3.0)],
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        cloud.write_pcd(&mut data, Encoding::Ascii).unwrap();
+        let read = PointCloud::read(&mut data.as_slice(), Path::new("test")).unwrap();
+        assert_eq!(read, cloud);
+    }
+
+    #[test]
+    fn colors_and_organization_in_headers() {
+        let cloud = make_organized_cloud();
+        let mut ply = Vec::new();
+        cloud.write_ply(&mut ply, Encoding::Ascii).unwrap();
+        let text = String::from_utf8(ply).unwrap();
+        assert!(text.contains("obj_info width 2\nobj_info height 2\n"));
+        assert!(text.contains("property uchar red\nproperty uchar green\nproperty uchar blue\n"));
+        assert!(text.contains("\nnan nan nan 0 0 0 0 1 1\n"));
+
+        let mut pcd = Vec::new();
+        cloud.write_pcd(&mut pcd, Encoding::Ascii).unwrap();
+        let text = String::from_utf8(pcd).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[2], "FIELDS x y z rgb intensity ring azimuth");
+        assert_eq!(lines[3], "SIZE 4 4 4 4 4 2 4");
+        assert_eq!(lines[4], "TYPE F F F F F U F");
+        assert_eq!(lines[6], "WIDTH 2");
+        assert_eq!(lines[7], "HEIGHT 2");
+        // Colours are written as the packed integer, like PCL does
+        assert_eq!(lines[11], "1 2 3 16711680 0.5 0 0");
+
+        // PCL may also write the packed colour as a float
+        let pcl = format!(
+            "VERSION 0.7\nFIELDS x y z rgb\nSIZE 4 4 4 4\nTYPE F F F F\nCOUNT 1 1 1 1\n\
+             WIDTH 1\nHEIGHT 1\nPOINTS 1\nDATA ascii\n1 2 3 {}\n",
+            f32::from_bits(0x00c8_6432)
+        );
+        let read = PointCloud::read(&mut pcl.as_bytes(), Path::new("test")).unwrap();
+        assert_eq!(read.colors, Some(vec![[200, 100, 50]]));
+        assert_eq!(read.dimensions, None);
+    }
+
+    #[test]
+    fn unsupported_files() {
+        let read = |text: &str| {
+            PointCloud::read(&mut text.as_bytes(), Path::new("scan.ply"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            read("ply\nformat binary_big_endian 1.0\nend_header\n"),
+            "scan.ply: unsupported format binary_big_endian 1.0"
+        );
+        assert_eq!(
+            read("ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n1\n"),
+            "scan.ply: points have no x, y and z"
+        );
+        assert_eq!(
+            read("ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n1\n"),
+            "scan.ply: expected 2 points"
+        );
+        assert_eq!(
+            read("VERSION 0.7\nFIELDS x\nSIZE 4\nTYPE F\nWIDTH 1\nDATA binary_compressed\n"),
+            "scan.ply: unsupported DATA binary_compressed"
+        );
+        assert!(PointCloud::load(Path::new("/nonexistent/scan.ply")).is_err());
+
+        // Headers that would otherwise panic or allocate without bound
+        assert_eq!(
+            read("ply\nformat binary_little_endian 1.0\nelement vertex 1\nend_header\n"),
+            "scan.ply: points have no fields"
+        );
+        assert_eq!(
+            read("FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nWIDTH 100000\nHEIGHT 100000\nDATA ascii\n"),
+            "scan.ply: WIDTH by HEIGHT is too large"
+        );
+        assert_eq!(
+            read(
+                "FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nCOUNT 1 1 4000000000\nWIDTH 1\nDATA ascii\n"
+            ),
+            "scan.ply: field z has COUNT 4000000000"
+        );
+        assert!(read(&format!(
+            "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\n\
+             property float y\nproperty float z\nend_header\n",
+            usize::MAX / 2
+        ))
+        .contains("too many"));
+        assert!(read(
+            "ply\nformat binary_little_endian 1.0\nelement vertex 1000000000\n\
+             property float x\nproperty float y\nproperty float z\nend_header\n"
+        )
+        .contains("failed to fill whole buffer"));
+    }
+
+    #[test]
+    fn depth_cloud_reprojects_onto_its_pixels() {
+        use crate::aov::render_aovs;
+        use crate::camera::PerspectiveCamera;
+        use crate::graphics::draw_point_cloud;
+        use crate::scene::Scene;
+        use crate::shape;
+
+        let mut scene = Scene::new();
+        scene.add_shape(
+            "ball",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(0.0, 0.0, 1.0),
+                radius: 1.0,
+            }),
+        );
+        let camera = PerspectiveCamera::look_at(
+            &na::Point3::new(1.0, -4.0, 3.0),
+            &na::Point3::new(0.0, 0.0, 1.0),
+            &na::Vector3::z(),
+            std::f32::consts::FRAC_PI_3,
+            40,
+            30,
+        );
+        let aovs = render_aovs(&scene, &camera);
+        let mut context = GraphicsContext::new(Box::new(camera));
+        context.imgbuf = image::RgbImage::from_fn(40, 30, |x, y| image::Rgb([x as u8, y as u8, 7]));
+
+        let cloud = PointCloud::from_depth(&aovs.depth, &context).unwrap();
+        assert_eq!(
+            PointCloud::from_depth(&aovs.depth[..40 * 29], &context)
+                .unwrap_err()
+                .to_string(),
+            "depth has 1160 pixels but the camera renders 40x30"
+        );
+        assert_eq!(cloud.dimensions, Some((40, 30)));
+        assert_eq!(cloud.colors.as_ref().unwrap()[31], [31, 0, 7]);
+        // Camera frame points keep their depth, world frame ones their
+        // position on the ball
+        let world = cloud.transformed(context.camera.pose());
+        for idx in 0..cloud.len() {
+            if aovs.object_id[idx] == 0 {
+                assert!(cloud.positions[idx].x.is_nan());
+                continue;
+            }
+            assert!(relative_eq!(
+                cloud.positions[idx].z,
+                aovs.depth[idx],
+                epsilon = 1.0e-4
+            ));
+            assert!(relative_eq!(
+                world.positions[idx],
+                aovs.position[idx],
+                epsilon = 1.0e-3
+            ));
+        }
+
+        // Saved and loaded, every point lands on the pixel it came from
+        let mut data = Vec::new();
+        world.write_ply(&mut data, Encoding::Binary).unwrap();
+        let loaded = PointCloud::read(&mut data.as_slice(), Path::new("test")).unwrap();
+        context.imgbuf = image::RgbImage::new(40, 30);
+        let drawn = draw_point_cloud(
+            &loaded,
+            &na::Isometry3::identity(),
+            image::Rgb([0, 0, 0]),
+            &mut context,
+        );
+        let hits = aovs.object_id.iter().filter(|&&id| id != 0).count();
+        assert!(hits > 100);
+        assert_eq!(drawn, hits);
+        for (idx, &id) in aovs.object_id.iter().enumerate() {
+            let (x, y) = (idx as u32 % 40, idx as u32 / 40);
+            let expected = if id == 0 {
+                [0, 0, 0]
+            } else {
+                [x as u8, y as u8, 7]
+            };
+            assert_eq!(context.imgbuf.get_pixel(x, y).0, expected);
+        }
+    }
+}
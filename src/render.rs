@@ -0,0 +1,335 @@
+extern crate image;
+extern crate log;
+extern crate nalgebra as na;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use log::info;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::filter::{BoxFilter, Filter};
+use crate::integrator::{Integrator, Whitted};
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::tonemap::ToneMapping;
+
+pub struct RenderSettings {
+    /// Number of worker threads, at least one.
+    pub threads: usize,
+    /// Edge length of the square tiles the frame is split into, in pixels.
+    pub tile_size: u32,
+    /// Computes the colour seen along each camera ray, e.g. `Whitted` for
+    /// direct lighting with mirrors and glass, or `PathTracer` for global
+    /// illumination.
+    pub integrator: Box<dyn Integrator>,
+    /// Number of radiance estimates per pixel, at least one.
+    pub samples_per_pixel: u32,
+    /// Weights the samples around each pixel to reconstruct the image.
+    pub filter: Box<dyn Filter>,
+    /// Generates the sample values for positions in the pixel, on the lens
+    /// and on lights. Renders with samplers of the same kind and seed are
+    /// identical.
+    pub sampler: Box<dyn Sampler>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            tile_size: 32,
+            integrator: Box::new(Whitted::default()),
+            samples_per_pixel: 1,
+            filter: Box::new(BoxFilter::default()),
+            sampler: Box::new(IndependentSampler::new(0)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn make_tiles(img_width: u32, img_height: u32, tile_size: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..img_height).step_by(tile_size as usize) {
+        for x in (0..img_width).step_by(tile_size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(img_width - x),
+                height: tile_size.min(img_height - y),
+            });
+        }
+    }
+    tiles
+}
+
+fn render_pixel(
+    scene: &Scene,
+    camera: &dyn Camera,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    x: u32,
+    y: u32,
+    film: &mut Film,
+) {
+    let samples = settings.samples_per_pixel.max(1);
+    for index in 0..samples {
+        // Sample values depend on the pixel coordinates alone, not on which
+        // thread renders the pixel
+        sampler.start_sample(&na::Point2::new(x, y), index, samples);
+        // A single sample goes through the pixel centre, more are jittered
+        // across the pixel to anti-alias edges
+        let jitter = sampler.get_2d();
+        let point = if samples == 1 {
+            na::Point2::new(x as f32 + 0.5, y as f32 + 0.5)
+        } else {
+            na::Point2::new(x as f32 + jitter.x, y as f32 + jitter.y)
+        };
+        let lens = sampler.get_2d();
+        let ray = camera.generate_ray(&point, &lens);
+        let radiance = settings.integrator.radiance(scene, &ray, sampler);
+        film.add_sample(settings.filter.as_ref(), &point, &radiance);
+    }
+}
+
+/// Renders the samples of `tile` into a film covering every pixel they
+/// contribute to.
+fn render_tile(scene: &Scene, camera: &dyn Camera, settings: &RenderSettings, tile: &Tile) -> Film {
+    let (img_width, img_height) = camera.resolution();
+    let border = (settings.filter.radius() + 0.5).ceil() as u32;
+    let x0 = tile.x.saturating_sub(border);
+    let y0 = tile.y.saturating_sub(border);
+    let mut film = Film::with_bounds(
+        x0,
+        y0,
+        (tile.x + tile.width + border).min(img_width) - x0,
+        (tile.y + tile.height + border).min(img_height) - y0,
+    );
+    let mut sampler = settings.sampler.clone_sampler();
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            render_pixel(scene, camera, settings, sampler.as_mut(), x, y, &mut film);
+        }
+    }
+    film
+}
+
+/// Renders `scene` as seen through `camera` into a new film.
+///
+/// The frame is split into tiles which worker threads pick up in turn. Every
+/// pixel's samples only depend on its own coordinates and tiles are merged
+/// in a fixed order, so the output is identical for any number of threads.
+pub fn render_film(scene: &Scene, camera: &dyn Camera, settings: &RenderSettings) -> Film {
+    let (img_width, img_height) = camera.resolution();
+    let tiles = make_tiles(img_width, img_height, settings.tile_size.max(1));
+    let threads = settings.threads.max(1).min(tiles.len().max(1));
+    info!(
+        "Rendering {}x{} image as {} tiles on {} threads",
+        img_width,
+        img_height,
+        tiles.len(),
+        threads
+    );
+
+    let next_tile = AtomicUsize::new(0);
+    let finished = Mutex::new(Vec::with_capacity(tiles.len()));
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                let tile = match tiles.get(idx) {
+                    Some(tile) => tile,
+                    None => break,
+                };
+                let film = render_tile(scene, camera, settings, tile);
+                finished.lock().unwrap().push((idx, film));
+            });
+        }
+    });
+
+    let mut finished = finished.into_inner().unwrap();
+    finished.sort_by_key(|(idx, _)| *idx);
+    let mut film = Film::new(img_width, img_height);
+    for (_, tile_film) in &finished {
+        film.merge(tile_film);
+    }
+    film
+}
+
+/// Renders `scene` as seen through `camera` into a new image, see
+/// `render_film`, with the default `ToneMapping`.
+pub fn render(scene: &Scene, camera: &dyn Camera, settings: &RenderSettings) -> image::RgbImage {
+    render_film(scene, camera, settings).tone_map(&ToneMapping::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::camera::PerspectiveCamera;
+    use crate::integrator::PathTracer;
+    use crate::light;
+    use crate::sampler::{BlueNoiseSampler, HaltonSampler, SobolSampler, StratifiedSampler};
+    use crate::shape;
+
+    fn make_camera() -> PerspectiveCamera {
+        PerspectiveCamera::look_at(
+            &na::Point3::origin(),
+            &na::Point3::new(0.0, 0.0, -1.0),
+            &na::Vector3::y(),
+            std::f32::consts::FRAC_PI_2,
+            67,
+            45,
+        )
+    }
+
+    fn make_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(
+            "light",
+            Box::new(light::RectLight {
+                pose: na::Isometry3::new(
+                    na::Vector3::new(2.0, 2.0, 0.0),
+                    na::Vector3::x() * std::f32::consts::FRAC_PI_2,
+                ),
+                width: 0.5,
+                height: 0.5,
+                color: crate::material::Color::new(1.0, 1.0, 1.0),
+                intensity: 32.0,
+            }),
+        );
+        scene.add_shape(
+            "floor",
+            Box::new(shape::Plane {
+                pose: na::Isometry3::new(
+                    na::Vector3::new(0.0, -1.0, 0.0),
+                    na::Vector3::x() * -std::f32::consts::FRAC_PI_2,
+                ),
+            }),
+        );
+        scene.add_shape(
+            "sphere",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(0.0, 0.0, -4.0),
+                radius: 1.0,
+            }),
+        );
+        scene.rebuild_bvh();
+        scene
+    }
+
+    #[test]
+    fn tiles_cover_image() {
+        let tiles = make_tiles(67, 45, 16);
+        assert_eq!(tiles.len(), 5 * 3);
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, 67 * 45);
+        assert_eq!(
+            tiles.last(),
+            Some(&Tile {
+                x: 64,
+                y: 32,
+                width: 3,
+                height: 13
+            })
+        );
+    }
+
+    fn settings(threads: usize, path_traced: bool) -> RenderSettings {
+        RenderSettings {
+            threads,
+            tile_size: 8,
+            integrator: if path_traced {
+                Box::new(PathTracer::default())
+            } else {
+                Box::new(Whitted::default())
+            },
+            samples_per_pixel: 2,
+            filter: Box::new(BoxFilter::default()),
+            sampler: Box::new(IndependentSampler::new(0)),
+        }
+    }
+
+    #[test]
+    fn deterministic_across_thread_counts() {
+        let scene = make_scene();
+        let camera = make_camera();
+        for path_traced in [false, true].iter() {
+            let reference = render(&scene, &camera, &settings(1, *path_traced));
+            for threads in [2, 3, 8].iter() {
+                let image = render(&scene, &camera, &settings(*threads, *path_traced));
+                assert!(
+                    *reference == *image,
+                    "Rendering with {} threads should match a single thread",
+                    threads
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn samples_anti_alias_edges() {
+        let scene = make_scene();
+        let camera = make_camera();
+        let aliased = render(&scene, &camera, &settings(1, false));
+        let smoothed = render(
+            &scene,
+            &camera,
+            &RenderSettings {
+                samples_per_pixel: 16,
+                ..settings(1, false)
+            },
+        );
+        // Pixels on the silhouette of the sphere mix it with the background
+        let background = ToneMapping::default().to_rgb(&scene.background);
+        let is_mixed =
+            |pixel: &image::Rgb<u8>| *pixel != background && pixel.0[2] > 100 && pixel.0[2] < 190;
+        let count = |image: &image::RgbImage| image.pixels().filter(|p| is_mixed(p)).count();
+        assert!(
+            count(&smoothed) > count(&aliased),
+            "{} mixed pixels with anti-aliasing, {} without",
+            count(&smoothed),
+            count(&aliased)
+        );
+    }
+
+    #[test]
+    fn seed_changes_noise() {
+        let scene = make_scene();
+        let camera = make_camera();
+        let reference = render(&scene, &camera, &settings(2, true));
+        let mut reseeded = settings(2, true);
+        reseeded.sampler = Box::new(IndependentSampler::new(1));
+        assert!(*reference != *render(&scene, &camera, &reseeded));
+    }
+
+    #[test]
+    fn samplers_are_deterministic_across_thread_counts() {
+        let scene = make_scene();
+        let camera = make_camera();
+        let samplers: Vec<Box<dyn Sampler>> = vec![
+            Box::new(StratifiedSampler::new(3)),
+            Box::new(HaltonSampler::new(3)),
+            Box::new(SobolSampler::new(3)),
+            Box::new(BlueNoiseSampler::new(3)),
+        ];
+        for sampler in samplers {
+            let with_threads = |threads| RenderSettings {
+                sampler: sampler.clone_sampler(),
+                ..settings(threads, true)
+            };
+            let reference = render(&scene, &camera, &with_threads(1));
+            assert!(*reference == *render(&scene, &camera, &with_threads(3)));
+        }
+    }
+}
@@ -0,0 +1,712 @@
+extern crate nalgebra as na;
+
+use std::sync::OnceLock;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+/// Generates the sample values a pixel sample uses, e.g. for its position in
+/// the pixel, on the lens and on lights.
+///
+/// Values are in [0, 1) and come in dimensions, the first call after
+/// `start_sample` gives the first dimension, the next call the next one and
+/// so on. Samplers other than `IndependentSampler` spread the values of each
+/// dimension evenly over the samples of a pixel, which reduces noise. The
+/// values only depend on the pixel, sample index, dimension and seed, so
+/// renders are reproducible.
+pub trait Sampler: Send + Sync {
+    /// Starts sample `index` of the `count` taken in `pixel`, going back to
+    /// the first dimension.
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32);
+
+    /// Value for the next dimension.
+    fn get_1d(&mut self) -> f32;
+
+    /// Values for the next two dimensions, which are spread evenly over the
+    /// square together rather than each on its own.
+    fn get_2d(&mut self) -> na::Point2<f32>;
+
+    /// A copy with the same seed, for another thread.
+    fn clone_sampler(&self) -> Box<dyn Sampler>;
+}
+
+/// The largest f32 below one.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Scrambles the bits of `value`, from MurmurHash3's finalizer.
+fn mix_bits(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    value ^= value >> 33;
+    value
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |h, &value| {
+        mix_bits(h ^ value).rotate_left(5)
+    })
+}
+
+/// Uniform value in [0, 1) from the top bits of `bits`.
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Element `index` of a random permutation of 0..`length` chosen by `seed`,
+/// from Kensler's "Correlated Multi-Jittered Sampling".
+fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    if length <= 1 {
+        return 0;
+    }
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // Hash within the next power of two until the result is in range
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+    index.wrapping_add(seed) % length
+}
+
+/// Uniform random values, a new random number generator per pixel sample.
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, _count: u32) {
+        let key = hash(&[
+            u64::from(pixel.x),
+            u64::from(pixel.y),
+            u64::from(index),
+            self.seed,
+        ]);
+        self.rng = Pcg32::seed_from_u64(key);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> na::Point2<f32> {
+        na::Point2::new(self.rng.gen(), self.rng.gen())
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Where a sampler is in the samples of a pixel.
+#[derive(Clone, Copy, Debug, Default)]
+struct SampleState {
+    pixel: (u32, u32),
+    index: u32,
+    count: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32) {
+        *self = SampleState {
+            pixel: (pixel.x, pixel.y),
+            index,
+            count: count.max(1),
+            dimension: 0,
+        };
+    }
+
+    /// Claims the next `n` dimensions, returning the first.
+    fn next_dimension(&mut self, n: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += n;
+        dimension
+    }
+
+    /// Hash of the pixel, `dimension` and `seed`, shared by all samples of
+    /// the pixel.
+    fn pixel_hash(&self, dimension: u32, seed: u64) -> u64 {
+        hash(&[
+            u64::from(self.pixel.0),
+            u64::from(self.pixel.1),
+            u64::from(dimension),
+            seed,
+        ])
+    }
+}
+
+/// Jittered samples, one in each of `count` equal strata per dimension.
+/// Pairs of dimensions use correlated multi-jittering, which also
+/// stratifies them on a grid of about sqrt(count) by sqrt(count) cells.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64) -> Self {
+        StratifiedSampler {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    /// Random offset within a stratum.
+    fn jitter(&self, dimension: u32) -> f32 {
+        let state = &self.state;
+        to_unit(hash(&[
+            state.pixel_hash(dimension, self.seed),
+            u64::from(state.index),
+        ]) as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32) {
+        self.state.start(pixel, index, count);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension(1);
+        let seed = self.state.pixel_hash(dimension, self.seed) as u32;
+        let stratum = permute(self.state.index, self.state.count, seed);
+        ((stratum as f32 + self.jitter(dimension)) / self.state.count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> na::Point2<f32> {
+        let dimension = self.state.next_dimension(2);
+        let seed = self.state.pixel_hash(dimension, self.seed) as u32;
+        let count = self.state.count;
+        let columns = ((count as f32).sqrt().round() as u32).max(1);
+        let rows = count.div_ceil(columns);
+
+        let index = permute(self.state.index, count, seed.wrapping_mul(0x5163_3e2d));
+        let sx = permute(index % columns, columns, seed.wrapping_mul(0x68bc_21eb));
+        let sy = permute(index / columns, rows, seed.wrapping_mul(0x02e5_be93));
+        let jx = self.jitter(dimension);
+        let jy = self.jitter(dimension + 1);
+        na::Point2::new(
+            ((sx as f32 + (sy as f32 + jx) / rows as f32) / columns as f32).min(ONE_MINUS_EPSILON),
+            ((index as f32 + jy) / count as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Bases of the Halton sequence, one per dimension.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Digits of `index` in `base` mirrored about the decimal point, with the
+/// digits at each position shuffled by a permutation chosen by `seed`.
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f32 {
+    let inverse_base = 1.0 / f64::from(base);
+    let mut scale = 1.0;
+    let mut result = 0.0;
+    let mut position = 0;
+    // Scrambled zeros contribute too, so go on until below f32 precision
+    while scale > 1.0e-9 {
+        let digit = index % base;
+        index /= base;
+        let permuted = permute(digit, base, hash(&[seed, position]) as u32);
+        scale *= inverse_base;
+        result += f64::from(permuted) * scale;
+        position += 1;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, the radical inverse of the sample index in a
+/// different prime base for each dimension. Digits are scrambled per pixel
+/// so that neighbouring pixels do not share a pattern. Dimensions beyond
+/// the 64th are independent random values.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f32 {
+        let seed = self.state.pixel_hash(dimension, self.seed);
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => scrambled_radical_inverse(base, self.state.index, seed),
+            None => to_unit(hash(&[seed, u64::from(self.state.index)]) as u32),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32) {
+        self.state.start(pixel, index, count);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> na::Point2<f32> {
+        let dimension = self.state.next_dimension(2);
+        na::Point2::new(self.sample(dimension), self.sample(dimension + 1))
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// First two dimensions of the Sobol sequence as 32-bit fractions.
+fn sobol(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut v = 1 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Owen scrambling of a 32-bit fraction, using the hash by Laine and Karras
+/// as described in Burley's "Practical Hash-based Owen Scrambling".
+fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// Owen scrambled Sobol points. Each dimension, or pair of dimensions, uses
+/// the first two Sobol dimensions with its own scrambling and sample order,
+/// so any number of dimensions is well distributed. Works best with a power
+/// of two samples per pixel.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        SobolSampler {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> (u32, u32) {
+        let key = self.state.pixel_hash(dimension, self.seed);
+        let index = permute(self.state.index, self.state.count, key as u32);
+        let (x, y) = sobol(index);
+        (
+            owen_scramble(x, key as u32),
+            owen_scramble(y, (key >> 32) as u32),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32) {
+        self.state.start(pixel, index, count);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension(1);
+        to_unit(self.sample(dimension).0)
+    }
+
+    fn get_2d(&mut self) -> na::Point2<f32> {
+        let dimension = self.state.next_dimension(2);
+        let (x, y) = self.sample(dimension);
+        na::Point2::new(to_unit(x), to_unit(y))
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Edge length of the tiled blue noise mask in pixels.
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+/// A blue noise mask made with Ulichney's void-and-cluster method, i.e. a
+/// ranking of its pixels in which every prefix is spread out evenly, as
+/// values in [0, 1). Generated on first use.
+pub fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(make_blue_noise_mask)
+}
+
+fn make_blue_noise_mask() -> Vec<f32> {
+    let size = BLUE_NOISE_SIZE;
+    let count = size * size;
+    // Gaussian energy kernel, wrapping around the edges of the mask
+    let sigma = 1.5_f32;
+    let kernel: Vec<f32> = (0..count)
+        .map(|idx| {
+            let wrap = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(idx % size), wrap(idx / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    // Energy of the set pixels at every pixel
+    struct Pattern<'a> {
+        size: usize,
+        kernel: &'a [f32],
+        set: Vec<bool>,
+        energy: Vec<f32>,
+    }
+    impl<'a> Pattern<'a> {
+        fn toggle(&mut self, idx: usize) {
+            self.set[idx] = !self.set[idx];
+            let sign = if self.set[idx] { 1.0 } else { -1.0 };
+            let (x0, y0) = (idx % self.size, idx / self.size);
+            for (other, energy) in self.energy.iter_mut().enumerate() {
+                let dx = (other % self.size + self.size - x0) % self.size;
+                let dy = (other / self.size + self.size - y0) % self.size;
+                *energy += sign * self.kernel[dy * self.size + dx];
+            }
+        }
+
+        /// The set pixel with the most energy.
+        fn tightest_cluster(&self) -> usize {
+            let mut best = None;
+            for (idx, &energy) in self.energy.iter().enumerate() {
+                if self.set[idx] && best.is_none_or(|(_, e)| energy > e) {
+                    best = Some((idx, energy));
+                }
+            }
+            best.map_or(0, |(idx, _)| idx)
+        }
+
+        /// The unset pixel with the least energy.
+        fn largest_void(&self) -> usize {
+            let mut best = None;
+            for (idx, &energy) in self.energy.iter().enumerate() {
+                if !self.set[idx] && best.is_none_or(|(_, e)| energy < e) {
+                    best = Some((idx, energy));
+                }
+            }
+            best.map_or(0, |(idx, _)| idx)
+        }
+    }
+
+    let mut pattern = Pattern {
+        size,
+        kernel: &kernel,
+        set: vec![false; count],
+        energy: vec![0.0; count],
+    };
+    let initial = count / 10;
+    let mut rng = Pcg32::seed_from_u64(0);
+    let mut placed = 0;
+    while placed < initial {
+        let idx = rng.gen_range(0, count);
+        if !pattern.set[idx] {
+            pattern.toggle(idx);
+            placed += 1;
+        }
+    }
+    // Move pixels from clusters into voids until the pattern is even
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+    // Rank the initial pixels by taking them away from the tightest clusters
+    let mut removing = Pattern {
+        size,
+        kernel: &kernel,
+        set: pattern.set.clone(),
+        energy: pattern.energy.clone(),
+    };
+    for rank in (0..initial).rev() {
+        let cluster = removing.tightest_cluster();
+        removing.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+    // Then the rest by filling the largest voids
+    for rank in initial..count {
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        ranks[void] = rank;
+    }
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / count as f32)
+        .collect()
+}
+
+/// Scrambled Sobol points shared by all pixels, each pixel shifting them by
+/// the value of a blue noise mask. Neighbouring pixels then get very
+/// different samples, which leaves the remaining noise at high frequencies
+/// where it is least visible.
+#[derive(Clone, Debug)]
+pub struct BlueNoiseSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        BlueNoiseSampler {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    /// Value of the mask at the pixel, tiled with an offset chosen by `key`.
+    fn mask_value(&self, key: u64) -> f32 {
+        let size = BLUE_NOISE_SIZE as u32;
+        let x = (self.state.pixel.0 % size + (key as u32) % size) % size;
+        let y = (self.state.pixel.1 % size + ((key >> 32) as u32) % size) % size;
+        blue_noise_mask()[(y * size + x) as usize]
+    }
+
+    fn sample(&self, dimension: u32) -> na::Point2<f32> {
+        let key = hash(&[u64::from(dimension), self.seed]);
+        let index = permute(self.state.index, self.state.count, key as u32);
+        let (x, y) = sobol(index);
+        let shift_x = self.mask_value(mix_bits(key));
+        let shift_y = self.mask_value(mix_bits(key ^ 1));
+        let shifted =
+            |value: u32, shift: f32| (to_unit(owen_scramble(value, key as u32)) + shift).fract();
+        na::Point2::new(
+            shifted(x, shift_x).min(ONE_MINUS_EPSILON),
+            shifted(y, shift_y).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, pixel: &na::Point2<u32>, index: u32, count: u32) {
+        self.state.start(pixel, index, count);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension(1);
+        self.sample(dimension).x
+    }
+
+    fn get_2d(&mut self) -> na::Point2<f32> {
+        let dimension = self.state.next_dimension(2);
+        self.sample(dimension)
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samplers(seed: u64) -> Vec<Box<dyn Sampler>> {
+        vec![
+            Box::new(IndependentSampler::new(seed)),
+            Box::new(StratifiedSampler::new(seed)),
+            Box::new(HaltonSampler::new(seed)),
+            Box::new(SobolSampler::new(seed)),
+            Box::new(BlueNoiseSampler::new(seed)),
+        ]
+    }
+
+    /// The first `dimensions` values of every sample of a pixel.
+    fn pixel_samples(
+        sampler: &mut dyn Sampler,
+        pixel: (u32, u32),
+        count: u32,
+        dimensions: usize,
+    ) -> Vec<Vec<f32>> {
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(&na::Point2::new(pixel.0, pixel.1), index, count);
+                (0..dimensions)
+                    .map(|dimension| {
+                        if dimension % 3 == 2 {
+                            sampler.get_1d()
+                        } else if dimension % 3 == 0 {
+                            sampler.get_2d().x
+                        } else {
+                            // Second half of the pair drawn above
+                            f32::NAN
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn values_in_unit_interval() {
+        for sampler in samplers(3).iter_mut() {
+            for sample in pixel_samples(sampler.as_mut(), (5, 9), 37, 90) {
+                for value in sample.into_iter().filter(|v| !v.is_nan()) {
+                    assert!((0.0..1.0).contains(&value), "{} out of range", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        for (sampler, other) in samplers(3).iter_mut().zip(samplers(3).iter_mut()) {
+            let first = pixel_samples(sampler.as_mut(), (1, 2), 8, 12);
+            let again = pixel_samples(other.as_mut(), (1, 2), 8, 12);
+            let copy = pixel_samples(sampler.clone_sampler().as_mut(), (1, 2), 8, 12);
+            let format = |samples: &Vec<Vec<f32>>| format!("{:?}", samples);
+            assert_eq!(format(&first), format(&again));
+            assert_eq!(format(&first), format(&copy));
+        }
+        for (sampler, other) in samplers(3).iter_mut().zip(samplers(4).iter_mut()) {
+            sampler.start_sample(&na::Point2::new(1, 2), 0, 8);
+            other.start_sample(&na::Point2::new(1, 2), 0, 8);
+            assert_ne!(sampler.get_2d(), other.get_2d());
+        }
+    }
+
+    /// Whether every one of `count` equal strata holds exactly one value.
+    fn is_stratified(values: &[f32]) -> bool {
+        let count = values.len();
+        let mut strata: Vec<usize> = values.iter().map(|v| (v * count as f32) as usize).collect();
+        strata.sort_unstable();
+        strata == (0..count).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn dimensions_are_stratified() {
+        let count = 16;
+        let stratified: Vec<Box<dyn Sampler>> = vec![
+            Box::new(StratifiedSampler::new(1)),
+            Box::new(SobolSampler::new(1)),
+        ];
+        for mut sampler in stratified {
+            let mut xs = Vec::new();
+            let mut ys = Vec::new();
+            let mut ws = Vec::new();
+            for index in 0..count {
+                sampler.start_sample(&na::Point2::new(3, 4), index, count);
+                sampler.get_2d();
+                let uv = sampler.get_2d();
+                xs.push(uv.x);
+                ys.push(uv.y);
+                ws.push(sampler.get_1d());
+            }
+            assert!(is_stratified(&xs) && is_stratified(&ys) && is_stratified(&ws));
+        }
+
+        // Halton dimensions are stratified for powers of their base
+        let mut halton = HaltonSampler::new(1);
+        let samples = |count: u32, halton: &mut HaltonSampler| -> Vec<na::Point2<f32>> {
+            (0..count)
+                .map(|index| {
+                    halton.start_sample(&na::Point2::new(3, 4), index, count);
+                    halton.get_2d()
+                })
+                .collect()
+        };
+        let xs: Vec<f32> = samples(16, &mut halton).iter().map(|p| p.x).collect();
+        let ys: Vec<f32> = samples(27, &mut halton).iter().map(|p| p.y).collect();
+        assert!(is_stratified(&xs) && is_stratified(&ys));
+    }
+
+    #[test]
+    fn permutations() {
+        for &length in [1, 2, 7, 16, 100].iter() {
+            for seed in 0..4 {
+                let mut values: Vec<u32> = (0..length).map(|i| permute(i, length, seed)).collect();
+                values.sort_unstable();
+                assert_eq!(values, (0..length).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_is_blue() {
+        let mask = blue_noise_mask();
+        let size = BLUE_NOISE_SIZE;
+        let mut sorted = mask.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        sorted.dedup();
+        assert_eq!(sorted.len(), size * size);
+
+        // Neighbours differ by 1/3 on average for white noise, more for blue
+        // noise which has no low frequencies
+        let mut difference = 0.0;
+        for y in 0..size {
+            for x in 0..size {
+                let value = mask[y * size + x];
+                difference += (value - mask[y * size + (x + 1) % size]).abs();
+                difference += (value - mask[((y + 1) % size) * size + x]).abs();
+            }
+        }
+        difference /= (2 * size * size) as f32;
+        assert!(difference > 0.4, "Mean neighbour difference {}", difference);
+    }
+}
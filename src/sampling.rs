@@ -0,0 +1,127 @@
+extern crate nalgebra as na;
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Maps a uniform sample in [0, 1)^2 to a uniformly distributed point on the
+/// unit disk, using Shirley's concentric mapping.
+pub fn concentric_disk(u: &na::Point2<f32>) -> na::Point2<f32> {
+    let offset = na::Point2::new(2.0 * u.x - 1.0, 2.0 * u.y - 1.0);
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return na::Point2::origin();
+    }
+    let (r, theta) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, FRAC_PI_4 * (offset.y / offset.x))
+    } else {
+        (offset.y, FRAC_PI_2 - FRAC_PI_4 * (offset.x / offset.y))
+    };
+    na::Point2::new(r * theta.cos(), r * theta.sin())
+}
+
+/// Direction in the hemisphere around +Z, distributed proportionally to the
+/// cosine of its angle with +Z.
+pub fn cosine_hemisphere(u: &na::Point2<f32>) -> na::Vector3<f32> {
+    let d = concentric_disk(u);
+    let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
+    na::Vector3::new(d.x, d.y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) / PI
+}
+
+/// Multiple importance sampling weight of a sample drawn from a strategy with
+/// density `f_pdf`, when another strategy with density `g_pdf` could also
+/// have produced it.
+pub fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g <= 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+/// Uniformly distributed direction within a cone around +Z whose half-angle
+/// has cosine `cos_max`.
+pub fn uniform_cone(u: &na::Point2<f32>, cos_max: f32) -> na::Vector3<f32> {
+    let cos_theta = (1.0 - u.x) + u.x * cos_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y;
+    na::Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
+pub fn uniform_cone_pdf(cos_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+/// Orthonormal basis whose third axis is `n`.
+pub fn coordinate_system(n: &na::Vector3<f32>) -> na::Rotation3<f32> {
+    let tangent = if n.x.abs() > n.y.abs() {
+        na::Vector3::new(-n.z, 0.0, n.x) / (n.x * n.x + n.z * n.z).sqrt()
+    } else {
+        na::Vector3::new(0.0, n.z, -n.y) / (n.y * n.y + n.z * n.z).sqrt()
+    };
+    let bitangent = n.cross(&tangent);
+    na::Rotation3::from_matrix_unchecked(na::Matrix3::from_columns(&[tangent, bitangent, *n]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    #[test]
+    fn disk_samples_stay_on_disk() {
+        for x in 0..=10 {
+            for y in 0..=10 {
+                let u = na::Point2::new(x as f32 / 10.0, y as f32 / 10.0);
+                let p = concentric_disk(&u);
+                assert!(p.coords.norm() <= 1.0 + 1.0e-6, "{} maps off the disk", p);
+            }
+        }
+        assert_eq!(
+            concentric_disk(&na::Point2::new(0.5, 0.5)),
+            na::Point2::origin()
+        );
+    }
+
+    #[test]
+    fn cone_samples_stay_in_cone() {
+        let cos_max = 0.9;
+        for x in 0..=10 {
+            for y in 0..=10 {
+                let u = na::Point2::new(x as f32 / 10.0, y as f32 / 10.0);
+                let d = uniform_cone(&u, cos_max);
+                assert!(relative_eq!(d.norm(), 1.0, epsilon = 1.0e-5));
+                assert!(d.z >= cos_max - 1.0e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_samples_in_hemisphere() {
+        for x in 0..=10 {
+            for y in 0..=10 {
+                let u = na::Point2::new(x as f32 / 10.0, y as f32 / 10.0);
+                let d = cosine_hemisphere(&u);
+                assert!(relative_eq!(d.norm(), 1.0, epsilon = 1.0e-5));
+                assert!(d.z >= 0.0);
+            }
+        }
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+    }
+
+    #[test]
+    fn orthonormal_basis() {
+        let n = na::Vector3::new(0.3, -0.5, 0.8).normalize();
+        let basis = coordinate_system(&n);
+        assert!(relative_eq!(basis * na::Vector3::z(), n, epsilon = 1.0e-6));
+        assert!(relative_eq!(
+            basis.matrix().transpose() * basis.matrix(),
+            na::Matrix3::identity(),
+            epsilon = 1.0e-6
+        ));
+    }
+}
@@ -1,82 +1,436 @@
-extern crate image; // DMDBG: Not sure if this should really be speaking 'image' directly
-
 use std::boxed::Box;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::info;
 
+use crate::bvh::Bvh;
+use crate::geometry::Aabb;
+use crate::light::Light;
+use crate::material::{self, Color, Material};
+use crate::sampler::Sampler;
 use crate::shape;
 
 use crate::geometry;
 use geometry::{Ray, RayHit};
 
+/// Hits closer than this along a ray are ignored when testing for occlusion,
+/// so that shadow rays do not hit the surface they start on.
+const SHADOW_EPSILON: f32 = 1.0e-4;
+
+/// Acceleration structure over the shapes of a scene. Shapes without finite
+/// bounds cannot be placed in the hierarchy and are tested separately.
+struct SceneBvh {
+    bvh: Bvh,
+    bounded: Vec<Arc<str>>,
+    unbounded: Vec<Arc<str>>,
+}
+
 pub struct Scene {
-    pub lights: HashMap<String, shape::PointLight>,
+    pub lights: HashMap<String, Box<dyn Light>>,
     pub shapes: HashMap<String, Box<dyn shape::Shape>>,
+    /// Materials keyed by the name of the shape they are attached to.
+    pub materials: HashMap<String, Arc<dyn Material>>,
+    /// Semantic class labels keyed by the name of the shape they apply to.
+    pub classes: HashMap<String, String>,
+    /// Material of shapes without an entry in `materials`.
+    pub default_material: Arc<dyn Material>,
+    /// Radiance of rays that leave the scene without hitting anything.
+    pub background: Color,
+    /// Number of shadow rays cast towards each area light per shaded point.
+    pub light_samples: usize,
+    bvh: Option<SceneBvh>,
+}
+
+/// Ray parameter of the near point of `hit`.
+fn hit_parameter(ray: &Ray, hit: &RayHit) -> f32 {
+    (hit.near - ray.origin).dot(&ray.direction) / ray.direction.norm_squared()
+}
+
+/// Fold step keeping whichever of two hits has the smaller ray parameter.
+fn closest<T>(lhs: Option<(f32, T)>, rhs: (f32, T)) -> Option<(f32, T)> {
+    match lhs {
+        Some(lhs) if lhs.0 <= rhs.0 => Some(lhs),
+        _ => Some(rhs),
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
 }
 
 impl Scene {
     pub fn new() -> Self {
-        return Scene {
+        Scene {
             lights: HashMap::new(),
             shapes: HashMap::new(),
-        };
+            materials: HashMap::new(),
+            classes: HashMap::new(),
+            default_material: Arc::new(material::Surface::default()),
+            background: Color::new(0.0, 150.0 / 255.0, 200.0 / 255.0),
+            light_samples: 16,
+            bvh: None,
+        }
     }
 
-    pub fn add_light(&mut self, name: &str, light: shape::PointLight) {
+    pub fn add_light(&mut self, name: &str, light: Box<dyn Light>) {
         self.lights.insert(name.to_string(), light);
     }
 
-    pub fn get_light(&self, name: &str) -> Option<&shape::PointLight> {
-        self.lights.get(name)
+    pub fn get_light(&self, name: &str) -> Option<&dyn Light> {
+        self.lights.get(name).map(|light| light.as_ref())
     }
 
+    /// Adds a shape, replacing any shape of the same name. Invalidates the
+    /// BVH until `rebuild_bvh` is called.
     pub fn add_shape(&mut self, name: &str, shape: Box<dyn shape::Shape>) {
         self.shapes.insert(name.to_string(), shape);
+        self.bvh = None;
     }
 
-    pub fn get_shape(&self, name: &str) -> Option<&Box<dyn shape::Shape>> {
-        self.shapes.get(name)
+    /// Removes a shape. Invalidates the BVH until `rebuild_bvh` is called.
+    pub fn remove_shape(&mut self, name: &str) -> Option<Box<dyn shape::Shape>> {
+        self.bvh = None;
+        self.materials.remove(name);
+        self.classes.remove(name);
+        self.shapes.remove(name)
     }
 
-    pub fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
+    pub fn get_shape(&self, name: &str) -> Option<&dyn shape::Shape> {
+        self.shapes.get(name).map(|shape| shape.as_ref())
+    }
+
+    /// Attaches `material` to the shape named `shape_name`. The same material
+    /// may be shared by any number of shapes.
+    pub fn set_material(&mut self, shape_name: &str, material: Arc<dyn Material>) {
+        self.materials.insert(shape_name.to_string(), material);
+    }
+
+    pub fn get_material(&self, shape_name: &str) -> Option<&dyn Material> {
+        self.materials
+            .get(shape_name)
+            .map(|material| material.as_ref())
+    }
+
+    /// Labels the shape named `shape_name` as being of `class`, e.g. for
+    /// segmentation masks.
+    pub fn set_class(&mut self, shape_name: &str, class: &str) {
+        self.classes
+            .insert(shape_name.to_string(), class.to_string());
+    }
+
+    pub fn get_class(&self, shape_name: &str) -> Option<&str> {
+        self.classes.get(shape_name).map(|class| class.as_str())
+    }
+
+    /// Integer IDs of the shapes, numbered from one in order of their names
+    /// so that they are stable for a given scene. Zero is left for the
+    /// background.
+    pub fn shape_ids(&self) -> HashMap<String, u32> {
+        let mut names: Vec<&String> = self.shapes.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(idx, name)| (name.clone(), idx as u32 + 1))
+            .collect()
+    }
+
+    /// Material of the surface that was hit, or the default material.
+    pub fn material_of(&self, hit: &RayHit) -> &dyn Material {
+        hit.shape
+            .as_ref()
+            .and_then(|name| self.get_material(name))
+            .unwrap_or_else(|| self.default_material.as_ref())
+    }
+
+    /// Builds the BVH over the current shapes. Must be called again after
+    /// shapes are added or removed, otherwise ray casts fall back to testing
+    /// every shape.
+    pub fn rebuild_bvh(&mut self) {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut bounds = Vec::new();
+        for (name, shape) in &self.shapes {
+            let shape_bounds = shape.bounds();
+            if shape_bounds.is_finite() {
+                bounded.push(Arc::from(name.as_str()));
+                bounds.push(shape_bounds);
+            } else if !shape_bounds.is_empty() {
+                unbounded.push(Arc::from(name.as_str()));
+            }
+        }
+        let bvh = Bvh::build(&bounds);
+        info!(
+            "Built BVH with {} nodes over {} shapes ({} unbounded)",
+            bvh.node_count(),
+            bounded.len(),
+            unbounded.len()
+        );
+        self.bvh = Some(SceneBvh {
+            bvh,
+            bounded,
+            unbounded,
+        });
+    }
+
+    /// Bounds of all shapes with a finite extent, e.g. for framing a camera.
+    /// Unbounded shapes such as planes are left out.
+    pub fn bounds(&self) -> Aabb {
         self.shapes
-            .iter()
-            .map(|item| item.1.ray_cast(&ray))
-            .filter(|hit| hit.is_some())
-            .map(|hit| hit.unwrap())
-            .max_by(|lhs, rhs| {
-                let lhs_norm = lhs.near.coords.norm();
-                let rhs_norm = rhs.near.coords.norm();
-                //lhs_norm.partial_cmp(&rhs_norm).unwrap().reverse()
-                if lhs_norm > rhs_norm {
-                    std::cmp::Ordering::Less
-                } else if lhs_norm < rhs_norm {
-                    std::cmp::Ordering::Greater
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            })
+            .values()
+            .map(|shape| shape.bounds())
+            .filter(|bounds| bounds.is_finite())
+            .fold(Aabb::empty(), |lhs, rhs| lhs.union(&rhs))
     }
 
-    pub fn paint(&self, hit: &RayHit) -> image::Rgb<u8> {
-        let l_scene = self.get_light("light").unwrap().pose.translation;
+    pub fn has_bvh(&self) -> bool {
+        self.bvh.is_some()
+    }
 
-        let n = hit.normal;
-        let l = (l_scene * (-hit.near)).coords.normalize();
+    fn cast_named<'a, N>(&self, name: &'a N, ray: &Ray) -> Option<(f32, (&'a N, RayHit))>
+    where
+        N: AsRef<str> + ?Sized,
+    {
+        self.shapes[name.as_ref()]
+            .ray_cast(ray)
+            .map(|hit| (hit_parameter(ray, &hit), (name, hit)))
+    }
 
-        let light_ray = geometry::Ray {
-            origin: hit.near,
-            direction: l,
+    /// Returns the hit closest to the origin of `ray`, tagged with the name of
+    /// the shape that was hit.
+    pub fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
+        let hit = match &self.bvh {
+            Some(accel) => {
+                let bounded = accel
+                    .bvh
+                    .closest_hit(ray, |idx| self.cast_named(&accel.bounded[idx], ray));
+                accel
+                    .unbounded
+                    .iter()
+                    .filter_map(|name| self.cast_named(name, ray))
+                    .fold(bounded, closest)
+                    .map(|(t, (name, hit))| (t, (name.clone(), hit)))
+            }
+            None => self
+                .shapes
+                .keys()
+                .filter_map(|name| self.cast_named(name, ray))
+                .fold(None, closest)
+                .map(|(t, (name, hit))| (t, (Arc::from(name.as_str()), hit))),
         };
+        hit.map(|(_, (name, mut hit))| {
+            hit.shape = Some(name);
+            hit
+        })
+    }
+
+    /// Returns true if anything lies along `ray` before `max_distance`.
+    pub fn occluded(&self, ray: &Ray, max_distance: f32) -> bool {
+        let t_max = max_distance / ray.direction.norm();
+        let blocks = |name: &str| match self.cast_named(name, ray) {
+            Some((t, _)) => t > SHADOW_EPSILON && t < t_max,
+            None => false,
+        };
+
+        match &self.bvh {
+            Some(accel) => {
+                accel
+                    .bvh
+                    .any_hit(ray, t_max, |idx| blocks(&accel.bounded[idx]))
+                    || accel.unbounded.iter().any(|name| blocks(name))
+            }
+            None => self.shapes.keys().any(|name| blocks(name)),
+        }
+    }
+
+    /// Blinn-Phong shading of `hit`, as seen along `ray`, summed over every
+    /// light in the scene. Lights without area get a single shadow ray, area
+    /// lights are sampled `light_samples` times for soft shadows.
+    pub fn shade(&self, ray: &Ray, hit: &RayHit, sampler: &mut dyn Sampler) -> Color {
+        let material = self.material_of(hit);
+        let n = hit.normal;
+        let v = -ray.direction.normalize();
 
-        match self.ray_cast(&light_ray) {
-            Some(hit) => {
-                return image::Rgb([0, 0, 0]);
-            },
-            None => {
+        let mut color = material.emission(hit);
+        for light in self.lights.values() {
+            let samples = if light.is_delta() {
+                1
+            } else {
+                self.light_samples.max(1)
+            };
+
+            let mut light_color = Color::zeros();
+            for _ in 0..samples {
+                let u = sampler.get_2d();
+                let sample = match light.sample(&hit.near, &u) {
+                    Some(sample) if sample.pdf > 0.0 => sample,
+                    _ => continue,
+                };
+                let l = sample.direction;
                 let n_dot_l = n.dot(&l);
-                let val = num::clamp(n_dot_l * 255.0, 0.0, 255.0) as u8;
-                return image::Rgb([val, val, val]);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+
+                let light_ray = geometry::Ray {
+                    origin: hit.near,
+                    direction: l,
+                };
+                if self.occluded(&light_ray, sample.distance) {
+                    continue;
+                }
+
+                let h = (l + v).normalize();
+                let n_dot_h = n.dot(&h).max(0.0);
+                let reflected = material.diffuse(hit) * n_dot_l
+                    + material.specular(hit) * n_dot_h.powf(material.shininess(hit));
+                light_color += reflected.component_mul(&sample.radiance) / sample.pdf;
             }
+            color += light_color / samples as f32;
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    use crate::light::{DiskLight, PointLight};
+    use crate::sampler::IndependentSampler;
+
+    fn make_light(position: [f32; 3], color: [f32; 3], intensity: f32) -> Box<dyn Light> {
+        Box::new(PointLight {
+            pose: na::Isometry3::translation(position[0], position[1], position[2]),
+            color: Color::new(color[0], color[1], color[2]),
+            intensity,
+        })
+    }
+
+    // White ground plane at z = 0 facing up, seen from straight above
+    fn make_scene() -> (Scene, Ray) {
+        let mut scene = Scene::new();
+        scene.default_material = Arc::new(material::Surface::matte(Color::repeat(1.0)));
+        scene.add_shape(
+            "floor",
+            Box::new(shape::Plane {
+                pose: na::Isometry3::identity(),
+            }),
+        );
+        let ray = Ray {
+            origin: na::Point3::new(0.0, 0.0, 5.0),
+            direction: -na::Vector3::z(),
         };
+        (scene, ray)
+    }
+
+    fn shade(scene: &Scene, ray: &Ray) -> Color {
+        let hit = scene.ray_cast(ray).unwrap();
+        let mut sampler = IndependentSampler::new(0);
+        sampler.start_sample(&na::Point2::origin(), 0, 1);
+        scene.shade(ray, &hit, &mut sampler)
+    }
+
+    #[test]
+    fn no_lights() {
+        let (scene, ray) = make_scene();
+        assert_eq!(shade(&scene, &ray), Color::zeros());
+    }
+
+    #[test]
+    fn inverse_square_falloff() {
+        let (mut scene, ray) = make_scene();
+        scene.add_light("near", make_light([0.0, 0.0, 1.0], [1.0, 1.0, 1.0], 1.0));
+        let near = shade(&scene, &ray);
+        assert!(relative_eq!(near, Color::new(1.0, 1.0, 1.0)));
+
+        scene.add_light("near", make_light([0.0, 0.0, 2.0], [1.0, 1.0, 1.0], 1.0));
+        let far = shade(&scene, &ray);
+        assert!(relative_eq!(far, near / 4.0));
+    }
+
+    #[test]
+    fn lights_are_summed() {
+        let (mut scene, ray) = make_scene();
+        scene.add_light("red", make_light([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], 0.5));
+        scene.add_light("green", make_light([0.0, 0.0, 2.0], [0.0, 1.0, 0.0], 2.0));
+        // Lights below the floor do not contribute
+        scene.add_light("below", make_light([0.0, 0.0, -1.0], [1.0, 1.0, 1.0], 1.0));
+        assert!(relative_eq!(shade(&scene, &ray), Color::new(0.5, 0.5, 0.0)));
+    }
+
+    #[test]
+    fn shadow_ray_per_light() {
+        let (mut scene, ray) = make_scene();
+        scene.add_shape(
+            "blocker",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(3.0, 0.0, 1.0),
+                radius: 0.5,
+            }),
+        );
+        scene.add_light(
+            "shadowed",
+            make_light([6.0, 0.0, 2.0], [1.0, 1.0, 1.0], 10.0),
+        );
+        scene.add_light("lit", make_light([0.0, 0.0, 1.0], [1.0, 1.0, 1.0], 1.0));
+        assert!(relative_eq!(shade(&scene, &ray), Color::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn area_light_soft_shadow() {
+        let (mut scene, ray) = make_scene();
+        scene.add_light(
+            "disk",
+            Box::new(DiskLight {
+                pose: na::Isometry3::new(
+                    na::Vector3::new(0.0, 0.0, 2.0),
+                    na::Vector3::x() * std::f32::consts::PI,
+                ),
+                radius: 1.0,
+                color: Color::new(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            }),
+        );
+        scene.light_samples = 256;
+        let lit = shade(&scene, &ray).x;
+        assert!(relative_eq!(
+            lit,
+            std::f32::consts::PI / 5.0,
+            max_relative = 0.05
+        ));
+
+        // A blocker covering part of the disk leaves the floor in penumbra
+        scene.add_shape(
+            "blocker",
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(0.5, 0.0, 1.0),
+                radius: 0.3,
+            }),
+        );
+        let penumbra = shade(&scene, &ray).x;
+        assert!(penumbra > 0.1 * lit && penumbra < 0.9 * lit);
+    }
+
+    #[test]
+    fn shape_ids_follow_names() {
+        let (mut scene, _) = make_scene();
+        for name in ["b", "a", "c"].iter() {
+            scene.add_shape(
+                name,
+                Box::new(shape::Sphere {
+                    pose: na::Isometry3::identity(),
+                    radius: 1.0,
+                }),
+            );
+        }
+        let ids = scene.shape_ids();
+        assert_eq!(ids.len(), 4);
+        assert_eq!((ids["a"], ids["b"], ids["c"], ids["floor"]), (1, 2, 3, 4));
     }
 }
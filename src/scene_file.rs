@@ -0,0 +1,1145 @@
+extern crate log;
+extern crate nalgebra as na;
+extern crate serde;
+extern crate toml;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::info;
+use serde::Deserialize;
+
+use crate::camera::{
+    self, Camera, Distortion, OrthographicCamera, PerspectiveCamera, PinholeCamera,
+};
+use crate::graphics::GraphicsContext;
+use crate::lidar::{self, Lidar};
+use crate::light::{self, Light};
+use crate::material::{Color, Material, Surface};
+use crate::obj::{self, ObjError};
+use crate::scene::Scene;
+use crate::segmentation::{Palette, BACKGROUND_CLASS};
+use crate::shape::{self, Shape};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Malformed TOML, or a value of the wrong type. The message names the
+    /// line and column.
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// Well-formed file describing an impossible scene, e.g. a reference to
+    /// an undefined material.
+    Invalid {
+        path: PathBuf,
+        field: String,
+        message: String,
+    },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Invalid {
+                path,
+                field,
+                message,
+            } => write!(f, "{}: {}: {}", path.display(), field, message),
+            SceneError::Obj(source) => source.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse { source, .. } => Some(source),
+            SceneError::Invalid { .. } => None,
+            SceneError::Obj(source) => Some(source),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(source: ObjError) -> Self {
+        SceneError::Obj(source)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    image: ImageDesc,
+    camera: CameraDesc,
+    background: Option<[f32; 3]>,
+    #[serde(default)]
+    frames: BTreeMap<String, PoseDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    shapes: Vec<ShapeDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+    /// Colours of classes in segmentation masks.
+    #[serde(default)]
+    palette: BTreeMap<String, [u8; 3]>,
+    #[serde(default)]
+    lidars: Vec<LidarDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageDesc {
+    width: u32,
+    height: u32,
+}
+
+impl Default for ImageDesc {
+    fn default() -> Self {
+        ImageDesc {
+            width: 1024,
+            height: 1024,
+        }
+    }
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+/// Vertical field of view of perspective cameras in degrees, if not given.
+const DEFAULT_FOV: f32 = 36.0;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CameraKind {
+    #[default]
+    Perspective,
+    Orthographic,
+    /// A calibrated camera given by its intrinsics and lens distortion.
+    Pinhole,
+}
+
+impl CameraKind {
+    fn name(&self) -> &'static str {
+        match self {
+            CameraKind::Perspective => "perspective",
+            CameraKind::Orthographic => "orthographic",
+            CameraKind::Pinhole => "pinhole",
+        }
+    }
+
+    /// Optional camera settings used by this kind of camera.
+    fn fields(&self) -> &'static [&'static str] {
+        match self {
+            CameraKind::Perspective => &["fov", "aperture_radius", "focus_distance"],
+            CameraKind::Orthographic => &["view_height"],
+            CameraKind::Pinhole => &["fx", "fy", "cx", "cy", "distortion"],
+        }
+    }
+}
+
+/// Lens distortion with OpenCV's coefficients, zero if not given.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case", deny_unknown_fields)]
+enum DistortionDesc {
+    BrownConrady {
+        #[serde(default)]
+        k1: f32,
+        #[serde(default)]
+        k2: f32,
+        #[serde(default)]
+        p1: f32,
+        #[serde(default)]
+        p2: f32,
+        #[serde(default)]
+        k3: f32,
+    },
+    Fisheye {
+        #[serde(default)]
+        k1: f32,
+        #[serde(default)]
+        k2: f32,
+        #[serde(default)]
+        k3: f32,
+        #[serde(default)]
+        k4: f32,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    #[serde(rename = "type", default)]
+    kind: CameraKind,
+    eye: [f32; 3],
+    target: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+    /// Vertical field of view of a perspective camera in degrees.
+    fov: Option<f32>,
+    /// Radius of a perspective camera's lens for depth of field, zero for a
+    /// pinhole.
+    aperture_radius: Option<f32>,
+    /// Distance of the plane in focus, the distance to the target if unset.
+    focus_distance: Option<f32>,
+    /// Height of the area an orthographic camera sees, in world units.
+    view_height: Option<f32>,
+    /// Focal lengths of a pinhole camera in pixels. `fy` defaults to `fx`.
+    fx: Option<f32>,
+    fy: Option<f32>,
+    /// Principal point of a pinhole camera in pixels, the centre of the image
+    /// if unset.
+    cx: Option<f32>,
+    cy: Option<f32>,
+    distortion: Option<DistortionDesc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PoseDesc {
+    #[serde(default)]
+    translation: [f32; 3],
+    /// Rotation axis scaled by the angle in degrees.
+    #[serde(default)]
+    rotation: [f32; 3],
+    /// Name of the frame the pose is relative to, the world if unset.
+    frame: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDesc {
+    diffuse: Option<[f32; 3]>,
+    specular: Option<[f32; 3]>,
+    shininess: Option<f32>,
+    emission: Option<[f32; 3]>,
+    reflective: Option<[f32; 3]>,
+    ior: Option<f32>,
+    opacity: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ShapeKind {
+    Sphere,
+    Plane,
+    Triangle,
+    /// Groups of an OBJ file, named `<name>/<group>` in the scene.
+    Mesh,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShapeDesc {
+    name: String,
+    #[serde(rename = "type")]
+    kind: ShapeKind,
+    #[serde(default)]
+    pose: PoseDesc,
+    material: Option<String>,
+    radius: Option<f32>,
+    vertices: Option<Vec<[f32; 3]>>,
+    /// OBJ file of a mesh, relative to the scene file.
+    path: Option<PathBuf>,
+    /// Semantic class, for segmentation masks.
+    class: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LightKind {
+    Point,
+    Directional,
+    Spot,
+    Rect,
+    Disk,
+    Sphere,
+}
+
+fn default_color() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDesc {
+    name: String,
+    #[serde(rename = "type")]
+    kind: LightKind,
+    #[serde(default)]
+    pose: PoseDesc,
+    #[serde(default = "default_color")]
+    color: [f32; 3],
+    intensity: f32,
+    radius: Option<f32>,
+    width: Option<f32>,
+    height: Option<f32>,
+    /// Half-angle of a spot light's cone, in degrees.
+    cone_angle: Option<f32>,
+    /// Angle from the axis at which a spot light starts to fall off, in
+    /// degrees. Defaults to the cone angle, for a hard edge.
+    falloff_angle: Option<f32>,
+}
+
+/// A lidar's channels, either each elevation or a number of channels spread
+/// evenly over a range. Angles are in degrees.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LidarDesc {
+    name: String,
+    #[serde(default)]
+    pose: PoseDesc,
+    elevations: Option<Vec<f32>>,
+    channels: Option<usize>,
+    /// Lowest and highest elevation of evenly spread channels.
+    elevation_range: Option<[f32; 2]>,
+    /// Angle between firings.
+    horizontal_resolution: f32,
+    #[serde(default)]
+    min_range: f32,
+    max_range: f32,
+}
+
+/// Scene and camera described by a scene file.
+pub struct LoadedScene {
+    pub scene: Scene,
+    pub context: GraphicsContext,
+    pub palette: Palette,
+    /// Lidars by name.
+    pub lidars: BTreeMap<String, Lidar>,
+}
+
+/// Loads a TOML scene description, see `scenes/default.toml` for an example.
+/// Relative mesh paths are resolved against the directory of the file.
+pub fn load_scene(path: &Path) -> Result<LoadedScene, SceneError> {
+    load_scene_with(path, &Overrides::default())
+}
+
+/// Parses a TOML scene description. `path` is used for error messages and to
+/// resolve relative mesh paths.
+pub fn parse_scene(text: &str, path: &Path) -> Result<LoadedScene, SceneError> {
+    parse_scene_with(text, path, &Overrides::default())
+}
+
+/// Settings that take precedence over the ones in a scene file, e.g. from the
+/// command line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Vertical field of view in degrees.
+    pub fov: Option<f32>,
+}
+
+/// Like `load_scene`, with some of the file's settings replaced.
+pub fn load_scene_with(path: &Path, overrides: &Overrides) -> Result<LoadedScene, SceneError> {
+    let text = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene_with(&text, path, overrides)
+}
+
+/// Like `parse_scene`, with some of the file's settings replaced.
+pub fn parse_scene_with(
+    text: &str,
+    path: &Path,
+    overrides: &Overrides,
+) -> Result<LoadedScene, SceneError> {
+    let mut file: SceneFile = toml::from_str(text).map_err(|source| SceneError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if let Some(width) = overrides.width {
+        file.image.width = width;
+    }
+    if let Some(height) = overrides.height {
+        file.image.height = height;
+    }
+    if let Some(fov) = overrides.fov {
+        file.camera.fov = Some(fov);
+    }
+    let loaded = Loader {
+        path,
+        file: &file,
+        frames: HashMap::new(),
+    }
+    .build()?;
+    info!(
+        "Loaded {} shapes and {} lights from {}",
+        loaded.scene.shapes.len(),
+        loaded.scene.lights.len(),
+        path.display()
+    );
+    Ok(loaded)
+}
+
+fn vector(v: &[f32; 3]) -> na::Vector3<f32> {
+    na::Vector3::new(v[0], v[1], v[2])
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    file: &'a SceneFile,
+    /// Frames resolved to world space so far.
+    frames: HashMap<String, na::Isometry3<f32>>,
+}
+
+impl<'a> Loader<'a> {
+    fn invalid<S: Into<String>>(&self, field: S, message: &str) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            field: field.into(),
+            message: message.to_string(),
+        }
+    }
+
+    fn positive(&self, field: String, value: Option<f32>) -> Result<f32, SceneError> {
+        match value {
+            Some(value) if value > 0.0 => Ok(value),
+            Some(_) => Err(self.invalid(field, "must be positive")),
+            None => Err(self.invalid(field, "is required")),
+        }
+    }
+
+    /// World space pose of the frame `name`. `visiting` holds the frames whose
+    /// resolution is in progress, to detect cycles.
+    fn resolve_frame(
+        &mut self,
+        name: &str,
+        field: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<na::Isometry3<f32>, SceneError> {
+        if let Some(pose) = self.frames.get(name) {
+            return Ok(*pose);
+        }
+        let desc = match self.file.frames.get(name) {
+            Some(desc) => desc,
+            None => return Err(self.invalid(field, &format!("unknown frame '{}'", name))),
+        };
+        if visiting.iter().any(|v| v == name) {
+            return Err(self.invalid(
+                format!("frames.{}", name),
+                &format!("frames form a cycle: {} -> {}", visiting.join(" -> "), name),
+            ));
+        }
+        visiting.push(name.to_string());
+        let pose = self.resolve_pose(desc, &format!("frames.{}.frame", name), visiting)?;
+        visiting.pop();
+        self.frames.insert(name.to_string(), pose);
+        Ok(pose)
+    }
+
+    fn resolve_pose(
+        &mut self,
+        desc: &PoseDesc,
+        field: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<na::Isometry3<f32>, SceneError> {
+        let local = na::Isometry3::new(
+            vector(&desc.translation),
+            vector(&desc.rotation) * std::f32::consts::PI / 180.0,
+        );
+        match &desc.frame {
+            Some(frame) => Ok(self.resolve_frame(frame, field, visiting)? * local),
+            None => Ok(local),
+        }
+    }
+
+    fn pose(&mut self, desc: &PoseDesc, field: String) -> Result<na::Isometry3<f32>, SceneError> {
+        self.resolve_pose(desc, &format!("{}.pose.frame", field), &mut Vec::new())
+    }
+
+    fn build(mut self) -> Result<LoadedScene, SceneError> {
+        let file = self.file;
+        let mut scene = Scene::new();
+        if let Some(background) = &file.background {
+            scene.background = vector(background);
+        }
+
+        let mut materials: HashMap<&str, Arc<dyn Material>> = HashMap::new();
+        for (name, desc) in &file.materials {
+            materials.insert(name, Arc::new(make_surface(desc)));
+        }
+
+        for (idx, desc) in file.shapes.iter().enumerate() {
+            let field = format!("shapes[{}]", idx);
+            if scene.shapes.contains_key(&desc.name) {
+                return Err(self.invalid(
+                    format!("{}.name", field),
+                    &format!("duplicate shape name '{}'", desc.name),
+                ));
+            }
+            let material = match &desc.material {
+                Some(name) => match materials.get(name.as_str()) {
+                    Some(material) => Some(material.clone()),
+                    None => {
+                        return Err(self.invalid(
+                            format!("{}.material", field),
+                            &format!("unknown material '{}'", name),
+                        ))
+                    }
+                },
+                None => None,
+            };
+            let pose = self.pose(&desc.pose, field.clone())?;
+
+            let names = match desc.kind {
+                ShapeKind::Mesh => self.add_mesh(&mut scene, desc, &field, &pose)?,
+                _ => {
+                    let shape = self.make_shape(desc, &field, pose)?;
+                    scene.add_shape(&desc.name, shape);
+                    vec![desc.name.clone()]
+                }
+            };
+            if let Some(material) = material {
+                for name in &names {
+                    scene.set_material(name, material.clone());
+                }
+            }
+            if let Some(class) = &desc.class {
+                for name in &names {
+                    scene.set_class(name, class);
+                }
+            }
+        }
+
+        let mut palette = Palette::default();
+        for (class, color) in &file.palette {
+            if class != BACKGROUND_CLASS && !scene.classes.values().any(|c| c == class) {
+                return Err(self.invalid(
+                    format!("palette.{}", class),
+                    &format!("no shape has class '{}'", class),
+                ));
+            }
+            palette.colors.insert(class.clone(), *color);
+        }
+
+        for (idx, desc) in file.lights.iter().enumerate() {
+            let field = format!("lights[{}]", idx);
+            let pose = self.pose(&desc.pose, field.clone())?;
+            if scene.lights.contains_key(&desc.name) {
+                return Err(self.invalid(
+                    format!("{}.name", field),
+                    &format!("duplicate light name '{}'", desc.name),
+                ));
+            }
+            let light = self.make_light(desc, &field, pose)?;
+            scene.add_light(&desc.name, light);
+        }
+        scene.rebuild_bvh();
+
+        let mut lidars = BTreeMap::new();
+        for (idx, desc) in file.lidars.iter().enumerate() {
+            let field = format!("lidars[{}]", idx);
+            if lidars.contains_key(&desc.name) {
+                return Err(self.invalid(
+                    format!("{}.name", field),
+                    &format!("duplicate lidar name '{}'", desc.name),
+                ));
+            }
+            let pose = self.pose(&desc.pose, field.clone())?;
+            let lidar = self.make_lidar(desc, &field, pose)?;
+            lidars.insert(desc.name.clone(), lidar);
+        }
+
+        let context = self.make_context()?;
+        Ok(LoadedScene {
+            scene,
+            context,
+            palette,
+            lidars,
+        })
+    }
+
+    fn make_shape(
+        &self,
+        desc: &ShapeDesc,
+        field: &str,
+        pose: na::Isometry3<f32>,
+    ) -> Result<Box<dyn Shape>, SceneError> {
+        Ok(match desc.kind {
+            ShapeKind::Sphere => Box::new(shape::Sphere {
+                pose,
+                radius: self.positive(format!("{}.radius", field), desc.radius)?,
+            }),
+            ShapeKind::Plane => Box::new(shape::Plane { pose }),
+            ShapeKind::Triangle => {
+                let vertices = match &desc.vertices {
+                    Some(vertices) if vertices.len() == 3 => vertices,
+                    Some(_) => {
+                        return Err(
+                            self.invalid(format!("{}.vertices", field), "must have 3 vertices")
+                        )
+                    }
+                    None => return Err(self.invalid(format!("{}.vertices", field), "is required")),
+                };
+                let point = |v: &[f32; 3]| na::Point3::new(v[0], v[1], v[2]);
+                Box::new(shape::Triangle {
+                    pose,
+                    vertices: [
+                        point(&vertices[0]),
+                        point(&vertices[1]),
+                        point(&vertices[2]),
+                    ],
+                })
+            }
+            ShapeKind::Mesh => unreachable!("meshes are loaded by add_mesh"),
+        })
+    }
+
+    fn add_mesh(
+        &self,
+        scene: &mut Scene,
+        desc: &ShapeDesc,
+        field: &str,
+        pose: &na::Isometry3<f32>,
+    ) -> Result<Vec<String>, SceneError> {
+        let relative = match &desc.path {
+            Some(path) => path,
+            None => return Err(self.invalid(format!("{}.path", field), "is required")),
+        };
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let mut model = obj::load_obj(&base_dir.join(relative))?;
+        for group in model.groups.iter_mut() {
+            group.name = format!("{}/{}", desc.name, group.name);
+        }
+        Ok(model.add_to_scene(scene, pose)?)
+    }
+
+    fn make_light(
+        &self,
+        desc: &LightDesc,
+        field: &str,
+        pose: na::Isometry3<f32>,
+    ) -> Result<Box<dyn Light>, SceneError> {
+        let color = vector(&desc.color);
+        let intensity = desc.intensity;
+        let positive =
+            |name: &str, value: Option<f32>| self.positive(format!("{}.{}", field, name), value);
+        Ok(match desc.kind {
+            LightKind::Point => Box::new(light::PointLight {
+                pose,
+                color,
+                intensity,
+            }),
+            LightKind::Directional => Box::new(light::DirectionalLight {
+                pose,
+                color,
+                intensity,
+            }),
+            LightKind::Spot => {
+                let cone_angle = positive("cone_angle", desc.cone_angle)?;
+                let falloff_angle = desc.falloff_angle.unwrap_or(cone_angle);
+                Box::new(light::SpotLight {
+                    pose,
+                    color,
+                    intensity,
+                    cone_angle: cone_angle.to_radians(),
+                    falloff_angle: falloff_angle.to_radians(),
+                })
+            }
+            LightKind::Rect => Box::new(light::RectLight {
+                pose,
+                width: positive("width", desc.width)?,
+                height: positive("height", desc.height)?,
+                color,
+                intensity,
+            }),
+            LightKind::Disk => Box::new(light::DiskLight {
+                pose,
+                radius: positive("radius", desc.radius)?,
+                color,
+                intensity,
+            }),
+            LightKind::Sphere => Box::new(light::SphereLight {
+                pose,
+                radius: positive("radius", desc.radius)?,
+                color,
+                intensity,
+            }),
+        })
+    }
+
+    fn make_lidar(
+        &self,
+        desc: &LidarDesc,
+        field: &str,
+        pose: na::Isometry3<f32>,
+    ) -> Result<Lidar, SceneError> {
+        let elevations = match (&desc.elevations, desc.channels, desc.elevation_range) {
+            (Some(elevations), None, None) => elevations.clone(),
+            (None, Some(channels), Some([lower, upper])) => {
+                if lower > upper {
+                    return Err(self.invalid(
+                        format!("{}.elevation_range", field),
+                        "must be from lowest to highest",
+                    ));
+                }
+                lidar::uniform_elevations(channels, lower, upper)
+            }
+            _ => {
+                return Err(self.invalid(
+                    field,
+                    "needs either elevations or channels and elevation_range",
+                ))
+            }
+        };
+        if elevations.is_empty() {
+            return Err(self.invalid(format!("{}.elevations", field), "must not be empty"));
+        }
+        let max_range = self.positive(format!("{}.max_range", field), Some(desc.max_range))?;
+        if desc.min_range < 0.0 || desc.min_range >= max_range {
+            return Err(self.invalid(
+                format!("{}.min_range", field),
+                "must be from zero up to max_range",
+            ));
+        }
+        let horizontal_resolution = self.positive(
+            format!("{}.horizontal_resolution", field),
+            Some(desc.horizontal_resolution),
+        )?;
+        Ok(Lidar {
+            pose,
+            elevations: elevations.iter().map(|e| e.to_radians()).collect(),
+            horizontal_resolution: horizontal_resolution.to_radians(),
+            min_range: desc.min_range,
+            max_range,
+        })
+    }
+
+    fn make_context(&self) -> Result<GraphicsContext, SceneError> {
+        let image = &self.file.image;
+        if image.width == 0 || image.height == 0 {
+            return Err(self.invalid("image", "width and height must be positive"));
+        }
+        let camera = &self.file.camera;
+        let eye = na::Point3::from(vector(&camera.eye));
+        let target = na::Point3::from(vector(&camera.target));
+        let up = vector(&camera.up);
+        if (target - eye).cross(&up).norm() <= 0.0 {
+            return Err(self.invalid("camera.up", "must not be parallel to the view direction"));
+        }
+
+        let given = [
+            ("fov", camera.fov.is_some()),
+            ("aperture_radius", camera.aperture_radius.is_some()),
+            ("focus_distance", camera.focus_distance.is_some()),
+            ("view_height", camera.view_height.is_some()),
+            ("fx", camera.fx.is_some()),
+            ("fy", camera.fy.is_some()),
+            ("cx", camera.cx.is_some()),
+            ("cy", camera.cy.is_some()),
+            ("distortion", camera.distortion.is_some()),
+        ];
+        for (field, is_given) in given.iter() {
+            if *is_given && !camera.kind.fields().contains(field) {
+                return Err(self.invalid(
+                    format!("camera.{}", field),
+                    &format!("is not used by {} cameras", camera.kind.name()),
+                ));
+            }
+        }
+
+        let camera: Box<dyn Camera> = match camera.kind {
+            CameraKind::Perspective => {
+                let fov = camera.fov.unwrap_or(DEFAULT_FOV);
+                if fov <= 0.0 || fov >= 180.0 {
+                    return Err(self.invalid("camera.fov", "must be between 0 and 180 degrees"));
+                }
+                let mut perspective = PerspectiveCamera::look_at(
+                    &eye,
+                    &target,
+                    &up,
+                    fov.to_radians(),
+                    image.width,
+                    image.height,
+                );
+                if let Some(aperture_radius) = camera.aperture_radius {
+                    if aperture_radius < 0.0 {
+                        return Err(self.invalid("camera.aperture_radius", "must not be negative"));
+                    }
+                    perspective.aperture_radius = aperture_radius;
+                }
+                if camera.focus_distance.is_some() {
+                    perspective.focus_distance =
+                        self.positive("camera.focus_distance".to_string(), camera.focus_distance)?;
+                }
+                Box::new(perspective)
+            }
+            CameraKind::Orthographic => {
+                let view_height =
+                    self.positive("camera.view_height".to_string(), camera.view_height)?;
+                Box::new(OrthographicCamera::look_at(
+                    &eye,
+                    &target,
+                    &up,
+                    view_height,
+                    image.width,
+                    image.height,
+                ))
+            }
+            CameraKind::Pinhole => {
+                let fx = self.positive("camera.fx".to_string(), camera.fx)?;
+                let fy = self.positive("camera.fy".to_string(), camera.fy.or(camera.fx))?;
+                let distortion = match camera.distortion {
+                    None => Distortion::None,
+                    Some(DistortionDesc::BrownConrady { k1, k2, p1, p2, k3 }) => {
+                        Distortion::BrownConrady { k1, k2, p1, p2, k3 }
+                    }
+                    Some(DistortionDesc::Fisheye { k1, k2, k3, k4 }) => {
+                        Distortion::Fisheye { k1, k2, k3, k4 }
+                    }
+                };
+                Box::new(PinholeCamera {
+                    pose: camera::look_at(&eye, &target, &up),
+                    fx,
+                    fy,
+                    cx: camera.cx.unwrap_or(image.width as f32 / 2.0),
+                    cy: camera.cy.unwrap_or(image.height as f32 / 2.0),
+                    distortion,
+                    width: image.width,
+                    height: image.height,
+                })
+            }
+        };
+        Ok(GraphicsContext::new(camera))
+    }
+}
+
+fn make_surface(desc: &MaterialDesc) -> Surface {
+    let defaults = Surface::default();
+    let color = |value: &Option<[f32; 3]>, default: Color| value.as_ref().map_or(default, vector);
+    Surface {
+        diffuse: color(&desc.diffuse, defaults.diffuse),
+        specular: color(&desc.specular, defaults.specular),
+        shininess: desc.shininess.unwrap_or(defaults.shininess),
+        emission: color(&desc.emission, defaults.emission),
+        reflective: color(&desc.reflective, defaults.reflective),
+        ior: desc.ior.unwrap_or(defaults.ior),
+        opacity: desc.opacity.unwrap_or(defaults.opacity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    const DEFAULT_SCENE: &str = include_str!("../scenes/default.toml");
+
+    fn parse(text: &str) -> Result<LoadedScene, SceneError> {
+        parse_scene(text, Path::new("test.toml"))
+    }
+
+    fn error_message(text: &str) -> String {
+        match parse(text) {
+            Ok(_) => panic!("Scene should not load:\n{}", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    const CAMERA: &str = "[camera]\neye = [0, 0, 0]\ntarget = [0, 0, -1]\n";
+
+    #[test]
+    fn default_scene() {
+        let loaded = parse(DEFAULT_SCENE).unwrap();
+        let scene = &loaded.scene;
+        assert_eq!(scene.shapes.len(), 3);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.has_bvh());
+        assert!(scene.get_material("sphere_one").is_some());
+        assert!(scene.get_material("floor").is_none());
+        assert_eq!(loaded.context.img_width, 1024);
+
+        // Seen where it was when the scene was built in view space, relative
+        // to a camera looking down -Z with +Y up
+        let target = na::Isometry3::translation(0.0, 0.0, -10.0)
+            * na::Isometry3::new(
+                na::Vector3::y() * -0.85,
+                na::Vector3::x() * std::f32::consts::PI / -2.4,
+            )
+            * na::Isometry3::rotation(na::Vector3::z() * std::f32::consts::PI * 3.5 / 4.0);
+        let view = target * na::Point3::new(-1.15, 0.0, 0.0);
+        let center = scene.get_shape("sphere_two").unwrap().origin() * na::Point3::origin();
+        let seen = loaded
+            .context
+            .camera
+            .pose()
+            .inverse_transform_point(&center);
+        assert!(relative_eq!(
+            seen,
+            na::Point3::new(view.x, -view.y, -view.z),
+            epsilon = 1.0e-2
+        ));
+    }
+
+    #[test]
+    fn overrides() {
+        let overrides = Overrides {
+            width: Some(64),
+            fov: Some(90.0),
+            ..Default::default()
+        };
+        let loaded = parse_scene_with(DEFAULT_SCENE, Path::new("test.toml"), &overrides).unwrap();
+        assert_eq!(loaded.context.camera.resolution(), (64, 1024));
+        assert_eq!(loaded.context.img_width, 64);
+
+        let overrides = Overrides {
+            fov: Some(180.0),
+            ..Default::default()
+        };
+        match parse_scene_with(CAMERA, Path::new("test.toml"), &overrides) {
+            Err(err) => assert!(err.to_string().contains("camera.fov"), "{}", err),
+            Ok(_) => panic!("A 180 degree field of view should be rejected"),
+        }
+    }
+
+    #[test]
+    fn orthographic_camera() {
+        let text = "[camera]\ntype = \"orthographic\"\neye = [0, 0, 5]\ntarget = [0, 0, 0]\nview_height = 2\n";
+        let loaded = parse(text).unwrap();
+        let ray = loaded
+            .context
+            .camera
+            .generate_ray(&na::Point2::new(0.0, 0.0), &na::Point2::new(0.5, 0.5));
+        assert!(relative_eq!(ray.origin, na::Point3::new(-1.0, 1.0, 5.0)));
+
+        let message = error_message(&text.replace("view_height = 2", "fov = 40"));
+        assert!(message.contains("camera.fov"), "{}", message);
+        let message = error_message(&text.replace("view_height = 2", ""));
+        assert!(message.contains("camera.view_height"), "{}", message);
+        let message = error_message(&text.replace("view_height = 2", "aperture_radius = 0.1"));
+        assert!(message.contains("camera.aperture_radius"), "{}", message);
+    }
+
+    #[test]
+    fn pinhole_camera() {
+        let text = "[image]\nwidth = 640\nheight = 480\n\n[camera]\ntype = \"pinhole\"\neye = [0, 0, 0]\ntarget = [0, 0, 1]\nfx = 400\n\n[camera.distortion]\nmodel = \"brown_conrady\"\nk1 = -0.1\n";
+        let loaded = parse(text).unwrap();
+        let camera = &loaded.context.camera;
+        assert_eq!(camera.resolution(), (640, 480));
+        let projected = camera.project_point(&na::Point3::new(0.0, 0.0, 3.0));
+        assert_eq!(projected, Some(na::Point2::new(320.0, 240.0)));
+
+        let message = error_message(&text.replace("k1 = -0.1", "k4 = -0.1"));
+        assert!(message.contains("k4"), "{}", message);
+        let message = error_message(&text.replace("fx = 400", "fov = 40"));
+        assert!(message.contains("camera.fov"), "{}", message);
+        let message = error_message(&text.replace("fx = 400", "fy = 400"));
+        assert_eq!(message, "test.toml: camera.fx: is required");
+    }
+
+    #[test]
+    fn syntax_error_names_line() {
+        let message = error_message(&format!("{}\n[[shapes]\nname = \"a\"\n", CAMERA));
+        assert!(message.contains("line 5"), "{}", message);
+    }
+
+    #[test]
+    fn type_error_names_field() {
+        let text = format!(
+            "{}\n[[shapes]]\nname = \"ball\"\ntype = \"sphere\"\nradius = \"big\"\n",
+            CAMERA
+        );
+        let message = error_message(&text);
+        assert!(message.contains("radius"), "{}", message);
+        assert!(message.contains("line"), "{}", message);
+
+        let text = format!("{}\n[[shapes]]\nname = \"ball\"\ntype = \"cube\"\n", CAMERA);
+        let message = error_message(&text);
+        assert!(message.contains("cube"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let text = format!("{}fov_degrees = 40\n", CAMERA);
+        let message = error_message(&text);
+        assert!(message.contains("fov_degrees"), "{}", message);
+    }
+
+    #[test]
+    fn invalid_values_name_field() {
+        let text = format!(
+            "{}\n[[shapes]]\nname = \"ball\"\ntype = \"sphere\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: shapes[0].radius: is required"
+        );
+
+        let text = format!(
+            "{}\n[[shapes]]\nname = \"floor\"\ntype = \"plane\"\nmaterial = \"wood\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: shapes[0].material: unknown material 'wood'"
+        );
+
+        let text = format!(
+            "{}\n[[lights]]\nname = \"sun\"\ntype = \"point\"\nintensity = 1\npose = {{ frame = \"sky\" }}\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: lights[0].pose.frame: unknown frame 'sky'"
+        );
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let text = format!(
+            "{}\n[[shapes]]\nname = \"ball\"\ntype = \"sphere\"\nradius = 1\n\n[[shapes]]\nname = \"ball\"\ntype = \"plane\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: shapes[1].name: duplicate shape name 'ball'"
+        );
+
+        let text = format!(
+            "{}\n[[lights]]\nname = \"sun\"\ntype = \"point\"\nintensity = 1\n\n[[lights]]\nname = \"sun\"\ntype = \"point\"\nintensity = 2\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: lights[1].name: duplicate light name 'sun'"
+        );
+    }
+
+    #[test]
+    fn frame_cycle() {
+        let text = format!(
+            "{}\n[frames.a]\nframe = \"b\"\n\n[frames.b]\nframe = \"a\"\n\n[[shapes]]\nname = \"floor\"\ntype = \"plane\"\npose = {{ frame = \"a\" }}\n",
+            CAMERA
+        );
+        let message = error_message(&text);
+        assert!(message.contains("cycle"), "{}", message);
+    }
+
+    #[test]
+    fn classes_and_palette() {
+        let text = format!(
+            "{}
+[palette]
+toy = [255, 0, 0]
+background = [9, 9, 9]
+
+[[shapes]]
+name = \"ball\"
+type = \"sphere\"
+radius = 1
+class = \"toy\"
+
+[[shapes]]
+name = \"floor\"
+type = \"plane\"
+",
+            CAMERA
+        );
+        let loaded = parse(&text).unwrap();
+        assert_eq!(loaded.scene.get_class("ball"), Some("toy"));
+        assert_eq!(loaded.scene.get_class("floor"), None);
+        assert_eq!(loaded.palette.colors["toy"], [255, 0, 0]);
+        assert_eq!(loaded.palette.colors[BACKGROUND_CLASS], [9, 9, 9]);
+
+        let text = format!(
+            "{}
+[palette]
+toys = [255, 0, 0]
+",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&text),
+            "test.toml: palette.toys: no shape has class 'toys'"
+        );
+    }
+
+    #[test]
+    fn lidars() {
+        let text = format!(
+            "{}
+[frames.car]
+translation = [1, 0, 0]
+
+[[lidars]]
+name = \"roof\"
+pose = {{ translation = [0, 0, 2], frame = \"car\" }}
+channels = 3
+elevation_range = [-10, 10]
+horizontal_resolution = 0.5
+max_range = 80
+
+[[lidars]]
+name = \"bumper\"
+elevations = [-2, 0]
+horizontal_resolution = 1
+min_range = 0.5
+max_range = 30
+",
+            CAMERA
+        );
+        let loaded = parse(&text).unwrap();
+        let names: Vec<&String> = loaded.lidars.keys().collect();
+        assert_eq!(names, ["bumper", "roof"]);
+        let roof = &loaded.lidars["roof"];
+        assert_eq!(
+            roof.pose.translation.vector,
+            na::Vector3::new(1.0, 0.0, 2.0)
+        );
+        assert_eq!(roof.elevations.len(), 3);
+        assert!(relative_eq!(roof.elevations[0], -10.0_f32.to_radians()));
+        assert_eq!(roof.columns(), 720);
+        assert_eq!(roof.min_range, 0.0);
+        assert_eq!(loaded.lidars["bumper"].min_range, 0.5);
+
+        let lidar = |fields: &str| {
+            let text = format!(
+                "{}
+[[lidars]]
+name = \"lidar\"
+horizontal_resolution = 1
+max_range = 30
+{}
+",
+                CAMERA, fields
+            );
+            error_message(&text)
+        };
+        assert_eq!(
+            lidar("channels = 16"),
+            "test.toml: lidars[0]: needs either elevations or channels and elevation_range"
+        );
+        assert_eq!(
+            lidar("elevations = []"),
+            "test.toml: lidars[0].elevations: must not be empty"
+        );
+        assert_eq!(
+            lidar("elevations = [0]\nmin_range = 40"),
+            "test.toml: lidars[0].min_range: must be from zero up to max_range"
+        );
+        assert_eq!(
+            lidar("channels = 2\nelevation_range = [10, -10]"),
+            "test.toml: lidars[0].elevation_range: must be from lowest to highest"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+extern crate image;
+extern crate nalgebra as na;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use log::warn;
+use serde::Serialize;
+
+use crate::aov::{AovBuffers, Gray16Image};
+use crate::scene::Scene;
+
+/// Colours of the classes in a class mask. Classes without a colour of their
+/// own get one from `id_color`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Palette {
+    pub colors: HashMap<String, [u8; 3]>,
+    /// Colour of pixels without a labelled shape.
+    pub background: [u8; 3],
+}
+
+impl Palette {
+    pub fn color(&self, class: &ClassInfo) -> [u8; 3] {
+        match self.colors.get(&class.name) {
+            Some(color) => *color,
+            None if class.id == 0 => self.background,
+            None => id_color(class.id),
+        }
+    }
+}
+
+/// A saturated colour for `id`, with hues spread by the golden angle so that
+/// consecutive IDs are easy to tell apart.
+pub fn id_color(id: u32) -> [u8; 3] {
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let channel = |value: f32| (55.0 + 200.0 * value).round() as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
+/// Pixels an instance covers in the image, `width` by `height` from the top
+/// left pixel (`x`, `y`).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClassInfo {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InstanceInfo {
+    /// The shape's ID, from `Scene::shape_ids`.
+    pub id: u32,
+    pub name: String,
+    pub class: Option<String>,
+    pub class_id: u32,
+    /// Number of pixels the instance covers.
+    pub pixels: u32,
+    /// None if the instance is not visible.
+    pub bbox: Option<BoundingBox>,
+}
+
+/// Instance and class masks of a frame, with what each ID stands for.
+#[derive(Clone, Debug)]
+pub struct Segmentation {
+    pub width: u32,
+    pub height: u32,
+    /// Instance ID per pixel, in row-major order from the top left. Zero
+    /// where nothing is hit.
+    pub instance_ids: Vec<u32>,
+    /// Every class by ID, numbered from one in order of their names. Class
+    /// zero, "background", covers pixels without a labelled shape, and
+    /// shapes labelled "background" themselves.
+    pub classes: Vec<ClassInfo>,
+    /// Every shape of the scene, by ID.
+    pub instances: Vec<InstanceInfo>,
+}
+
+/// Name of class zero.
+pub const BACKGROUND_CLASS: &str = "background";
+
+impl Segmentation {
+    /// Segments the frame of `aovs`, which should have been rendered from
+    /// `scene`. Pixels with IDs of no shape in `scene` are left out of every
+    /// instance, and are background in the class mask.
+    pub fn new(scene: &Scene, aovs: &AovBuffers) -> Self {
+        let mut class_names: Vec<&String> = scene
+            .classes
+            .values()
+            .filter(|name| name.as_str() != BACKGROUND_CLASS)
+            .collect();
+        class_names.sort();
+        class_names.dedup();
+        let classes: Vec<ClassInfo> = std::iter::once(BACKGROUND_CLASS)
+            .chain(class_names.into_iter().map(|name| name.as_str()))
+            .enumerate()
+            .map(|(id, name)| ClassInfo {
+                id: id as u32,
+                name: name.to_string(),
+            })
+            .collect();
+
+        let mut instances: Vec<InstanceInfo> = scene
+            .shape_ids()
+            .into_iter()
+            .map(|(name, id)| {
+                let class = scene.get_class(&name).map(|class| class.to_string());
+                let class_id = class.as_ref().map_or(0, |class| {
+                    classes.iter().position(|c| &c.name == class).unwrap_or(0) as u32
+                });
+                InstanceInfo {
+                    id,
+                    name,
+                    class,
+                    class_id,
+                    pixels: 0,
+                    bbox: None,
+                }
+            })
+            .collect();
+        instances.sort_by_key(|instance| instance.id);
+        let index: HashMap<u32, usize> = instances
+            .iter()
+            .enumerate()
+            .map(|(idx, instance)| (instance.id, idx))
+            .collect();
+
+        // Grow the bounding boxes over the mask
+        let mut unknown = 0;
+        for (idx, &id) in aovs.object_id.iter().enumerate() {
+            if id == 0 {
+                continue;
+            }
+            let instance = match index.get(&id) {
+                Some(&instance) => &mut instances[instance],
+                None => {
+                    unknown += 1;
+                    continue;
+                }
+            };
+            let (x, y) = (idx as u32 % aovs.width, idx as u32 / aovs.width);
+            instance.pixels += 1;
+            instance.bbox = Some(match instance.bbox {
+                None => BoundingBox {
+                    x,
+                    y,
+                    width: 1,
+                    height: 1,
+                },
+                Some(bbox) => {
+                    let (x0, y0) = (bbox.x.min(x), bbox.y.min(y));
+                    let x1 = (bbox.x + bbox.width).max(x + 1);
+                    let y1 = (bbox.y + bbox.height).max(y + 1);
+                    BoundingBox {
+                        x: x0,
+                        y: y0,
+                        width: x1 - x0,
+                        height: y1 - y0,
+                    }
+                }
+            });
+        }
+        if unknown > 0 {
+            warn!("{} pixels have IDs of no shape in the scene", unknown);
+        }
+
+        Segmentation {
+            width: aovs.width,
+            height: aovs.height,
+            instance_ids: aovs.object_id.clone(),
+            classes,
+            instances,
+        }
+    }
+
+    /// Instance IDs, which must fit in 16 bits. Larger IDs, of scenes with
+    /// more shapes than that, are clipped to the largest 16-bit value.
+    pub fn instance_mask(&self) -> Gray16Image {
+        if let Some(instance) = self.instances.last() {
+            if instance.id > u32::from(u16::MAX) {
+                warn!(
+                    "{} instances do not fit the 16-bit instance mask, IDs above {} are clipped",
+                    self.instances.len(),
+                    u16::MAX
+                );
+            }
+        }
+        Gray16Image::from_fn(self.width, self.height, |x, y| {
+            let id = self.instance_ids[(y * self.width + x) as usize];
+            image::Luma([id.min(u32::from(u16::MAX)) as u16])
+        })
+    }
+
+    /// Class of every pixel in the colour `palette` gives it.
+    pub fn class_mask(&self, palette: &Palette) -> image::RgbImage {
+        let colors: Vec<[u8; 3]> = self.classes.iter().map(|c| palette.color(c)).collect();
+        let instance_colors: HashMap<u32, [u8; 3]> = self
+            .instances
+            .iter()
+            .map(|instance| (instance.id, colors[instance.class_id as usize]))
+            .collect();
+        image::RgbImage::from_fn(self.width, self.height, |x, y| {
+            let id = self.instance_ids[(y * self.width + x) as usize];
+            image::Rgb(*instance_colors.get(&id).unwrap_or(&colors[0]))
+        })
+    }
+
+    /// Sidecar describing the masks, with the colour of each class in
+    /// `palette`.
+    pub fn to_json(&self, palette: &Palette) -> serde_json::Value {
+        #[derive(Serialize)]
+        struct ClassEntry<'a> {
+            #[serde(flatten)]
+            class: &'a ClassInfo,
+            color: [u8; 3],
+        }
+        let classes: Vec<ClassEntry> = self
+            .classes
+            .iter()
+            .map(|class| ClassEntry {
+                class,
+                color: palette.color(class),
+            })
+            .collect();
+        serde_json::json!({
+            "width": self.width,
+            "height": self.height,
+            "classes": classes,
+            "instances": self.instances,
+        })
+    }
+
+    pub fn save_json(&self, path: &Path, palette: &Palette) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &self.to_json(palette))?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::aov::render_aovs;
+    use crate::test_util::{floor_and_balls, top_down_camera};
+
+    /// Two labelled balls and an unlabelled one on an unlabelled floor, seen
+    /// from straight above.
+    fn make_scene() -> (Scene, AovBuffers) {
+        let mut scene = floor_and_balls(&[
+            ("a", [-3.0, 0.0, 1.0]),
+            ("b", [0.0, 0.0, 1.0]),
+            ("c", [3.0, 0.0, 1.0]),
+            // Hidden below the floor
+            ("d", [0.0, 0.0, -5.0]),
+        ]);
+        scene.set_class("a", "toy");
+        scene.set_class("b", "food");
+        scene.set_class("d", "toy");
+        let aovs = render_aovs(&scene, &top_down_camera(10.0, 100, 60));
+        (scene, aovs)
+    }
+
+    #[test]
+    fn classes_and_instances() {
+        let (scene, aovs) = make_scene();
+        let segmentation = Segmentation::new(&scene, &aovs);
+        let names: Vec<&str> = segmentation
+            .classes
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, [BACKGROUND_CLASS, "food", "toy"]);
+
+        let instance = |name: &str| {
+            segmentation
+                .instances
+                .iter()
+                .find(|i| i.name == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(instance("a").class_id, 2);
+        assert_eq!(instance("b").class_id, 1);
+        assert_eq!(instance("c").class_id, 0);
+        assert_eq!(instance("c").class, None);
+        assert_eq!(instance("d").bbox, None);
+        assert_eq!(instance("d").pixels, 0);
+
+        // The middle ball spans the same columns either side of the centre
+        let bbox = instance("b").bbox.unwrap();
+        assert_eq!(bbox.x + bbox.width / 2, 50);
+        assert_eq!(bbox.y + bbox.height / 2, 30);
+        assert_eq!(bbox.width, bbox.height);
+        assert!(instance("floor").bbox.unwrap().width == 100);
+    }
+
+    #[test]
+    fn masks() {
+        let (scene, aovs) = make_scene();
+        let segmentation = Segmentation::new(&scene, &aovs);
+        let b = segmentation
+            .instances
+            .iter()
+            .find(|i| i.name == "b")
+            .unwrap();
+        assert_eq!(
+            segmentation.instance_mask().get_pixel(50, 30).0[0],
+            b.id as u16
+        );
+
+        let mut palette = Palette::default();
+        palette.colors.insert("food".to_string(), [1, 2, 3]);
+        let mask = segmentation.class_mask(&palette);
+        assert_eq!(*mask.get_pixel(50, 30), image::Rgb([1, 2, 3]));
+        assert_eq!(*mask.get_pixel(0, 0), image::Rgb([0, 0, 0]));
+        assert_eq!(*mask.get_pixel(40, 30), image::Rgb(id_color(2)));
+        assert_ne!(id_color(1), id_color(2));
+    }
+
+    #[test]
+    fn foreign_ids_and_background_class() {
+        let (mut scene, mut aovs) = make_scene();
+        scene.set_class("floor", BACKGROUND_CLASS);
+        // As if rendered from a scene with more shapes
+        aovs.object_id[0] = 99;
+        let segmentation = Segmentation::new(&scene, &aovs);
+        let names: Vec<&str> = segmentation
+            .classes
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, [BACKGROUND_CLASS, "food", "toy"]);
+
+        let floor = segmentation
+            .instances
+            .iter()
+            .find(|i| i.name == "floor")
+            .unwrap();
+        assert_eq!(floor.class_id, 0);
+        assert_eq!(floor.bbox.unwrap().y, 0);
+        assert_eq!(
+            segmentation.instances.iter().map(|i| i.pixels).sum::<u32>(),
+            100 * 60 - 1
+        );
+
+        let mask = segmentation.class_mask(&Palette::default());
+        assert_eq!(*mask.get_pixel(0, 0), image::Rgb([0, 0, 0]));
+        assert_eq!(*mask.get_pixel(1, 0), image::Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn json_sidecar() {
+        let (scene, aovs) = make_scene();
+        let segmentation = Segmentation::new(&scene, &aovs);
+        let json = segmentation.to_json(&Palette::default());
+        assert_eq!(json["width"], 100);
+        assert_eq!(json["classes"][1]["name"], "food");
+        assert_eq!(json["classes"][0]["color"], serde_json::json!([0, 0, 0]));
+        let instances = json["instances"].as_array().unwrap();
+        assert_eq!(instances.len(), 5);
+        assert_eq!(instances[0]["name"], "a");
+        assert_eq!(instances[0]["class"], "toy");
+        assert!(instances[0]["bbox"]["width"].as_u64().unwrap() > 0);
+        assert!(instances[3]["bbox"].is_null());
+    }
+}
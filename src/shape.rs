@@ -4,13 +4,17 @@ extern crate nalgebra as na;
 extern crate simple_logging;
 
 use crate::geometry;
-use geometry::{Ray, RayHit};
+use geometry::{Aabb, Ray, RayHit};
 
 use std::fmt;
 
-pub trait Shape {
+pub trait Shape: Send + Sync {
     fn ray_cast(&self, ray: &Ray) -> Option<RayHit>;
     fn origin(&self) -> &na::Isometry3<f32>;
+
+    /// World-space axis-aligned bounds. Shapes without a finite extent return
+    /// bounds that are infinite along the unbounded axes.
+    fn bounds(&self) -> Aabb;
 }
 
 pub struct Plane {
@@ -19,7 +23,22 @@ pub struct Plane {
 
 impl Shape for Plane {
     fn origin(&self) -> &na::Isometry3<f32> {
-        return &self.pose;
+        &self.pose
+    }
+
+    fn bounds(&self) -> Aabb {
+        // An axis-aligned plane is a slab of zero thickness along its normal,
+        // any other orientation extends infinitely along every axis.
+        let n = self.pose * na::Vector3::z();
+        let mut bounds = Aabb::infinite();
+        let zero_axes: Vec<usize> = (0..3).filter(|&axis| n[axis] == 0.0).collect();
+        if zero_axes.len() == 2 {
+            let axis = 3 - zero_axes[0] - zero_axes[1];
+            let offset = self.pose.translation.vector[axis];
+            bounds.min[axis] = offset;
+            bounds.max[axis] = offset;
+        }
+        bounds
     }
 
     fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
@@ -41,6 +60,7 @@ impl Shape for Plane {
             near: near_hit,
             far: near_hit,
             normal: (self.pose * na::Vector3::z()).normalize(),
+            shape: None,
         })
     }
 }
@@ -52,38 +72,50 @@ pub struct Sphere {
 
 impl Shape for Sphere {
     fn origin(&self) -> &na::Isometry3<f32> {
-        return &self.pose;
+        &self.pose
+    }
+
+    fn bounds(&self) -> Aabb {
+        let center = self.pose * na::Point3::origin();
+        let extent = na::Vector3::repeat(self.radius);
+        Aabb::new(center - extent, center + extent)
     }
 
     fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
+        let center = self.pose * na::Point3::origin();
+        let direction = ray.direction.normalize();
+
         // L = C - O
-        let l = self.pose * na::Point3::origin() - ray.origin;
+        let l = center - ray.origin;
+        let inside = l.norm_squared() < self.radius.powi(2);
 
         // T_ca = L dot D
-        let t_ca = l.dot(&ray.direction.normalize());
-        if t_ca < 0.0 {
+        let t_ca = l.dot(&direction);
+        if t_ca < 0.0 && !inside {
             return None;
         }
 
         // d = sqrt(l^2 - T_ca^2)
-        let d = (l.dot(&l) - t_ca.powi(2)).sqrt();
-        if d > self.radius || d < 0.0 {
+        let d = (l.dot(&l) - t_ca.powi(2)).max(0.0).sqrt();
+        if d > self.radius {
             return None;
         }
 
         // T_hc = sqrt(r^2 - d^2)
         let t_hc = (self.radius.powi(2) - d.powi(2)).sqrt();
-        let t_0 = t_ca - t_hc;
         let t_1 = t_ca + t_hc;
+        // From inside the sphere the first hit is where the ray leaves it
+        let t_0 = if inside { t_1 } else { t_ca - t_hc };
 
-        let near_hit = ray.origin + (ray.direction * t_0);
-        let far_hit = ray.origin + (ray.direction * t_1);
-        let normal = (near_hit - self.pose * na::Point3::origin()).normalize();
+        let near_hit = ray.origin + (direction * t_0);
+        let far_hit = ray.origin + (direction * t_1);
+        let normal = (near_hit - center).normalize();
 
         Some(RayHit {
             near: near_hit,
             far: far_hit,
-            normal: normal,
+            normal,
+            shape: None,
         })
     }
 }
@@ -99,8 +131,240 @@ impl fmt::Display for Sphere {
     }
 }
 
-pub struct PointLight {
+/// Barycentric coordinates and ray parameter of a ray/triangle intersection.
+struct TriangleHit {
+    t: f32,
+    barycentric: [f32; 3],
+}
+
+fn max_dimension(v: &na::Vector3<f32>) -> usize {
+    let a = v.abs();
+    if a.x > a.y {
+        if a.x > a.z {
+            0
+        } else {
+            2
+        }
+    } else if a.y > a.z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin and Wald, 2013).
+///
+/// The ray is sheared into a space where it runs along +Z, so that the edge
+/// tests of neighbouring triangles sharing an edge are evaluated on exactly
+/// the same values and a ray can never slip through the crack between them.
+/// Both faces are hit; `t` must be strictly positive.
+fn intersect_triangle(
+    origin: &na::Point3<f32>,
+    direction: &na::Vector3<f32>,
+    vertices: [&na::Point3<f32>; 3],
+) -> Option<TriangleHit> {
+    // Permute the axes so the dominant direction component becomes Z
+    let kz = max_dimension(direction);
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    if direction[kz] == 0.0 {
+        return None;
+    }
+
+    // Shear constants
+    let s_x = direction[kx] / direction[kz];
+    let s_y = direction[ky] / direction[kz];
+    let s_z = 1.0 / direction[kz];
+
+    let a = vertices[0] - origin;
+    let b = vertices[1] - origin;
+    let c = vertices[2] - origin;
+
+    let a_x = a[kx] - s_x * a[kz];
+    let a_y = a[ky] - s_y * a[kz];
+    let b_x = b[kx] - s_x * b[kz];
+    let b_y = b[ky] - s_y * b[kz];
+    let c_x = c[kx] - s_x * c[kz];
+    let c_y = c[ky] - s_y * c[kz];
+
+    // Scaled barycentric coordinates
+    let mut u = c_x * b_y - c_y * b_x;
+    let mut v = a_x * c_y - a_y * c_x;
+    let mut w = b_x * a_y - b_y * a_x;
+
+    // Edge cases are recomputed in double precision to stay watertight
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        u = (c_x as f64 * b_y as f64 - c_y as f64 * b_x as f64) as f32;
+        v = (a_x as f64 * c_y as f64 - a_y as f64 * c_x as f64) as f32;
+        w = (b_x as f64 * a_y as f64 - b_y as f64 * a_x as f64) as f32;
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let a_z = s_z * a[kz];
+    let b_z = s_z * b[kz];
+    let c_z = s_z * c[kz];
+    let t_scaled = u * a_z + v * b_z + w * c_z;
+
+    // Reject hits behind the origin without dividing by the determinant
+    if (det < 0.0 && t_scaled >= 0.0) || (det > 0.0 && t_scaled <= 0.0) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some(TriangleHit {
+        t: t_scaled * inv_det,
+        barycentric: [u * inv_det, v * inv_det, w * inv_det],
+    })
+}
+
+/// Single triangle, with vertices given in the frame of `pose`.
+///
+/// The face normal follows the counter-clockwise winding of `vertices`.
+pub struct Triangle {
+    pub pose: na::Isometry3<f32>,
+    pub vertices: [na::Point3<f32>; 3],
+}
+
+impl Triangle {
+    fn face_normal(&self) -> na::Vector3<f32> {
+        let e1 = self.vertices[1] - self.vertices[0];
+        let e2 = self.vertices[2] - self.vertices[0];
+        e1.cross(&e2).normalize()
+    }
+}
+
+impl Shape for Triangle {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.vertices
+            .iter()
+            .fold(Aabb::empty(), |bounds, v| bounds.grow(&(self.pose * v)))
+    }
+
+    fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
+        let origin = self.pose.inverse_transform_point(&ray.origin);
+        let direction = self.pose.inverse_transform_vector(&ray.direction);
+        let v = &self.vertices;
+        let hit = intersect_triangle(&origin, &direction, [&v[0], &v[1], &v[2]])?;
+
+        let near_hit = ray.origin + ray.direction * hit.t;
+        Some(RayHit {
+            near: near_hit,
+            far: near_hit,
+            normal: self.pose * self.face_normal(),
+            shape: None,
+        })
+    }
+}
+
+impl fmt::Display for Triangle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[Pose {}, Vertices: ({}, {}, {})]",
+            self.pose * na::Point3::origin(),
+            self.vertices[0],
+            self.vertices[1],
+            self.vertices[2]
+        )
+    }
+}
+
+/// Indexed triangle mesh, with vertices given in the frame of `pose`.
+///
+/// Each entry in `indices` selects three entries of `vertices`. When
+/// `normals` is set it must hold one normal per vertex, and hits report the
+/// barycentric interpolation of the three vertex normals instead of the flat
+/// face normal.
+pub struct TriangleMesh {
     pub pose: na::Isometry3<f32>,
+    pub vertices: Vec<na::Point3<f32>>,
+    pub indices: Vec<[usize; 3]>,
+    pub normals: Option<Vec<na::Vector3<f32>>>,
+}
+
+impl TriangleMesh {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn shading_normal(&self, face: &[usize; 3], barycentric: &[f32; 3]) -> na::Vector3<f32> {
+        match &self.normals {
+            Some(normals) => (normals[face[0]] * barycentric[0]
+                + normals[face[1]] * barycentric[1]
+                + normals[face[2]] * barycentric[2])
+                .normalize(),
+            None => {
+                let e1 = self.vertices[face[1]] - self.vertices[face[0]];
+                let e2 = self.vertices[face[2]] - self.vertices[face[0]];
+                e1.cross(&e2).normalize()
+            }
+        }
+    }
+}
+
+impl Shape for TriangleMesh {
+    fn origin(&self) -> &na::Isometry3<f32> {
+        &self.pose
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.vertices
+            .iter()
+            .fold(Aabb::empty(), |bounds, v| bounds.grow(&(self.pose * v)))
+    }
+
+    fn ray_cast(&self, ray: &Ray) -> Option<RayHit> {
+        let origin = self.pose.inverse_transform_point(&ray.origin);
+        let direction = self.pose.inverse_transform_vector(&ray.direction);
+
+        let (face, hit) = self
+            .indices
+            .iter()
+            .filter_map(|face| {
+                let vertices = [
+                    &self.vertices[face[0]],
+                    &self.vertices[face[1]],
+                    &self.vertices[face[2]],
+                ];
+                intersect_triangle(&origin, &direction, vertices).map(|hit| (face, hit))
+            })
+            .min_by(|lhs, rhs| lhs.1.t.partial_cmp(&rhs.1.t).unwrap())?;
+
+        let near_hit = ray.origin + ray.direction * hit.t;
+        Some(RayHit {
+            near: near_hit,
+            far: near_hit,
+            normal: self.pose * self.shading_normal(face, &hit.barycentric),
+            shape: None,
+        })
+    }
+}
+
+impl fmt::Display for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[Pose {}, Vertices: {}, Triangles: {}]",
+            self.pose * na::Point3::origin(),
+            self.vertices.len(),
+            self.indices.len()
+        )
+    }
 }
 
 #[cfg(test)]
@@ -128,7 +392,7 @@ mod tests {
                 sphere_origin[1],
                 sphere_origin[2],
             ),
-            radius: radius,
+            radius,
         }
     }
 
@@ -159,7 +423,7 @@ mod tests {
             1.0,             // Sphere Radius
         );
         assert!(
-            sphere.ray_cast(&ray).is_som(),
+            sphere.ray_cast(&ray).is_some(),
             "Ray {} should intersect Sphere {}",
             ray,
             sphere
@@ -244,7 +508,7 @@ mod tests {
             1.0,             // Sphere Radius
         );
         assert!(
-            !sphere.ray_cast(&ray).is_some(),
+            sphere.ray_cast(&ray).is_none(),
             "Ray {} should not intersect Sphere {}",
             ray,
             sphere
@@ -260,12 +524,18 @@ mod tests {
             [0.0, 0.0, 2.0], // Sphere Origin
             3.0,             // Sphere Radius
         );
+        let hit = sphere.ray_cast(&ray);
         assert!(
-            sphere.ray_cast(&ray).is_some(),
+            hit.is_some(),
             "Ray {} inside Sphere {} should intersect",
             ray,
             sphere
         );
+        // The hit is where the ray leaves the sphere, not behind its origin
+        assert!(relative_eq!(
+            hit.unwrap().near,
+            na::Point3::new(0.0, 0.0, 5.0)
+        ));
     }
 
     #[test]
@@ -359,4 +629,205 @@ mod tests {
             na::Vector3::<f32>::new(1.0, 0.0, 0.0)
         );
     }
+
+    fn make_triangle(vertices: [[f32; 3]; 3]) -> Triangle {
+        Triangle {
+            pose: na::Isometry3::<f32>::identity(),
+            vertices: [
+                na::Point3::new(vertices[0][0], vertices[0][1], vertices[0][2]),
+                na::Point3::new(vertices[1][0], vertices[1][1], vertices[1][2]),
+                na::Point3::new(vertices[2][0], vertices[2][1], vertices[2][2]),
+            ],
+        }
+    }
+
+    fn unit_triangle() -> Triangle {
+        make_triangle([[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]])
+    }
+
+    // Unit square in the XY plane, split along its diagonal
+    fn make_quad(normals: Option<Vec<na::Vector3<f32>>>) -> TriangleMesh {
+        TriangleMesh {
+            pose: na::Isometry3::<f32>::identity(),
+            vertices: vec![
+                na::Point3::new(0.0, 0.0, 0.0),
+                na::Point3::new(1.0, 0.0, 0.0),
+                na::Point3::new(1.0, 1.0, 0.0),
+                na::Point3::new(0.0, 1.0, 0.0),
+            ],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            normals,
+        }
+    }
+
+    #[test]
+    fn triangle_intersection() {
+        let triangle = unit_triangle();
+        let ray = make_ray([0.25, 0.25, 1.0], [0.0, 0.0, -1.0]);
+        let hit = triangle.ray_cast(&ray);
+        assert!(
+            hit.is_some(),
+            "Ray {} should intersect Triangle {}",
+            ray,
+            triangle
+        );
+        let hit = hit.unwrap();
+        assert!(relative_eq!(hit.near, na::Point3::new(0.25, 0.25, 0.0)));
+        assert!(relative_eq!(hit.normal, na::Vector3::z()));
+    }
+
+    #[test]
+    fn triangle_non_intersection() {
+        let triangle = unit_triangle();
+        let ray = make_ray([0.75, 0.75, 1.0], [0.0, 0.0, -1.0]);
+        assert!(
+            triangle.ray_cast(&ray).is_none(),
+            "Ray {} should not intersect Triangle {}",
+            ray,
+            triangle
+        );
+    }
+
+    #[test]
+    fn triangle_behind_ray() {
+        let triangle = unit_triangle();
+        let ray = make_ray([0.25, 0.25, 1.0], [0.0, 0.0, 1.0]);
+        assert!(
+            triangle.ray_cast(&ray).is_none(),
+            "Ray {} pointing away should not intersect Triangle {}",
+            ray,
+            triangle
+        );
+    }
+
+    #[test]
+    fn triangle_back_face_intersection() {
+        // Normal still follows the winding when hit from behind
+        let triangle = unit_triangle();
+        let ray = make_ray([0.25, 0.25, -1.0], [0.0, 0.0, 1.0]);
+        let hit = triangle.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(hit.near, na::Point3::new(0.25, 0.25, 0.0)));
+        assert!(relative_eq!(hit.normal, na::Vector3::z()));
+    }
+
+    #[test]
+    fn triangle_pose_is_applied() {
+        let mut triangle = unit_triangle();
+        triangle.pose = na::Isometry3::new(
+            na::Vector3::new(0.0, 0.0, -5.0),
+            na::Vector3::x() * std::f32::consts::PI,
+        );
+        let ray = make_ray([0.25, -0.25, 0.0], [0.0, 0.0, -1.0]);
+        let hit = triangle.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(
+            hit.near,
+            na::Point3::new(0.25, -0.25, -5.0),
+            epsilon = 1.0e-5
+        ));
+        assert!(relative_eq!(
+            hit.normal,
+            -na::Vector3::z(),
+            epsilon = 1.0e-5
+        ));
+    }
+
+    #[test]
+    fn mesh_shared_edge_is_watertight() {
+        // Rays straight through the shared diagonal must hit the mesh
+        let mesh = make_quad(None);
+        for idx in 1..64 {
+            let s = idx as f32 / 64.0;
+            let ray = make_ray([s, s, 1.0], [0.0, 0.0, -1.0]);
+            assert!(
+                mesh.ray_cast(&ray).is_some(),
+                "Ray {} through the shared edge should intersect Mesh {}",
+                ray,
+                mesh
+            );
+        }
+    }
+
+    #[test]
+    fn mesh_nearest_hit() {
+        // Two stacked quads, the ray should report the closest one
+        let mut mesh = make_quad(None);
+        mesh.vertices.extend(
+            mesh.vertices
+                .clone()
+                .iter()
+                .map(|v| v + na::Vector3::new(0.0, 0.0, -1.0)),
+        );
+        mesh.indices.extend(vec![[4, 5, 6], [4, 6, 7]]);
+        let ray = make_ray([0.5, 0.25, 1.0], [0.0, 0.0, -1.0]);
+        let hit = mesh.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(hit.near, na::Point3::new(0.5, 0.25, 0.0)));
+
+        let ray = make_ray([0.5, 0.25, -3.0], [0.0, 0.0, 1.0]);
+        let hit = mesh.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(hit.near, na::Point3::new(0.5, 0.25, -1.0)));
+    }
+
+    #[test]
+    fn mesh_smooth_normal_interpolation() {
+        let tilt = |x: f32| na::Vector3::new(x, 0.0, 1.0).normalize();
+        let mesh = make_quad(Some(vec![tilt(-1.0), tilt(1.0), tilt(1.0), tilt(-1.0)]));
+
+        // Halfway across the quad the tilted normals cancel out
+        let ray = make_ray([0.5, 0.25, 1.0], [0.0, 0.0, -1.0]);
+        let hit = mesh.ray_cast(&ray).unwrap();
+        assert!(
+            relative_eq!(hit.normal, na::Vector3::z(), epsilon = 1.0e-6),
+            concat!(
+                "Ray {} should hit Mesh {} with interpolated normal:\n",
+                "  Expected: {}\n",
+                "    Actual: {}"
+            ),
+            ray,
+            mesh,
+            na::Point3::from(na::Vector3::<f32>::z()),
+            na::Point3::from(hit.normal),
+        );
+
+        // At a vertex the normal matches the vertex normal
+        let ray = make_ray([1.0, 0.0, 1.0], [0.0, 0.0, -1.0]);
+        let hit = mesh.ray_cast(&ray).unwrap();
+        assert!(relative_eq!(hit.normal, tilt(1.0), epsilon = 1.0e-6));
+    }
+
+    #[test]
+    fn sphere_bounds() {
+        let sphere = make_sphere([1.0, 2.0, 3.0], 0.5);
+        let bounds = sphere.bounds();
+        assert_eq!(bounds.min, na::Point3::new(0.5, 1.5, 2.5));
+        assert_eq!(bounds.max, na::Point3::new(1.5, 2.5, 3.5));
+    }
+
+    #[test]
+    fn plane_bounds() {
+        // Axis-aligned planes are bounded along their normal
+        let plane = Plane {
+            pose: na::Isometry3::translation(0.0, 0.0, -1.0),
+        };
+        let bounds = plane.bounds();
+        assert!(!bounds.is_finite());
+        assert_eq!((bounds.min.z, bounds.max.z), (-1.0, -1.0));
+        assert_eq!(
+            (bounds.min.x, bounds.max.x),
+            (f32::NEG_INFINITY, f32::INFINITY)
+        );
+
+        let tilted = Plane {
+            pose: na::Isometry3::rotation(na::Vector3::x() * 0.3),
+        };
+        assert_eq!(tilted.bounds(), Aabb::infinite());
+    }
+
+    #[test]
+    fn mesh_bounds() {
+        let mut mesh = make_quad(None);
+        mesh.pose = na::Isometry3::translation(0.0, 0.0, 2.0);
+        let bounds = mesh.bounds();
+        assert_eq!(bounds.min, na::Point3::new(0.0, 0.0, 2.0));
+        assert_eq!(bounds.max, na::Point3::new(1.0, 1.0, 2.0));
+    }
 }
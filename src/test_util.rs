@@ -0,0 +1,42 @@
+//! Fixtures shared by the tests of modules that render or scan a scene.
+
+extern crate nalgebra as na;
+
+use crate::camera::PerspectiveCamera;
+use crate::scene::Scene;
+use crate::shape;
+
+/// A floor plane at z = 0 with unit spheres centred at `balls`.
+pub fn floor_and_balls(balls: &[(&str, [f32; 3])]) -> Scene {
+    let mut scene = Scene::new();
+    scene.add_shape(
+        "floor",
+        Box::new(shape::Plane {
+            pose: na::Isometry3::identity(),
+        }),
+    );
+    for (name, [x, y, z]) in balls {
+        scene.add_shape(
+            name,
+            Box::new(shape::Sphere {
+                pose: na::Isometry3::translation(*x, *y, *z),
+                radius: 1.0,
+            }),
+        );
+    }
+    scene.rebuild_bvh();
+    scene
+}
+
+/// A camera `altitude` above the origin looking straight down, with +Y up in
+/// the image.
+pub fn top_down_camera(altitude: f32, width: u32, height: u32) -> PerspectiveCamera {
+    PerspectiveCamera::look_at(
+        &na::Point3::new(0.0, 0.0, altitude),
+        &na::Point3::origin(),
+        &na::Vector3::y(),
+        std::f32::consts::FRAC_PI_2,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,257 @@
+extern crate image;
+
+use crate::material::Color;
+
+/// Compresses linear radiance into the [0, 1] range of a display.
+pub trait ToneMapper: Send + Sync {
+    fn map(&self, radiance: &Color) -> Color;
+}
+
+/// Clips each channel to [0, 1], losing all detail above one.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clamp;
+
+impl ToneMapper for Clamp {
+    fn map(&self, radiance: &Color) -> Color {
+        radiance.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+/// Relative luminance of linear Rec. 709 RGB.
+pub fn luminance(color: &Color) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// Reinhard's operator, L / (1 + L) on luminance so that hues are kept.
+/// Luminance `white` and above maps to one, infinity gives the plain
+/// operator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reinhard {
+    pub white: f32,
+}
+
+impl Default for Reinhard {
+    fn default() -> Self {
+        Reinhard {
+            white: f32::INFINITY,
+        }
+    }
+}
+
+impl ToneMapper for Reinhard {
+    fn map(&self, radiance: &Color) -> Color {
+        let l = luminance(radiance);
+        if l <= 0.0 {
+            return Color::zeros();
+        }
+        let mapped = l * (1.0 + l / (self.white * self.white)) / (1.0 + l);
+        (radiance * (mapped / l)).map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+/// Narkowicz's fit of the ACES filmic curve, with a toe that deepens shadows
+/// and a soft shoulder.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AcesFilmic;
+
+impl ToneMapper for AcesFilmic {
+    fn map(&self, radiance: &Color) -> Color {
+        radiance.map(|x| {
+            let x = x.max(0.0);
+            (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+        })
+    }
+}
+
+/// John Hable's filmic curve from Uncharted 2, scaled so that `white` maps
+/// to one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hable {
+    pub white: f32,
+}
+
+impl Default for Hable {
+    fn default() -> Self {
+        Hable { white: 11.2 }
+    }
+}
+
+impl Hable {
+    fn curve(x: f32) -> f32 {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    }
+}
+
+impl ToneMapper for Hable {
+    fn map(&self, radiance: &Color) -> Color {
+        // The curve is meant for radiance doubled by an exposure bias
+        let scale = 1.0 / Hable::curve(self.white);
+        radiance.map(|x| (Hable::curve(2.0 * x.max(0.0)) * scale).clamp(0.0, 1.0))
+    }
+}
+
+/// Encodes linear [0, 1] values for output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transfer {
+    /// Values are written as they are.
+    Linear,
+    /// The sRGB transfer function, which image viewers expect of 8-bit
+    /// images.
+    #[default]
+    Srgb,
+}
+
+impl Transfer {
+    pub fn encode(self, value: f32) -> f32 {
+        match self {
+            Transfer::Linear => value,
+            Transfer::Srgb => srgb_encode(value),
+        }
+    }
+}
+
+/// Linear value to sRGB, both in [0, 1].
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// sRGB value to linear, both in [0, 1].
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// How linear radiance is turned into display values.
+pub struct ToneMapping {
+    /// Radiance is scaled by two to the power of this many stops before
+    /// tone mapping.
+    pub exposure: f32,
+    pub operator: Box<dyn ToneMapper>,
+    pub transfer: Transfer,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            operator: Box::new(Clamp),
+            transfer: Transfer::Srgb,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Display value of `radiance`, each channel in [0, 1].
+    pub fn apply(&self, radiance: &Color) -> Color {
+        let exposed = radiance * self.exposure.exp2();
+        self.operator
+            .map(&exposed)
+            .map(|c| self.transfer.encode(c.clamp(0.0, 1.0)))
+    }
+
+    /// Display value of `radiance` rounded to 8 bits per channel.
+    pub fn to_rgb(&self, radiance: &Color) -> image::Rgb<u8> {
+        let color = self.apply(radiance);
+        let channel = |value: f32| (value * 255.0).round() as u8;
+        image::Rgb([channel(color.x), channel(color.y), channel(color.z)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::relative_eq;
+
+    fn operators() -> Vec<Box<dyn ToneMapper>> {
+        vec![
+            Box::new(Clamp),
+            Box::new(Reinhard::default()),
+            Box::new(Reinhard { white: 4.0 }),
+            Box::new(AcesFilmic),
+            Box::new(Hable::default()),
+        ]
+    }
+
+    #[test]
+    fn operators_are_monotonic() {
+        for operator in operators() {
+            assert!(relative_eq!(
+                operator.map(&Color::zeros()),
+                Color::zeros(),
+                epsilon = 1.0e-6
+            ));
+            let mut previous = 0.0;
+            for i in 1..200 {
+                let value = operator.map(&Color::repeat(i as f32 * 0.1)).x;
+                assert!(value >= previous && value <= 1.0);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn highlights_are_compressed() {
+        // Unlike clamping, the curves keep detail above one
+        let reinhard = Reinhard::default();
+        assert!(reinhard.map(&Color::repeat(2.0)).x < reinhard.map(&Color::repeat(4.0)).x);
+        assert!(relative_eq!(reinhard.map(&Color::repeat(1.0)).x, 0.5));
+        assert!(relative_eq!(
+            Reinhard { white: 4.0 }.map(&Color::repeat(4.0)).x,
+            1.0
+        ));
+        let hable = Hable::default();
+        assert!(relative_eq!(hable.map(&Color::repeat(5.6)).x, 1.0));
+        assert!(AcesFilmic.map(&Color::repeat(2.0)).x < AcesFilmic.map(&Color::repeat(4.0)).x);
+
+        // Reinhard scales all channels alike, keeping the hue
+        let mapped = reinhard.map(&Color::new(1.0, 0.5, 0.25));
+        assert!(relative_eq!(mapped.x, 2.0 * mapped.y) && relative_eq!(mapped.y, 2.0 * mapped.z));
+    }
+
+    #[test]
+    fn srgb_transfer() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!(relative_eq!(srgb_encode(1.0), 1.0, epsilon = 1.0e-6));
+        assert!(relative_eq!(srgb_encode(0.5), 0.735_357, epsilon = 1.0e-5));
+        assert!(relative_eq!(srgb_encode(0.002), 0.025_84, epsilon = 1.0e-5));
+        for i in 0..=100 {
+            let value = i as f32 / 100.0;
+            assert!(relative_eq!(
+                srgb_decode(srgb_encode(value)),
+                value,
+                epsilon = 1.0e-5
+            ));
+        }
+    }
+
+    #[test]
+    fn exposure_scales_radiance() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            transfer: Transfer::Linear,
+            ..Default::default()
+        };
+        assert_eq!(
+            tone_mapping.apply(&Color::new(0.25, 0.1, 2.0)),
+            Color::new(0.5, 0.2, 1.0)
+        );
+        let darker = ToneMapping {
+            exposure: -2.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            darker.to_rgb(&Color::repeat(4.0)),
+            image::Rgb([255, 255, 255])
+        );
+        assert_eq!(darker.to_rgb(&Color::repeat(0.0)), image::Rgb([0, 0, 0]));
+    }
+}